@@ -0,0 +1,130 @@
+//Counts the voxels visited by the ray marcher with and without the distance field, using the CPU mirror of chunk_shader.wgsl.
+//Run with `cargo bench --bench empty_space_skipping`.
+
+use std::time::Instant;
+
+use egde::scene::chunk::{chunk_content::voxel_is_solid, distance_field::DistanceField, traversal::{march, ray_box_entry}};
+use glam::{UVec3, Vec3};
+
+const IMAGE_SIZE: u32 = 128;
+
+struct Volume {
+    name: &'static str,
+    dimensions: UVec3,
+    albedo: Vec<u8>,
+}
+
+fn generate(name: &'static str, dimensions: UVec3, voxel: impl Fn(UVec3) -> bool) -> Volume {
+    let mut albedo = Vec::with_capacity((dimensions.x * dimensions.y * dimensions.z * 4) as usize);
+    for z in 0..dimensions.z {
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                albedo.extend(if voxel(UVec3::new(x, y, z)) { [200, 200, 200, 255] } else { [0, 0, 0, 0] });
+            }
+        }
+    }
+
+    Volume { name, dimensions, albedo }
+}
+
+fn volumes() -> Vec<Volume> {
+    vec![
+        generate("sparse boxes 128^3", UVec3::splat(128), |position| {
+            let cell = position % 48;
+            cell.cmplt(UVec3::splat(6)).all()
+        }),
+        generate("terrain 256x64x256", UVec3::new(256, 64, 256), |position| {
+            let height = 20.0 + 10.0 * (position.x as f32 * 0.05).sin() + 10.0 * (position.z as f32 * 0.035).cos();
+            (position.y as f32) < height
+        }),
+        generate("sphere 128^3", UVec3::splat(128), |position| {
+            (position.as_vec3() + 0.5 - 64.0).length() < 60.0
+        }),
+    ]
+}
+
+struct Stats {
+    rays: u32,
+    hits: u32,
+    total_steps: u64,
+    max_steps: u32,
+    seconds: f64,
+}
+
+//Perspective rays from a point above a corner of the volume towards its center
+fn trace(volume: &Volume, distance_field: Option<&DistanceField>) -> Stats {
+    let size = volume.dimensions.as_vec3();
+    let eye = Vec3::new(-0.4, 1.3, -0.6) * size;
+    let forward = (size * 0.5 - eye).normalize();
+    let right = Vec3::Y.cross(forward).normalize();
+    let up = forward.cross(right);
+
+    let mut stats = Stats { rays: 0, hits: 0, total_steps: 0, max_steps: 0, seconds: 0.0 };
+    let start = Instant::now();
+
+    for y in 0..IMAGE_SIZE {
+        for x in 0..IMAGE_SIZE {
+            let u = (x as f32 + 0.5) / IMAGE_SIZE as f32 * 2.0 - 1.0;
+            let v = (y as f32 + 0.5) / IMAGE_SIZE as f32 * 2.0 - 1.0;
+            let direction = (forward + (right * u + up * v) * 0.6).normalize();
+
+            //Rays start on the surface of the chunk like the fragments of its bounding box
+            let Some(entry) = ray_box_entry(eye, direction, Vec3::ZERO, size) else {
+                continue;
+            };
+
+            let result = march(
+                volume.dimensions,
+                |position| {
+                    let index = ((position.x + position.y * volume.dimensions.x + position.z * volume.dimensions.x * volume.dimensions.y) * 4) as usize;
+                    voxel_is_solid(&volume.albedo[index..index + 4], 0)
+                },
+                distance_field,
+                eye + direction * (entry + 1e-3),
+                direction,
+            );
+
+            stats.rays += 1;
+            stats.hits += result.hit.is_some() as u32;
+            stats.total_steps += result.steps as u64;
+            stats.max_steps = stats.max_steps.max(result.steps);
+        }
+    }
+
+    stats.seconds = start.elapsed().as_secs_f64();
+    stats
+}
+
+fn main() {
+    println!("{:<20} {:>10} {:>8} {:>12} {:>10} {:>10}", "volume", "mode", "hits", "mean steps", "max steps", "ms");
+
+    for volume in volumes() {
+        let start = Instant::now();
+        let distance_field = DistanceField::from_albedo(&volume.albedo, volume.dimensions, 0);
+        let build = start.elapsed().as_secs_f64() * 1000.0;
+
+        let dense = trace(&volume, None);
+        let skipping = trace(&volume, Some(&distance_field));
+        assert_eq!(dense.hits, skipping.hits, "skipping must not change what the rays hit");
+
+        for (mode, stats) in [("dense", &dense), ("skipping", &skipping)] {
+            println!(
+                "{:<20} {:>10} {:>8} {:>12.1} {:>10} {:>10.1}",
+                volume.name,
+                mode,
+                stats.hits,
+                stats.total_steps as f64 / stats.rays as f64,
+                stats.max_steps,
+                stats.seconds * 1000.0,
+            );
+        }
+
+        println!(
+            "{:<20} {:>10} step reduction {:.1}x, distance field built in {:.1} ms",
+            volume.name,
+            "",
+            dense.total_steps as f64 / skipping.total_steps as f64,
+            build,
+        );
+    }
+}
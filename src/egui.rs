@@ -1,7 +1,7 @@
 use std::time::SystemTime;
 
 use egui::{ahash::HashMapExt, viewport, DroppedFile, Event, HoveredFile, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2, ViewportId, ViewportIdMap, ViewportInfo};
-use sdl2::{event::WindowEvent, keyboard::{Keycode, Scancode}, libc::SOCKET, mouse::{MouseButton, MouseWheelDirection}, video::FullscreenType};
+use sdl2::{event::WindowEvent, keyboard::{Keycode, Scancode}, mouse::{MouseButton, MouseWheelDirection}, video::FullscreenType};
 
 use crate::Game;
 
@@ -0,0 +1,238 @@
+use glam::UVec2;
+use image::RgbaImage;
+use wgpu::{Buffer, Device, Extent3d, Queue, SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureView};
+
+use crate::render::chunk_renderer::ChunkRenderer;
+use crate::render::lighting_renderer::LightingRenderer;
+use crate::render::bloom_renderer::BloomRenderer;
+use crate::render::g_buffer::GBuffer;
+use crate::render::picker::{PickResult, Picker};
+use crate::render::render_plane::RenderPlane;
+use crate::scene::camera::CameraData;
+use crate::scene::lighting::LightingData;
+use crate::scene::chunk::chunk_content::ChunkContentLoadingError;
+use crate::scene::{Scene, UnloadedScene};
+use crate::GameConfig;
+
+pub const OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+//Renders scenes without any window or surface, the frame is read back from an offscreen texture
+pub struct HeadlessGame {
+    config: GameConfig,
+    device: Device,
+    queue: Queue,
+
+    output: Texture,
+    output_view: TextureView,
+    readback_buffer: Buffer,
+    padded_bytes_per_row: u32,
+
+    render_plane: RenderPlane,
+    chunk_renderer: ChunkRenderer,
+    lighting_renderer: LightingRenderer,
+    bloom_renderer: BloomRenderer,
+    g_buffer: GBuffer,
+    picker: Picker,
+
+    current_scene: Option<Scene>,
+}
+
+impl HeadlessGame {
+    //Returns None when no adapter or device is available (for example on a machine without any GPU driver)
+    pub async fn new(config: GameConfig, force_fallback_adapter: bool) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter,
+            },
+        ).await?;
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                required_limits:  wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await.ok()?;
+
+        let output = device.create_texture(&TextureDescriptor {
+            label: Some("Headless output"),
+            size: Extent3d {
+                width: config.window_width,
+                height: config.window_height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: OUTPUT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[]
+        });
+
+        let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+
+        //Rows copied into a buffer have to be aligned on COPY_BYTES_PER_ROW_ALIGNMENT
+        let unpadded_bytes_per_row = config.window_width * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless readback buffer"),
+            size: (padded_bytes_per_row * config.window_height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        //The render plane only needs to know the format it renders to
+        let output_config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: OUTPUT_FORMAT,
+            width: config.window_width,
+            height: config.window_height,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        let g_buffer = GBuffer::new(&device, config.window_width * config.render_scale, config.window_height * config.render_scale);
+
+        let render_plane = RenderPlane::new(&device, &output_config);
+
+        let chunk_renderer = ChunkRenderer::new(&device);
+        let lighting_renderer = LightingRenderer::new(&device);
+        let bloom_renderer = BloomRenderer::new(&device);
+
+        let picker = Picker::new(&device);
+
+        Some(HeadlessGame {
+            config,
+            device,
+            queue,
+            output,
+            output_view,
+            readback_buffer,
+            padded_bytes_per_row,
+            render_plane,
+            chunk_renderer,
+            lighting_renderer,
+            bloom_renderer,
+            g_buffer,
+            picker,
+            current_scene: None,
+        })
+    }
+
+    pub fn load_scene(&mut self, to_load: UnloadedScene) -> Result<(), ChunkContentLoadingError> {
+        self.current_scene = Some(to_load.load(&self.device, &self.queue, self.config.window_width as f32 / self.config.window_height as f32)?);
+        Ok(())
+    }
+
+    pub fn set_camera_data(&mut self, camera_data: CameraData) {
+        if let Some(ref mut scene) = self.current_scene {
+            scene.set_camera_data(&self.queue, camera_data);
+        }
+    }
+
+    pub fn set_lighting_data(&mut self, lighting_data: LightingData) {
+        if let Some(ref mut scene) = self.current_scene {
+            scene.set_lighting_data(&self.queue, lighting_data);
+        }
+    }
+
+    pub fn scene_mut(&mut self) -> Option<&mut Scene> {
+        self.current_scene.as_mut()
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    //Asks for the chunk and voxel seen at a pixel of the window, copied from the G-buffer at the end of the next frame.
+    //Only the last request made before a frame is kept.
+    pub fn request_pick(&mut self, x: u32, y: u32) {
+        let scale = self.config.render_scale;
+        self.picker.request(UVec2::new(x, y) * scale + scale / 2);
+    }
+
+    //Result of the last request once it has been read back, without waiting for the GPU
+    pub fn poll_pick(&mut self) -> Option<PickResult> {
+        let (pixel, texel) = self.picker.poll(&self.device)?;
+        let pixel = pixel / self.config.render_scale;
+        Some(PickResult {
+            x: pixel.x,
+            y: pixel.y,
+            pick: self.current_scene.as_ref().and_then(|scene| scene.resolve_pick(texel)),
+        })
+    }
+
+    pub fn render(&mut self) -> RgbaImage {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Render Encoder"),
+        });
+
+        if let Some(ref mut scene) = self.current_scene {
+            scene.flush_edits(&self.device, &self.queue);
+            scene.render(&self.chunk_renderer, &self.lighting_renderer, &self.bloom_renderer, &self.device, &self.queue, &self.g_buffer, &mut encoder);
+            if self.picker.will_copy() {
+                scene.render_pick(&self.chunk_renderer, &self.device, &self.g_buffer, &mut encoder);
+            }
+        }
+        self.picker.encode_copy(&mut encoder, &self.g_buffer);
+
+        self.render_plane.render(&mut encoder, &self.device, &self.output_view, &self.g_buffer);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.output,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.config.window_height),
+                },
+            },
+            Extent3d {
+                width: self.config.window_width,
+                height: self.config.window_height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.picker.after_submit();
+
+        let buffer_slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().expect("Failed to map the headless readback buffer");
+
+        let mut pixels = Vec::<u8>::with_capacity((self.config.window_width * self.config.window_height * 4) as usize);
+        {
+            let padded = buffer_slice.get_mapped_range();
+            for row in padded.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..(self.config.window_width * 4) as usize]);
+            }
+        }
+        self.readback_buffer.unmap();
+
+        RgbaImage::from_raw(self.config.window_width, self.config.window_height, pixels).unwrap()
+    }
+}
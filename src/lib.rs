@@ -4,13 +4,14 @@ pub mod scene;
 pub mod render;
 pub mod memory;
 pub mod egui;
+pub mod headless;
 
 use core::default::Default;
 use std::path::Path;
 use std::time::Instant;
 use ::egui::{Context, FullOutput};
 use egui_wgpu_backend::ScreenDescriptor;
-use glam::{UVec3, Vec3};
+use glam::{UVec2, UVec3, Vec3};
 use scene::camera::{Camera, CameraData};
 use scene::chunk::chunk_content::ChunkContentLoadingError;
 use scene::chunk::{self, Chunk};
@@ -24,6 +25,9 @@ use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
 
 use crate::render::g_buffer::GBuffer;
 use crate::render::chunk_renderer::ChunkRenderer;
+use crate::render::lighting_renderer::LightingRenderer;
+use crate::render::bloom_renderer::BloomRenderer;
+use crate::render::picker::{PickResult, Picker};
 use crate::render::render_plane::RenderPlane;
 
 #[derive(Debug, Clone)]
@@ -45,7 +49,10 @@ pub struct Game<'a> {
 
     render_plane: RenderPlane,
     chunk_renderer: ChunkRenderer,
+    lighting_renderer: LightingRenderer,
+    bloom_renderer: BloomRenderer,
     g_buffer: GBuffer,
+    picker: Picker,
 
     game_start: Instant,
     last_frame: Instant,
@@ -128,6 +135,10 @@ impl Game<'_> {
         let render_plane = RenderPlane::new(&device, &surface_config);
 
         let chunk_renderer = ChunkRenderer::new(&device);
+        let lighting_renderer = LightingRenderer::new(&device);
+        let bloom_renderer = BloomRenderer::new(&device);
+
+        let picker = Picker::new(&device);
 
         let egui_context = Context::default();
 
@@ -144,6 +155,9 @@ impl Game<'_> {
             g_buffer,
             render_plane,
             chunk_renderer,
+            lighting_renderer,
+            bloom_renderer,
+            picker,
             last_frame: Instant::now(),
             game_start: Instant::now(),
             current_scene: None,
@@ -154,7 +168,7 @@ impl Game<'_> {
     }
 
     pub fn load_scene(& mut self, to_load: UnloadedScene) -> Result<(), ChunkContentLoadingError> {
-        self.current_scene = Some(to_load.load(&self.device, &self.queue, self.config.window_width as f32 / self.config.window_height as f32)?);
+        self.current_scene = Some(to_load.load(&self.device, &self.queue, self.aspect_ratio())?);
         Ok(())
     }
 
@@ -229,9 +243,14 @@ impl Game<'_> {
             label: Some("Render Encoder"),
         });
 
-        if let Some(ref scene) = self.current_scene {
-            scene.render(&self.chunk_renderer, &self.device, &self.g_buffer, &mut encoder);
+        if let Some(ref mut scene) = self.current_scene {
+            scene.flush_edits(&self.device, &self.queue);
+            scene.render(&self.chunk_renderer, &self.lighting_renderer, &self.bloom_renderer, &self.device, &self.queue, &self.g_buffer, &mut encoder);
+            if self.picker.will_copy() {
+                scene.render_pick(&self.chunk_renderer, &self.device, &self.g_buffer, &mut encoder);
+            }
         }
+        self.picker.encode_copy(&mut encoder, &self.g_buffer);
 
         self.render_plane.render(&mut encoder, &self.device, &view, &self.g_buffer);
        
@@ -261,17 +280,46 @@ impl Game<'_> {
                 .unwrap();
         }
         self.queue.submit(std::iter::once(encoder.finish()));
+        self.picker.after_submit();
         output.present();
     
         Ok(())
     }
 
-    //TODO: Prendre en charge les différentes tailles de fenêrtre correctement (en redimentionant aussi le GBuffer)
+    //Asks for the chunk and voxel seen at a pixel of the window, copied from the G-buffer at the end of the next frame.
+    //Only the last request made before a frame is kept.
+    pub fn request_pick(&mut self, x: u32, y: u32) {
+        let scale = self.config.render_scale;
+        self.picker.request(UVec2::new(x, y) * scale + scale / 2);
+    }
+
+    //Result of the last request once it has been read back, without waiting for the GPU
+    pub fn poll_pick(&mut self) -> Option<PickResult> {
+        let (pixel, texel) = self.picker.poll(&self.device)?;
+        let pixel = pixel / self.config.render_scale;
+        Some(PickResult {
+            x: pixel.x,
+            y: pixel.y,
+            pick: self.current_scene.as_ref().and_then(|scene| scene.resolve_pick(texel)),
+        })
+    }
+
     pub fn resize(&mut self, new_width: i32, new_height: i32) {
         if new_width > 0 && new_height > 0 {
             self.surface_config.width = new_width as u32;
             self.surface_config.height = new_height as u32;
             self.surface.configure(&self.device, &self.surface_config);
+
+            self.g_buffer = GBuffer::new(&self.device, self.surface_config.width * self.config.render_scale, self.surface_config.height * self.config.render_scale);
+
+            let aspect_ratio = self.aspect_ratio();
+            if let Some(ref mut scene) = self.current_scene {
+                scene.set_aspect_ratio(&self.queue, aspect_ratio);
+            }
         }
     }
+
+    fn aspect_ratio(&self) -> f32 {
+        self.surface_config.width as f32 / self.surface_config.height as f32
+    }
 }
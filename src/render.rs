@@ -1,3 +1,6 @@
 pub mod render_plane;
 pub mod g_buffer;
 pub mod chunk_renderer;
+pub mod lighting_renderer;
+pub mod bloom_renderer;
+pub mod picker;
\ No newline at end of file
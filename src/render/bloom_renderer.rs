@@ -0,0 +1,235 @@
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BufferUsages, CommandEncoder, Device, PipelineLayoutDescriptor, RenderPipelineDescriptor, Sampler, TextureView};
+
+use crate::scene::lighting::{Bloom, Lighting};
+
+use super::g_buffer::{self, GBuffer};
+
+//Adds the bloom of the scene to the lit texture. The bright part of the lit texture is downsampled level by level into the
+//bloom textures of the G-buffer, then each level is upsampled and added to the larger one, and the first is added back
+//to the lit texture. Nothing is drawn when the scene has no bloom.
+pub struct BloomRenderer {
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    source_bind_group_layout: wgpu::BindGroupLayout,
+    bloom_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl BloomRenderer {
+    pub fn new(device: &Device) -> Self {
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("bloom_renderer_source_bind_group_layout"),
+        });
+
+        let bloom_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("bloom_renderer_bloom_bind_group_layout"),
+        });
+
+        //The taps of the filters fall between texels and rely on bilinear filtering, clamped at the edges
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor{
+            label: Some("Bloom renderer pipeline layout"),
+            bind_group_layouts: &[&source_layout, &bloom_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/bloom_shader.wgsl"));
+
+        let add = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let keep = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        Self {
+            prefilter_pipeline: Self::generate_pipeline(device, &pipeline_layout, &shader, "fs_prefilter", g_buffer::BLOOM_FORMAT, wgpu::BlendState::REPLACE),
+            downsample_pipeline: Self::generate_pipeline(device, &pipeline_layout, &shader, "fs_downsample", g_buffer::BLOOM_FORMAT, wgpu::BlendState::REPLACE),
+            upsample_pipeline: Self::generate_pipeline(device, &pipeline_layout, &shader, "fs_upsample", g_buffer::BLOOM_FORMAT, wgpu::BlendState { color: add, alpha: keep }),
+            composite_pipeline: Self::generate_pipeline(device, &pipeline_layout, &shader, "fs_composite", g_buffer::LIT_FORMAT, wgpu::BlendState { color: add, alpha: keep }),
+            source_bind_group_layout: source_layout,
+            bloom_bind_group_layout: bloom_layout,
+            sampler,
+        }
+    }
+
+    //Every pass draws a fullscreen triangle sampling one texture into another
+    fn generate_pipeline(device: &Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, entry_point: &str, format: wgpu::TextureFormat, blend: wgpu::BlendState) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor{
+            label: Some("Bloom renderer pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState{
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    //To be called after the lighting pass
+    pub fn render(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, lighting: &Lighting) {
+        let Some(bloom) = lighting.data.bloom else {
+            return;
+        };
+
+        let levels = &g_buffer.bloom_texture_views;
+        let uniform = BloomUniform::from_bloom(&bloom, levels.len());
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Bloom buffer"),
+            contents: unsafe { crate::memory::any_as_u8_slice(&uniform) },
+            usage: BufferUsages::UNIFORM,
+        });
+        let bloom_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bloom_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(buffer.as_entire_buffer_binding()),
+                },
+            ],
+            label: Some("bloom_renderer_bloom_bind_group"),
+        });
+
+        self.render_pass(encoder, device, &self.prefilter_pipeline, &g_buffer.lit_texture_view, &levels[0], &bloom_bind_group, true);
+        for level in 1..levels.len() {
+            self.render_pass(encoder, device, &self.downsample_pipeline, &levels[level - 1], &levels[level], &bloom_bind_group, true);
+        }
+        for level in (1..levels.len()).rev() {
+            self.render_pass(encoder, device, &self.upsample_pipeline, &levels[level], &levels[level - 1], &bloom_bind_group, false);
+        }
+        self.render_pass(encoder, device, &self.composite_pipeline, &levels[0], &g_buffer.lit_texture_view, &bloom_bind_group, false);
+    }
+
+    //Passes replacing their target clear it, the ones adding to it load it
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(&self, encoder: &mut CommandEncoder, device: &Device, pipeline: &wgpu::RenderPipeline, source: &TextureView, target: &TextureView, bloom_bind_group: &BindGroup, clear: bool) {
+        let source_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.source_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("bloom_renderer_source_bind_group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Bloom Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: match clear {
+                        true => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        false => wgpu::LoadOp::Load,
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &source_bind_group, &[]);
+        render_pass.set_bind_group(1, bloom_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone)]
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    //Divided by the number of levels, which the upsampling sums into the first one
+    intensity: f32,
+}
+
+impl BloomUniform {
+    fn from_bloom(bloom: &Bloom, levels: usize) -> Self {
+        Self {
+            threshold: bloom.threshold,
+            knee: bloom.knee,
+            intensity: bloom.intensity / levels as f32,
+        }
+    }
+}
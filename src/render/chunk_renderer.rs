@@ -1,8 +1,8 @@
 use std::mem;
 
-use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BufferUsages, CommandEncoder, Device, PipelineLayoutDescriptor, RenderPipelineDescriptor};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BufferUsages, CommandEncoder, Device, PipelineLayoutDescriptor, RenderPipelineDescriptor, TextureView};
 
-use crate::scene::{camera::Camera, chunk::Chunk};
+use crate::scene::{camera::Camera, chunk::{Chunk, RenderMode}, lighting::Lighting};
 
 use super::g_buffer::{self, GBuffer};
 
@@ -42,12 +42,27 @@ const CHUNK_INDICES: &[u16] = &[
     4, 5, 0, 0, 5, 1
 ];
 
+//Translucent layers peeled at most, the voxels left in front of the last one are composited chunk by chunk
+pub const MAX_TRANSLUCENT_LAYERS: usize = 8;
+
 pub struct ChunkRenderer {
     render_pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
+    layer_pipeline: wgpu::RenderPipeline,
+    pick_pipeline: wgpu::RenderPipeline,
+    furthest_pipeline: wgpu::RenderPipeline,
+    bound_pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     chunk_bind_group_layout: wgpu::BindGroupLayout,
     camera_bind_group_layout: wgpu::BindGroupLayout,
+    peel_bind_group_layout: wgpu::BindGroupLayout,
+    opaque_depth_bind_group_layout: wgpu::BindGroupLayout,
+    shadow_bind_group_layout: wgpu::BindGroupLayout,
+    //Bound in place of the peel textures the chunks drawn do not read
+    depth_placeholder: TextureView,
+    object_placeholder: TextureView,
 }
 
 impl ChunkRenderer {
@@ -56,14 +71,103 @@ impl ChunkRenderer {
         let chunk_layout = Chunk::generate_bind_group_layout(device);
         let camera_layout = Camera::generate_bind_group_layout(device);
 
+        let opaque_depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("chunk_renderer_opaque_depth_bind_group_layout"),
+        });
+
+        let texture_entry = |binding: u32, sample_type: wgpu::TextureSampleType| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let depth = wgpu::TextureSampleType::Float { filterable: false };
+        let object = wgpu::TextureSampleType::Uint;
+
+        let peel_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0, depth), //Peel depth
+                texture_entry(1, object), //Peel object
+                texture_entry(2, object), //Layer object
+                texture_entry(3, depth), //Bound depth
+                texture_entry(4, object), //Bound object
+            ],
+            label: Some("chunk_renderer_peel_bind_group_layout"),
+        });
+
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor{
             label: Some("Chunk Renderer pipeline layout"),
-            bind_group_layouts: &[&chunk_layout, &camera_layout],
+            bind_group_layouts: &[&chunk_layout, &camera_layout, &peel_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shadow_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0, //G-buffer normal
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1, //Lighting uniform
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("chunk_renderer_shadow_bind_group_layout"),
+        });
+
+        let shadow_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor{
+            label: Some("Chunk Renderer shadow pipeline layout"),
+            bind_group_layouts: &[&chunk_layout, &camera_layout, &opaque_depth_layout, &shadow_layout],
             push_constant_ranges: &[],
         });
 
+        let placeholder = |label: &str, format: wgpu::TextureFormat| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+
         Self {
-            render_pipeline:  Self::generate_render_plane_pipeline(device, render_pipeline_layout),
+            render_pipeline: Self::generate_render_plane_pipeline(device, &render_pipeline_layout, RenderMode::FirstHit, "fs_main"),
+            translucent_pipeline: Self::generate_render_plane_pipeline(device, &render_pipeline_layout, RenderMode::Accumulate, "fs_main"),
+            layer_pipeline: Self::generate_render_plane_pipeline(device, &render_pipeline_layout, RenderMode::Accumulate, "fs_layer"),
+            pick_pipeline: Self::generate_pick_pipeline(device, &render_pipeline_layout),
+            furthest_pipeline: Self::generate_peel_pipeline(device, &render_pipeline_layout, "fs_furthest"),
+            bound_pipeline: Self::generate_peel_pipeline(device, &render_pipeline_layout, "fs_bound"),
+            shadow_pipeline: Self::generate_shadow_pipeline(device, &shadow_pipeline_layout),
             vertex_buffer: device.create_buffer_init(
                 &BufferInitDescriptor {
                     label: Some("Chunk renderer vertex buffer"),
@@ -80,16 +184,150 @@ impl ChunkRenderer {
             ),
             chunk_bind_group_layout: chunk_layout,
             camera_bind_group_layout: camera_layout,
+            peel_bind_group_layout: peel_layout,
+            opaque_depth_bind_group_layout: opaque_depth_layout,
+            shadow_bind_group_layout: shadow_layout,
+            depth_placeholder: placeholder("Chunk renderer depth placeholder", g_buffer::RAY_DEPTH_FORMAT),
+            object_placeholder: placeholder("Chunk renderer object placeholder", g_buffer::PEEL_OBJECT_FORMAT),
         }
     }
 
 
-    fn generate_render_plane_pipeline(device: &Device, layout: wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+    //Translucent chunks are blended over what is behind them and leave the depth of the opaque chunks untouched
+    fn generate_render_plane_pipeline(device: &Device, layout: &wgpu::PipelineLayout, render_mode: RenderMode, entry_point: &str) -> wgpu::RenderPipeline {
+        let (blend, depth_write_enabled) = match render_mode {
+            RenderMode::FirstHit => (wgpu::BlendState::REPLACE, true),
+            RenderMode::Accumulate | RenderMode::Volume => (wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING, false),
+        };
+
         let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/chunk_shader.wgsl"));
     
         device.create_render_pipeline(&RenderPipelineDescriptor{
             label: Some("Chunk renderer pipeline"),
-            layout: Some(&layout),
+            layout: Some(layout),
+            vertex: wgpu::VertexState{
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    Vertex::desc(),
+                ],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::EMISSION_FORMAT,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    //Translucent chunks write the peel depth instead of the ray depth, which keeps the depth of the opaque chunks
+                    //like the depth attachment
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::RAY_DEPTH_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    //Translucent chunks replace the normal of what is behind them, the lighting pass lights their composited colour
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::NORMAL_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    //The pick is written by the pick pipeline alone, the attachments of a pass are limited to 32 bytes per sample
+                ],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: g_buffer::DEPTH_FORMAT,
+                depth_write_enabled,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    //Opaque and translucent chunks alike write the pick of their first voxel, the depth test keeps the closest one
+    fn generate_pick_pipeline(device: &Device, layout: &wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/chunk_shader.wgsl"));
+
+        device.create_render_pipeline(&RenderPipelineDescriptor{
+            label: Some("Chunk renderer pick pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState{
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    Vertex::desc(),
+                ],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_pick",
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::PICK_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: g_buffer::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    //Writes the key of the furthest translucent voxel of each chunk, the depth test keeps the furthest one
+    fn generate_peel_pipeline(device: &Device, layout: &wgpu::PipelineLayout, entry_point: &str) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/chunk_shader.wgsl"));
+
+        device.create_render_pipeline(&RenderPipelineDescriptor{
+            label: Some("Chunk renderer peel pipeline"),
+            layout: Some(layout),
             vertex: wgpu::VertexState{
                 module: &shader,
                 entry_point: "vs_main",
@@ -100,10 +338,71 @@ impl ChunkRenderer {
             },
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
-                entry_point: "fs_main",
+                entry_point,
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::RAY_DEPTH_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: g_buffer::PEEL_OBJECT_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            //Equal keys are written by increasing object id, the last one wins
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: g_buffer::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::GreaterEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    //Every chunk multiplies the shadow target by the light it lets through
+    fn generate_shadow_pipeline(device: &Device, layout: &wgpu::PipelineLayout) -> wgpu::RenderPipeline {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/chunk_shader.wgsl"));
+        let multiply = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Zero,
+            dst_factor: wgpu::BlendFactor::Src,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        device.create_render_pipeline(&RenderPipelineDescriptor{
+            label: Some("Chunk renderer shadow pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState{
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_shadow",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: g_buffer::FORMAT,
-                    blend: Some(wgpu::BlendState::REPLACE),
+                    format: g_buffer::SHADOW_FORMAT,
+                    blend: Some(wgpu::BlendState { color: multiply, alpha: multiply }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(),
@@ -112,7 +411,7 @@ impl ChunkRenderer {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode: None,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 unclipped_depth: false,
                 conservative: false,
@@ -127,9 +426,244 @@ impl ChunkRenderer {
         })
     }
 
-    pub fn render(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, chunk: &Chunk, camera: &Camera) {
-        let chunk_bind_group =  chunk.generate_bind_group(device, &self.chunk_bind_group_layout);
+    //Opaque chunks first, then the translucent voxels in layers from the furthest to the closest. In each pixel, a layer is
+    //drawn by the chunk with the furthest voxel left and composites its voxels behind the furthest voxel left of the other
+    //chunks, so that overlapping chunks are ordered per voxel. The voxels left after MAX_TRANSLUCENT_LAYERS layers are
+    //composited chunk by chunk from the furthest chunk center to the closest.
+    pub fn render<'a>(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, chunks: impl Iterator<Item = &'a Chunk>, camera: &Camera) {
+        let (mut translucent, opaque): (Vec<&Chunk>, Vec<&Chunk>) = chunks.partition(|chunk| chunk.render_mode().is_translucent());
+        let camera_bind_group = camera.generate_bind_group(device, &self.camera_bind_group_layout);
+
+        let placeholder_bind_group = self.peel_bind_group(device, &self.depth_placeholder, &self.object_placeholder, &self.object_placeholder, &self.depth_placeholder, &self.object_placeholder);
+        let opaque_bind_groups = self.chunk_bind_groups(device, &opaque);
+        self.render_pass(encoder, g_buffer, &opaque_bind_groups, &self.render_pipeline, &camera_bind_group, &placeholder_bind_group, &g_buffer.ray_depth_texture_view, true);
+
+        if translucent.is_empty() {
+            return;
+        }
+
+        //A single chunk is composited whole, a layer per chunk is enough where they do not overlap
+        let layers = (2 * (translucent.len() - 1)).min(MAX_TRANSLUCENT_LAYERS);
+
+        //The first layer peels from the depth of the opaque chunks
+        Self::clear_pass(encoder, &g_buffer.peel_object_texture_views[1]);
+        let mut peel_depth = &g_buffer.ray_depth_texture_view;
+
+        if layers > 0 {
+            //Ties between voxels at the same depth go to the largest object id
+            translucent.sort_by_key(|chunk| chunk.object_id());
+            let translucent_bind_groups = self.chunk_bind_groups(device, &translucent);
+
+            for layer in 0..layers {
+                let (current, previous) = (layer % 2, (layer + 1) % 2);
+                let peel_object = &g_buffer.peel_object_texture_views[previous];
+                let layer_object = &g_buffer.peel_object_texture_views[current];
+
+                let furthest_bind_group = self.peel_bind_group(device, peel_depth, peel_object, &self.object_placeholder, &self.depth_placeholder, &self.object_placeholder);
+                self.peel_pass(encoder, g_buffer, &translucent_bind_groups, &self.furthest_pipeline, &camera_bind_group, &furthest_bind_group, layer_object);
+
+                let bound_bind_group = self.peel_bind_group(device, peel_depth, peel_object, layer_object, &self.depth_placeholder, &self.object_placeholder);
+                self.peel_pass(encoder, g_buffer, &translucent_bind_groups, &self.bound_pipeline, &camera_bind_group, &bound_bind_group, &g_buffer.bound_object_texture_view);
+
+                let layer_bind_group = self.peel_bind_group(device, peel_depth, peel_object, layer_object, &g_buffer.bound_depth_texture_view, &g_buffer.bound_object_texture_view);
+                self.render_pass(encoder, g_buffer, &translucent_bind_groups, &self.layer_pipeline, &camera_bind_group, &layer_bind_group, &g_buffer.peel_depth_texture_views[current], false);
+                peel_depth = &g_buffer.peel_depth_texture_views[current];
+            }
+        }
+
+        let position = camera.data.position;
+        translucent.sort_by(|a, b| position.distance_squared(b.center()).total_cmp(&position.distance_squared(a.center())));
+
+        let translucent_bind_groups = self.chunk_bind_groups(device, &translucent);
+        let remaining_bind_group = self.peel_bind_group(device, peel_depth, &g_buffer.peel_object_texture_views[(layers + 1) % 2], &self.object_placeholder, &self.depth_placeholder, &self.object_placeholder);
+        self.render_pass(encoder, g_buffer, &translucent_bind_groups, &self.translucent_pipeline, &camera_bind_group, &remaining_bind_group, &g_buffer.peel_depth_texture_views[layers % 2], false);
+    }
+
+    //Fills the pick texture of the G-buffer once the chunks are drawn, only needed in the frames a pick is read back. Each
+    //pixel names the closest voxel drawn there, translucent voxels in front of the opaque chunks included.
+    pub fn render_pick<'a>(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, chunks: impl Iterator<Item = &'a Chunk>, camera: &Camera) {
+        //Ties between voxels at the same depth go to the smallest object id, the closest in the peel order
+        let mut chunks: Vec<&Chunk> = chunks.collect();
+        chunks.sort_by_key(|chunk| (chunk.render_mode().is_translucent(), chunk.object_id()));
+        let chunk_bind_groups = self.chunk_bind_groups(device, &chunks);
+        let camera_bind_group = camera.generate_bind_group(device, &self.camera_bind_group_layout);
+
+        //Translucent chunks trace their voxels up to the opaque chunks, like before their first layer
+        Self::clear_pass(encoder, &g_buffer.peel_object_texture_views[1]);
+        let peel_bind_group = self.peel_bind_group(device, &g_buffer.ray_depth_texture_view, &g_buffer.peel_object_texture_views[1], &self.object_placeholder, &self.depth_placeholder, &self.object_placeholder);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Pick Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.pick_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    //Zero is NO_OBJECT
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &g_buffer.layer_depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        self.draw_chunks(&mut render_pass, &chunk_bind_groups, &self.pick_pipeline, &camera_bind_group, &peel_bind_group);
+    }
+
+    //Fills the shadow texture of the G-buffer once the chunks are drawn. Volumes cast no shadows, and translucent chunks are
+    //shadowed at the depth of the opaque chunks behind them.
+    pub fn render_shadows<'a>(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, chunks: impl Iterator<Item = &'a Chunk>, camera: &Camera, lighting: &Lighting) {
+        let chunk_bind_groups: Vec<wgpu::BindGroup> = match lighting.data.has_shadows() {
+            true => chunks
+                .filter(|chunk| chunk.render_mode() != RenderMode::Volume)
+                .map(|chunk| chunk.generate_bind_group(device, &self.chunk_bind_group_layout))
+                .collect(),
+            false => Vec::new(),
+        };
         let camera_bind_group = camera.generate_bind_group(device, &self.camera_bind_group_layout);
+        let depth_bind_group = self.opaque_depth_bind_group(device, &g_buffer.ray_depth_texture_view);
+        let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.shadow_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.normal_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(lighting.buffer.as_entire_buffer_binding()),
+                },
+            ],
+            label: Some("chunk_renderer_shadow_bind_group"),
+        });
+
+        //Cleared to full visibility, which is also what the lighting pass reads without shadowed lights
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.shadow_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(1, &camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &depth_bind_group, &[]);
+        render_pass.set_bind_group(3, &shadow_bind_group, &[]);
+
+        for chunk_bind_group in chunk_bind_groups.iter() {
+            render_pass.set_bind_group(0, chunk_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn opaque_depth_bind_group(&self, device: &Device, view: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.opaque_depth_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+            label: Some("chunk_renderer_opaque_depth_bind_group"),
+        })
+    }
+
+    fn peel_bind_group(&self, device: &Device, peel_depth: &TextureView, peel_object: &TextureView, layer_object: &TextureView, bound_depth: &TextureView, bound_object: &TextureView) -> BindGroup {
+        let views = [peel_depth, peel_object, layer_object, bound_depth, bound_object];
+        let entries: Vec<wgpu::BindGroupEntry> = views.iter().enumerate()
+            .map(|(binding, view)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.peel_bind_group_layout,
+            entries: &entries,
+            label: Some("chunk_renderer_peel_bind_group"),
+        })
+    }
+
+    //Object id of each chunk with its bind group
+    fn chunk_bind_groups(&self, device: &Device, chunks: &[&Chunk]) -> Vec<(u32, BindGroup)> {
+        chunks.iter()
+            .map(|chunk| (chunk.object_id(), chunk.generate_bind_group(device, &self.chunk_bind_group_layout)))
+            .collect()
+    }
+
+    //Clears a target without drawing anything
+    fn clear_pass(encoder: &mut CommandEncoder, view: &TextureView) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+    }
+
+    //Writes the furthest voxel of the chunks to the bound depth and object_view, pixels without any are left at zero
+    #[allow(clippy::too_many_arguments)]
+    fn peel_pass(&self, encoder: &mut CommandEncoder, g_buffer: &GBuffer, chunk_bind_groups: &[(u32, BindGroup)], pipeline: &wgpu::RenderPipeline, camera_bind_group: &BindGroup, peel_bind_group: &BindGroup, object_view: &TextureView) {
+        let target = |view| Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Peel Pass"),
+            color_attachments: &[target(&g_buffer.bound_depth_texture_view), target(object_view)],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &g_buffer.layer_depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        self.draw_chunks(&mut render_pass, chunk_bind_groups, pipeline, camera_bind_group, peel_bind_group);
+    }
+
+    //The first pass clears the G-buffer, the following ones draw over it. Translucent passes write a fresh peel depth to
+    //ray_depth_view, zero where they draw nothing as nothing is left to peel there.
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(&self, encoder: &mut CommandEncoder, g_buffer: &GBuffer, chunk_bind_groups: &[(u32, BindGroup)], pipeline: &wgpu::RenderPipeline, camera_bind_group: &BindGroup, peel_bind_group: &BindGroup, ray_depth_view: &TextureView, clear: bool) {
+        let load = |color: wgpu::Color| match clear {
+            true => wgpu::LoadOp::Clear(color),
+            false => wgpu::LoadOp::Load,
+        };
 
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
@@ -137,7 +671,7 @@ impl ChunkRenderer {
                 view: &g_buffer.albedo_texture_view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                    load: load(wgpu::Color {
                         r: 0.1,
                         g: 0.2,
                         b: 0.3,
@@ -145,20 +679,66 @@ impl ChunkRenderer {
                     }),
                     store: wgpu::StoreOp::Store,
                 },
+            }),
+            Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.emission_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: load(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            }),
+            Some(wgpu::RenderPassColorAttachment {
+                view: ray_depth_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(match clear {
+                        true => wgpu::Color::WHITE,
+                        false => wgpu::Color::TRANSPARENT,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            }),
+            Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.normal_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    //Zero w leaves the background unlit
+                    load: load(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &g_buffer.depth_texture_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: match clear {
+                        true => wgpu::LoadOp::Clear(1.0),
+                        false => wgpu::LoadOp::Load,
+                    },
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
             occlusion_query_set: None,
             timestamp_writes: None,
         });
 
-        render_pass.set_pipeline(&self.render_pipeline);
+        self.draw_chunks(&mut render_pass, chunk_bind_groups, pipeline, camera_bind_group, peel_bind_group);
+    }
+
+    fn draw_chunks<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, chunk_bind_groups: &'a [(u32, BindGroup)], pipeline: &'a wgpu::RenderPipeline, camera_bind_group: &'a BindGroup, peel_bind_group: &'a BindGroup) {
+        render_pass.set_pipeline(pipeline);
 
-        render_pass.set_bind_group(0, &chunk_bind_group, &[]);
-        render_pass.set_bind_group(1, &camera_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, peel_bind_group, &[]);
 
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..(CHUNK_INDICES.len() as u32), 0, 0..1);        
+
+        //The object id of each chunk reaches the shader as its instance index
+        for (object_id, chunk_bind_group) in chunk_bind_groups.iter() {
+            render_pass.set_bind_group(0, chunk_bind_group, &[]);
+            render_pass.draw_indexed(0..(CHUNK_INDICES.len() as u32), 0, *object_id..*object_id + 1);
+        }
     }
 }
-
@@ -1,36 +1,302 @@
+
+use glam::UVec2;
 use wgpu::{Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureView};
 
 use crate::GameConfig;
 
 pub const FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+//Object id of the chunk then coordinate of the voxel seen in each pixel, see picker::PickTexel. Filled by a pass of its own
+//in the frames a pick is read back, with the other attachments it would not fit the 32 bytes per sample allowed to a pass.
+pub const PICK_FORMAT: TextureFormat = TextureFormat::Rgba32Uint;
+//Depth of the ray hit in each pixel, the same value as the depth attachment but readable from shaders on every backend
+pub const RAY_DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+//World space normal of the face hit in each pixel packed as 4 snorm bytes, w is 0 where the pixel is not lit, like the
+//background, and 0.5 plus half the voxel ambient occlusion where it is. Packed to fit the 32 bytes per sample allowed to
+//the attachments of the chunk pass.
+pub const NORMAL_FORMAT: TextureFormat = TextureFormat::R32Uint;
+//Emitted colour scaled by the intensity as a fraction of MAX_EMISSION of the voxels seen in each pixel,
+//the lighting pass adds it whatever the lights
+pub const EMISSION_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+//Fraction of the light of each shadowed directional light reaching the pixel, one channel per light, see MAX_SHADOWED_LIGHTS
+pub const SHADOW_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+//Fraction of the ambient light reaching the pixel found by the screen space ambient occlusion pass
+pub const AMBIENT_OCCLUSION_FORMAT: TextureFormat = TextureFormat::R8Unorm;
+//Albedo once lit by the lighting pass, then the bloom, presented by the render plane. High dynamic range so that the emissive
+//voxels can be brighter than white, the render plane clamps it.
+pub const LIT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+//Format of the downsampled copies of the lit texture the bloom is blurred in, see BloomRenderer
+pub const BLOOM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+//Number of bloom levels, the first one is half the size of the G-buffer and each following one half the previous one
+pub const BLOOM_LEVELS: u32 = 5;
+//Object id of the chunk of a translucent voxel in the peel keys, see ChunkRenderer::render. Their depth is a RAY_DEPTH_FORMAT.
+pub const PEEL_OBJECT_FORMAT: TextureFormat = TextureFormat::R32Uint;
 
 pub struct GBuffer {
     albedo: Texture,
     pub albedo_texture_view: TextureView,
+
+    depth: Texture,
+    pub depth_texture_view: TextureView,
+
+    ray_depth: Texture,
+    pub ray_depth_texture_view: TextureView,
+
+    normal: Texture,
+    pub normal_texture_view: TextureView,
+
+    emission: Texture,
+    pub emission_texture_view: TextureView,
+
+    shadow: Texture,
+    pub shadow_texture_view: TextureView,
+
+    ambient_occlusion: Texture,
+    pub ambient_occlusion_texture_view: TextureView,
+
+    lit: Texture,
+    pub lit_texture_view: TextureView,
+
+    bloom: Vec<Texture>,
+    pub bloom_texture_views: Vec<TextureView>,
+
+    //Keeps the furthest translucent voxel of all the chunks while the layers are peeled, see ChunkRenderer::render
+    layer_depth: Texture,
+    pub layer_depth_texture_view: TextureView,
+
+    //Key of the closest voxel composited by the last translucent layer. Two of each, one is read while the other is written.
+    peel_depth: Vec<Texture>,
+    pub peel_depth_texture_views: Vec<TextureView>,
+    peel_object: Vec<Texture>,
+    pub peel_object_texture_views: Vec<TextureView>,
+
+    //Key of the furthest voxel left of the chunks other than the one drawing the translucent layer
+    bound_depth: Texture,
+    pub bound_depth_texture_view: TextureView,
+    bound_object: Texture,
+    pub bound_object_texture_view: TextureView,
+
+    pick: Texture,
+    pub pick_texture_view: TextureView,
 }
 
 impl GBuffer {
     pub fn new(device: &Device, render_width: u32, render_height: u32) -> Self {
+        let size = Extent3d {
+            width: render_width,
+            height: render_height,
+            depth_or_array_layers: 1,
+        };
+
         let albedo = device.create_texture(&TextureDescriptor { 
             label: Some("GBuffer albedo"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let albedo_texture_view = albedo.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let depth = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer depth"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: DEPTH_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let depth_texture_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let ray_depth = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer ray depth"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: RAY_DEPTH_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let ray_depth_texture_view = ray_depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let normal = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer normal"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: NORMAL_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let normal_texture_view = normal.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let emission = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer emission"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: EMISSION_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let emission_texture_view = emission.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shadow = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer shadow"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: SHADOW_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let shadow_texture_view = shadow.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let ambient_occlusion = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer ambient occlusion"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: AMBIENT_OCCLUSION_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let ambient_occlusion_texture_view = ambient_occlusion.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let lit = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer lit"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: LIT_FORMAT, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let lit_texture_view = lit.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bloom: Vec<Texture> = (1..=BLOOM_LEVELS).map(|level| device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer bloom"), 
             size: Extent3d {
-                width: render_width,
-                height: render_height,
+                width: (render_width >> level).max(1),
+                height: (render_height >> level).max(1),
                 depth_or_array_layers: 1,
             }, 
             mip_level_count: 1, 
             sample_count: 1, 
             dimension: TextureDimension::D2, 
-            format: FORMAT, 
+            format: BLOOM_FORMAT, 
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
             view_formats: &[]
+        })).collect();
+
+        let bloom_texture_views = bloom.iter().map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default())).collect();
+
+        let layer_depth = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer layer depth"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: DEPTH_FORMAT, 
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
         });
 
-        let albedo_texture_view = albedo.create_view(&wgpu::TextureViewDescriptor::default());
+        let layer_depth_texture_view = layer_depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let peel_target = |label: &str, format: TextureFormat| device.create_texture(&TextureDescriptor { 
+            label: Some(label), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format, 
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT, 
+            view_formats: &[]
+        });
+
+        let peel_depth: Vec<Texture> = (0..2).map(|_| peel_target("GBuffer peel depth", RAY_DEPTH_FORMAT)).collect();
+        let peel_depth_texture_views = peel_depth.iter().map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default())).collect();
+
+        let peel_object: Vec<Texture> = (0..2).map(|_| peel_target("GBuffer peel object", PEEL_OBJECT_FORMAT)).collect();
+        let peel_object_texture_views = peel_object.iter().map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default())).collect();
+
+        let bound_depth = peel_target("GBuffer bound depth", RAY_DEPTH_FORMAT);
+        let bound_depth_texture_view = bound_depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bound_object = peel_target("GBuffer bound object", PEEL_OBJECT_FORMAT);
+        let bound_object_texture_view = bound_object.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let pick = device.create_texture(&TextureDescriptor { 
+            label: Some("GBuffer pick"), 
+            size, 
+            mip_level_count: 1, 
+            sample_count: 1, 
+            dimension: TextureDimension::D2, 
+            format: PICK_FORMAT, 
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC, 
+            view_formats: &[]
+        });
+
+        let pick_texture_view = pick.create_view(&wgpu::TextureViewDescriptor::default());
     
         GBuffer {
             albedo,
-            albedo_texture_view
+            albedo_texture_view,
+            depth,
+            depth_texture_view,
+            ray_depth,
+            ray_depth_texture_view,
+            normal,
+            normal_texture_view,
+            emission,
+            emission_texture_view,
+            shadow,
+            shadow_texture_view,
+            ambient_occlusion,
+            ambient_occlusion_texture_view,
+            lit,
+            lit_texture_view,
+            bloom,
+            bloom_texture_views,
+            layer_depth,
+            layer_depth_texture_view,
+            peel_depth,
+            peel_depth_texture_views,
+            peel_object,
+            peel_object_texture_views,
+            bound_depth,
+            bound_depth_texture_view,
+            bound_object,
+            bound_object_texture_view,
+            pick,
+            pick_texture_view,
         }
     }
+
+    pub fn size(&self) -> UVec2 {
+        UVec2::new(self.albedo.width(), self.albedo.height())
+    }
+
+    pub fn pick_texture(&self) -> &Texture {
+        &self.pick
+    }
 }
\ No newline at end of file
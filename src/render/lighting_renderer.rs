@@ -0,0 +1,223 @@
+use wgpu::{CommandEncoder, Device, PipelineLayoutDescriptor, Queue, RenderPipelineDescriptor};
+
+use crate::scene::{camera::Camera, lighting::{light_grid::LightGrid, Lighting}};
+
+use super::g_buffer::{self, GBuffer};
+
+//Lights the albedo of the G-buffer with the normals written by the chunk renderer and adds the emission, into the lit texture.
+//The screen space ambient occlusion of the ambient light is found by a pass of its own beforehand.
+pub struct LightingRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    ambient_occlusion_pipeline: wgpu::RenderPipeline,
+    g_buffer_bind_group_layout: wgpu::BindGroupLayout,
+    ambient_occlusion_g_buffer_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    lighting_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl LightingRenderer {
+    pub fn new(device: &Device) -> Self {
+        let g_buffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                Self::g_buffer_layout_entry(0, wgpu::TextureSampleType::Float { filterable: false }), //Albedo
+                Self::g_buffer_layout_entry(1, wgpu::TextureSampleType::Uint), //Normal
+                Self::g_buffer_layout_entry(2, wgpu::TextureSampleType::Float { filterable: false }), //Shadow
+                Self::g_buffer_layout_entry(3, wgpu::TextureSampleType::Float { filterable: false }), //Ambient occlusion
+                Self::g_buffer_layout_entry(4, wgpu::TextureSampleType::Float { filterable: false }), //Ray depth
+                Self::g_buffer_layout_entry(5, wgpu::TextureSampleType::Float { filterable: false }), //Emission
+            ],
+            label: Some("lighting_renderer_g_buffer_bind_group_layout"),
+        });
+
+        let ambient_occlusion_g_buffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                Self::g_buffer_layout_entry(0, wgpu::TextureSampleType::Float { filterable: false }), //Ray depth
+                Self::g_buffer_layout_entry(1, wgpu::TextureSampleType::Uint), //Normal
+            ],
+            label: Some("lighting_renderer_ambient_occlusion_g_buffer_bind_group_layout"),
+        });
+
+        let camera_layout = Camera::generate_bind_group_layout(device);
+        let lighting_layout = Lighting::generate_bind_group_layout(device);
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor{
+            label: Some("Lighting renderer pipeline layout"),
+            bind_group_layouts: &[&g_buffer_layout, &lighting_layout, &camera_layout],
+            push_constant_ranges: &[],
+        });
+
+        let ambient_occlusion_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor{
+            label: Some("Lighting renderer ambient occlusion pipeline layout"),
+            bind_group_layouts: &[&ambient_occlusion_g_buffer_layout, &camera_layout, &lighting_layout],
+            push_constant_ranges: &[],
+        });
+
+        let lighting_shader = device.create_shader_module(wgpu::include_wgsl!("shaders/lighting_shader.wgsl"));
+        let ambient_occlusion_shader = device.create_shader_module(wgpu::include_wgsl!("shaders/ambient_occlusion_shader.wgsl"));
+
+        Self {
+            render_pipeline: Self::generate_pipeline(device, &render_pipeline_layout, &lighting_shader, g_buffer::LIT_FORMAT),
+            ambient_occlusion_pipeline: Self::generate_pipeline(device, &ambient_occlusion_pipeline_layout, &ambient_occlusion_shader, g_buffer::AMBIENT_OCCLUSION_FORMAT),
+            g_buffer_bind_group_layout: g_buffer_layout,
+            ambient_occlusion_g_buffer_bind_group_layout: ambient_occlusion_g_buffer_layout,
+            camera_bind_group_layout: camera_layout,
+            lighting_bind_group_layout: lighting_layout,
+        }
+    }
+
+    //G-buffer textures are read texel by texel
+    fn g_buffer_layout_entry(binding: u32, sample_type: wgpu::TextureSampleType) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        }
+    }
+
+    //Both passes draw a fullscreen triangle into a G-buffer texture
+    fn generate_pipeline(device: &Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor{
+            label: Some("Lighting renderer pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState{
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    pub fn render(&self, encoder: &mut CommandEncoder, device: &Device, queue: &Queue, g_buffer: &GBuffer, lighting: &mut Lighting, camera: &Camera) {
+        let light_grid = LightGrid::new(&lighting.data, camera.transform(), g_buffer.size());
+        lighting.write_light_grid(device, queue, &light_grid);
+        let lighting_bind_group = lighting.generate_bind_group(device, &self.lighting_bind_group_layout);
+        let camera_bind_group = camera.generate_bind_group(device, &self.camera_bind_group_layout);
+        self.render_ambient_occlusion(encoder, device, g_buffer, lighting, &camera_bind_group, &lighting_bind_group);
+
+        let g_buffer_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.g_buffer_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.albedo_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.normal_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.shadow_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.ambient_occlusion_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.ray_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.emission_texture_view),
+                },
+            ],
+            label: Some("lighting_renderer_g_buffer_bind_group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Lighting Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.lit_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &g_buffer_bind_group, &[]);
+        render_pass.set_bind_group(1, &lighting_bind_group, &[]);
+        render_pass.set_bind_group(2, &camera_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    //Cleared to no occlusion, which the lighting pass does not read unless the scene enables it
+    fn render_ambient_occlusion(&self, encoder: &mut CommandEncoder, device: &Device, g_buffer: &GBuffer, lighting: &Lighting, camera_bind_group: &wgpu::BindGroup, lighting_bind_group: &wgpu::BindGroup) {
+        let g_buffer_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.ambient_occlusion_g_buffer_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.ray_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&g_buffer.normal_texture_view),
+                },
+            ],
+            label: Some("lighting_renderer_ambient_occlusion_g_buffer_bind_group"),
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Ambient Occlusion Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &g_buffer.ambient_occlusion_texture_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        if lighting.data.ambient_occlusion.screen_space.is_none() {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.ambient_occlusion_pipeline);
+        render_pass.set_bind_group(0, &g_buffer_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, lighting_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
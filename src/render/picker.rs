@@ -0,0 +1,146 @@
+use std::sync::mpsc::{self, Receiver};
+
+use glam::{UVec2, UVec3};
+use uuid::Uuid;
+use wgpu::{Buffer, BufferAsyncError, CommandEncoder, Device, Extent3d};
+
+use super::g_buffer::GBuffer;
+
+//Object id written where no chunk covers the pixel, chunks are numbered from 1
+pub const NO_OBJECT: u32 = 0;
+
+//Texel of the pick attachment: the object id of the chunk then the voxel coordinate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickTexel {
+    pub object_id: u32,
+    pub voxel: UVec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pick {
+    pub chunk: Uuid,
+    pub voxel: UVec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickResult {
+    //Pixel of the window that was requested
+    pub x: u32,
+    pub y: u32,
+    //None when no chunk covers the pixel, or when the chunk was removed before the readback
+    pub pick: Option<Pick>,
+}
+
+//Reads single texels of the pick attachment back without stalling the frame: a request is copied at the end of the next
+//frame rendered, then mapped once the GPU is done with it.
+pub struct Picker {
+    readback_buffer: Buffer,
+    //Pixel to copy during the next frame
+    requested: Option<UVec2>,
+    //Pixel copied in the frame being submitted
+    copied: Option<UVec2>,
+    mapping: Option<(UVec2, Receiver<Result<(), BufferAsyncError>>)>,
+}
+
+impl Picker {
+    pub fn new(device: &Device) -> Self {
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picker readback buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            readback_buffer,
+            requested: None,
+            copied: None,
+            mapping: None,
+        }
+    }
+
+    //Pixel of the G-buffer, a newer request replaces the one not rendered yet
+    pub fn request(&mut self, pixel: UVec2) {
+        self.requested = Some(pixel);
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.requested.is_some() || self.copied.is_some() || self.mapping.is_some()
+    }
+
+    //Whether encode_copy copies a texel in the frame being rendered, the pick texture only has to be filled then
+    pub fn will_copy(&self) -> bool {
+        self.requested.is_some() && self.mapping.is_none() && self.copied.is_none()
+    }
+
+    //To be called after the chunks are rendered, waits for the previous readback to be mapped before copying a new one
+    pub fn encode_copy(&mut self, encoder: &mut CommandEncoder, g_buffer: &GBuffer) {
+        if self.mapping.is_some() || self.copied.is_some() {
+            return;
+        }
+
+        let Some(pixel) = self.requested.take() else {
+            return;
+        };
+
+        //A resize can shrink the G-buffer after the request, the closest texel still answers it
+        let texel = pixel.min(g_buffer.size() - 1);
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: g_buffer.pick_texture(),
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: texel.x, y: texel.y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: Some(1),
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.copied = Some(pixel);
+    }
+
+    //To be called once the frame with the copy has been submitted
+    pub fn after_submit(&mut self) {
+        let Some(pixel) = self.copied.take() else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.mapping = Some((pixel, receiver));
+    }
+
+    //Pixel and texel of the last request once they have been read back, None while the readback is not done
+    pub fn poll(&mut self, device: &Device) -> Option<(UVec2, PickTexel)> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let result = self.mapping.as_ref()?.1.try_recv().ok()?;
+        let (pixel, _) = self.mapping.take().unwrap();
+        result.expect("Failed to map the picker readback buffer");
+
+        let texel = {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let word = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+            PickTexel {
+                object_id: word(0),
+                voxel: UVec3::new(word(1), word(2), word(3)),
+            }
+        };
+        self.readback_buffer.unmap();
+
+        Some((pixel, texel))
+    }
+}
@@ -148,7 +148,7 @@ impl RenderPlane {
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&g_buffer.albedo_texture_view),
+                        resource: wgpu::BindingResource::TextureView(&g_buffer.lit_texture_view),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
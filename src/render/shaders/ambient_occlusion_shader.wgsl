@@ -0,0 +1,120 @@
+// Vertex shader
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(1)
+var t_normal: texture_2d<u32>;
+
+struct CameraUniform {
+    position: vec3<f32>,
+    transform: mat4x4<f32>,
+    inverse_transform: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Same as lighting_shader.wgsl
+const MAX_DIRECTIONAL_LIGHTS: u32 = 8u;
+
+struct DirectionalLight {
+    direction: vec4<f32>,
+    radiance: vec4<f32>,
+    shadow_channel: i32,
+    shadow_cone: f32,
+}
+
+struct LightingUniform {
+    ambient: vec4<f32>,
+    directional_light_count: u32,
+    soft_shadow_samples: u32,
+    ambient_occlusion: u32,
+    directional_lights: array<DirectionalLight, MAX_DIRECTIONAL_LIGHTS>,
+    screen_space_radius: f32,
+    screen_space_intensity: f32,
+}
+
+@group(2) @binding(0)
+var<uniform> lighting: LightingUniform;
+
+const SAMPLES: u32 = 16u;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+const TAU: f32 = 6.28318531;
+// Sine of the angle above the tangent plane from which an occluder counts
+const RISE: f32 = 0.15;
+
+fn world_position(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let center = vec2<f32>(pixel) + 0.5;
+    let ndc = vec2<f32>(center.x / size.x * 2.0 - 1.0, 1.0 - center.y / size.y * 2.0);
+    let world = camera.inverse_transform * vec4<f32>(ndc, textureLoad(t_depth, pixel, 0).r, 1.0);
+    return world.xyz / world.w;
+}
+
+// Per pixel rotation of the samples, trading banding for noise
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+// Samples points in the hemisphere above the surface, the ones behind what the G-buffer shows at their pixel are occluded.
+// Occluders further than the radius from the surface, like the background behind a silhouette, do not count, and neither do
+// the ones about level with the surface, which are the surface itself seen through a neighbouring pixel.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let normal_texel = unpack4x8snorm(textureLoad(t_normal, pixel, 0).x);
+
+    // Nothing lit to occlude
+    if (normal_texel.w == 0.0) {
+        return vec4<f32>(1.0);
+    }
+
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let radius = lighting.screen_space_radius;
+    let normal = normalize(normal_texel.xyz);
+    let position = world_position(pixel);
+    let tangent = normalize(cross(normal, select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(normal.y) > 0.9)));
+    let bitangent = cross(normal, tangent);
+    let noise = interleaved_gradient_noise(in.clip_position.xy);
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < SAMPLES; i++) {
+        // Cosine weighted directions on a spiral, at distances denser close to the surface
+        let t = (f32(i) + 0.5) / f32(SAMPLES);
+        let angle = f32(i) * GOLDEN_ANGLE + noise * TAU;
+        let direction = (tangent * cos(angle) + bitangent * sin(angle)) * sqrt(t) + normal * sqrt(1.0 - t);
+        let reach = mix(0.3, 1.0, fract(t * 7.0 + noise));
+        let sample_pos = position + direction * radius * reach * reach;
+
+        let clip = camera.transform * vec4<f32>(sample_pos, 1.0);
+        let ndc = clip.xy / clip.w;
+        if (clip.w <= 0.0 || any(abs(ndc) >= vec2<f32>(1.0))) {
+            continue;
+        }
+
+        let sample_pixel = vec2<i32>(vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) * size);
+        let surface = world_position(sample_pixel);
+        let to_surface = surface - position;
+        let in_front = distance(camera.position, surface) < distance(camera.position, sample_pos);
+        let above = dot(normal, to_surface) > RISE * length(to_surface);
+        let in_range = smoothstep(0.0, 1.0, radius / max(length(to_surface), 1e-4));
+        occlusion += select(0.0, in_range, in_front && above);
+    }
+
+    return vec4<f32>(1.0 - lighting.screen_space_intensity * occlusion / f32(SAMPLES));
+}
@@ -0,0 +1,87 @@
+// Vertex shader
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// Fragment shader
+
+// The lit texture or a bloom level, sampled bilinearly
+@group(0) @binding(0)
+var t_source: texture_2d<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+struct BloomUniform {
+    threshold: f32,
+    knee: f32,
+    // Already divided by the number of levels summed by the upsampling
+    intensity: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+fn sample_source(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_source));
+    return textureSampleLevel(t_source, s_source, uv + offset * texel, 0.0).rgb;
+}
+
+// Halves the resolution: four bilinear taps each averaging a square of 2x2 texels, a box of 4x4 texels around the pixel
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    return (sample_source(uv, vec2<f32>(-1.0, -1.0)) + sample_source(uv, vec2<f32>(1.0, -1.0))
+        + sample_source(uv, vec2<f32>(-1.0, 1.0)) + sample_source(uv, vec2<f32>(1.0, 1.0))) * 0.25;
+}
+
+// Doubles the resolution with a 3x3 tent filter, which blurs the level further on the way up
+fn upsample(uv: vec2<f32>) -> vec3<f32> {
+    var sum = sample_source(uv, vec2<f32>(0.0)) * 4.0;
+    sum += (sample_source(uv, vec2<f32>(-1.0, 0.0)) + sample_source(uv, vec2<f32>(1.0, 0.0))
+        + sample_source(uv, vec2<f32>(0.0, -1.0)) + sample_source(uv, vec2<f32>(0.0, 1.0))) * 2.0;
+    sum += sample_source(uv, vec2<f32>(-1.0, -1.0)) + sample_source(uv, vec2<f32>(1.0, -1.0))
+        + sample_source(uv, vec2<f32>(-1.0, 1.0)) + sample_source(uv, vec2<f32>(1.0, 1.0));
+    return sum / 16.0;
+}
+
+// Keeps the part of the colour above the threshold, with a quadratic curve easing in over the knee below it
+fn bright_part(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = bloom.threshold * bloom.knee;
+    let soft = clamp(brightness - bloom.threshold + knee, 0.0, 2.0 * knee);
+    let contribution = max(soft * soft / (4.0 * knee + 1e-5), brightness - bloom.threshold);
+    return color * contribution / max(brightness, 1e-5);
+}
+
+// First level, from the lit texture
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(bright_part(downsample(in.uv)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv), 1.0);
+}
+
+// Added to the larger level
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(in.uv), 1.0);
+}
+
+// Added to the lit texture, leaving its alpha untouched
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(upsample(in.uv) * bloom.intensity, 0.0);
+}
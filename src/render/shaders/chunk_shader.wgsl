@@ -7,13 +7,21 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) local_position: vec4<f32>,
-    @location(1) world_position: vec4<f32>
+    @location(1) world_position: vec4<f32>,
+    @location(2) @interpolate(flat) object_id: u32,
 }
 
 struct ChunkUniform {
     size: vec3<u32>,
+    layers: u32,
     transform: mat4x4<f32>,
-    invert_rotation: mat4x4<f32>
+    invert_rotation: mat4x4<f32>,
+    inverse_transform: mat4x4<f32>,
+    albedo_storage: u32,
+    flags: u32,
+    alpha_threshold: u32,
+    render_mode: u32,
+    volume_step: f32,
 }
 
 @group(0) @binding(0) 
@@ -22,14 +30,16 @@ var<uniform> chunk: ChunkUniform;
 struct CameraUniform {
     position: vec3<f32>,
     transform: mat4x4<f32>,
+    inverse_transform: mat4x4<f32>,
 }
 
 @group(1) @binding(0) 
 var<uniform> camera: CameraUniform;
 
 @vertex
-fn vs_main( in: VertexInput,) -> VertexOutput {
+fn vs_main( in: VertexInput, @builtin(instance_index) object_id: u32) -> VertexOutput {
     var out: VertexOutput;
+    out.object_id = object_id;
     out.local_position = vec4<f32>(in.position, 1.0);
     out.world_position = chunk.transform * out.local_position;
     out.clip_position = camera.transform * out.world_position;
@@ -41,14 +51,373 @@ var t_albedo: texture_3d<f32>;
 @group(0) @binding(2)
 var c_sampler: sampler;
 
-// Fragment shader
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+// Optional layers, the bit of each one in chunk.layers tells if the chunk has it, otherwise a 1x1x1 placeholder is bound
+const LAYER_NORMAL: u32 = 2u;
+const LAYER_MATERIAL: u32 = 4u;
+const LAYER_EMISSION: u32 = 8u;
+const LAYER_ROUGHNESS: u32 = 16u;
+const LAYER_DENSITY: u32 = 32u;
+
+@group(0) @binding(3)
+var t_normal: texture_3d<f32>;
+@group(0) @binding(4)
+var t_material: texture_3d<u32>;
+@group(0) @binding(5)
+var t_emission: texture_3d<f32>;
+@group(0) @binding(6)
+var t_roughness: texture_3d<f32>;
+
+fn has_layer(layer: u32) -> bool {
+    return (chunk.layers & layer) != 0u;
+}
+
+// Indexed chunks bind a placeholder as t_albedo and store their colours as packed RGBA8 in the palette
+const ALBEDO_INDEXED: u32 = 1u;
+
+@group(0) @binding(7)
+var t_albedo_index: texture_3d<u32>;
+@group(0) @binding(8)
+var<storage, read> palette: array<u32>;
+
+// Voxels are solid when their alpha is above the threshold of the chunk, mirrored by voxel_is_solid
+fn is_solid(color: vec4<f32>) -> bool {
+    return u32(round(color.a * 255.0)) > chunk.alpha_threshold;
+}
+
+// Same clamping as the sampler used for RGBA albedos
+fn clamp_to_chunk(map_pos: vec3<i32>) -> vec3<i32> {
+    return clamp(map_pos, vec3<i32>(0), vec3<i32>(chunk.size) - vec3<i32>(1));
+}
+
+// Emitted colour of the voxel scaled by its intensity as a fraction of MAX_EMISSION, the lighting pass applies MAX_EMISSION
+fn emission_at(map_pos: vec3<i32>) -> vec3<f32> {
+    if (!has_layer(LAYER_EMISSION)) {
+        return vec3<f32>(0.0);
+    }
+    let emission = textureLoad(t_emission, clamp_to_chunk(map_pos), 0);
+    return emission.rgb * emission.a;
+}
+
+fn albedo_at(map_pos: vec3<i32>) -> vec4<f32> {
+    if (chunk.albedo_storage == ALBEDO_INDEXED) {
+        let texel = clamp_to_chunk(map_pos);
+        let index = min(textureLoad(t_albedo_index, texel, 0).x, arrayLength(&palette) - 1u);
+        return unpack4x8unorm(palette[index]);
+    }
+
+    return textureSample(t_albedo, c_sampler, vec3<f32>(f32(map_pos.x) / f32(chunk.size.x), f32(map_pos.y) / f32(chunk.size.y), f32(map_pos.z) / f32(chunk.size.z)));
+}
+
+// Brickmap chunks store their voxels in 8x8x8 bricks, the grid holds the index of each brick in the atlas plus one
+const ALBEDO_BRICKMAP: u32 = 2u;
+const BRICK_SIZE: i32 = 8;
+const EMPTY_BRICK: u32 = 0u;
+
+@group(0) @binding(9)
+var t_brick_grid: texture_3d<u32>;
+@group(0) @binding(10)
+var t_brick_atlas: texture_3d<f32>;
+
+fn brick_at(texel: vec3<i32>) -> u32 {
+    return textureLoad(t_brick_grid, texel / BRICK_SIZE, 0).x;
+}
+
+// Bricks fill the atlas along x first then y then z
+fn brick_albedo_at(brick: u32, texel: vec3<i32>) -> vec4<f32> {
+    let atlas_bricks = textureDimensions(t_brick_atlas) / u32(BRICK_SIZE);
+    let slot = brick - 1u;
+    let atlas_brick = vec3<u32>(slot % atlas_bricks.x, (slot / atlas_bricks.x) % atlas_bricks.y, slot / (atlas_bricks.x * atlas_bricks.y));
+    return textureLoad(t_brick_atlas, vec3<i32>(atlas_brick) * BRICK_SIZE + texel % BRICK_SIZE, 0);
+}
+
+// Chebyshev distance from each voxel to the closest solid one, only bound when chunk.flags has FLAG_DISTANCE_FIELD
+const FLAG_DISTANCE_FIELD: u32 = 1u;
+
+@group(0) @binding(11)
+var t_distance: texture_3d<u32>;
+
+// Moves the traversal to the first voxel after the box of empty voxels [box_min, box_max] containing the current voxel,
+// stepping each axis as many times as the voxel by voxel traversal would. Mirrored by VoxelTraversal::leap.
+fn leap(map_pos: ptr<function, vec3<i32>>, side_dist: ptr<function, vec3<f32>>, mask: ptr<function, vec3<i32>>, hit_dist: ptr<function, f32>, box_min: vec3<i32>, box_max: vec3<i32>, ray_step: vec3<i32>, delta_dist: vec3<f32>) {
+    let crossings = max(select(*map_pos - box_min + 1, box_max - *map_pos + 1, ray_step > vec3<i32>(0)), vec3<i32>(1));
+    let exits = *side_dist + vec3<f32>(crossings - 1) * delta_dist;
+    let exit_dist = min(exits.x, min(exits.y, exits.z));
+    let exit_axes = exits <= vec3<f32>(exit_dist);
+
+    var steps = clamp(vec3<i32>(floor((exit_dist - *side_dist) / delta_dist)) + 1, vec3<i32>(0), crossings);
+    steps = select(steps, crossings, exit_axes);
+    steps = select(steps, vec3<i32>(0), ray_step == vec3<i32>(0));
+
+    *mask = vec3<i32>(exit_axes);
+    *hit_dist = exit_dist;
+    *side_dist += vec3<f32>(steps) * delta_dist;
+    *map_pos += steps * ray_step;
+}
+
+// Reads the voxel the traversal is in into color, leaping over the empty voxels around it instead when they are known.
+// Returns false after a leap, the traversal then goes on from the voxel it leaped to.
+fn read_voxel(map_pos: ptr<function, vec3<i32>>, side_dist: ptr<function, vec3<f32>>, mask: ptr<function, vec3<i32>>, hit_dist: ptr<function, f32>, color: ptr<function, vec4<f32>>, ray_step: vec3<i32>, delta_dist: vec3<f32>) -> bool {
+    if (chunk.albedo_storage == ALBEDO_BRICKMAP) {
+        let texel = clamp_to_chunk(*map_pos);
+        let brick = brick_at(texel);
+        if (brick == EMPTY_BRICK) {
+            let brick_min = (texel / BRICK_SIZE) * BRICK_SIZE;
+            leap(map_pos, side_dist, mask, hit_dist, brick_min, brick_min + BRICK_SIZE - 1, ray_step, delta_dist);
+            *color = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+            return false;
+        }
+
+        *color = brick_albedo_at(brick, texel);
+        return true;
+    }
+
+    *color = albedo_at(*map_pos);
+
+    if (!is_solid(*color) && (chunk.flags & FLAG_DISTANCE_FIELD) != 0u) {
+        let texel = clamp_to_chunk(*map_pos);
+        let radius = i32(textureLoad(t_distance, texel, 0).x) - 1;
+        if (radius > 0) {
+            leap(map_pos, side_dist, mask, hit_dist, texel - radius, texel + radius, ray_step, delta_dist);
+            return false;
+        }
+    }
+
+    return true;
+}
+
+// Moves the traversal to the next voxel along the ray
+fn step_voxel(map_pos: ptr<function, vec3<i32>>, side_dist: ptr<function, vec3<f32>>, mask: ptr<function, vec3<i32>>, hit_dist: ptr<function, f32>, ray_step: vec3<i32>, delta_dist: vec3<f32>) {
+    let sides = *side_dist;
+    *mask = vec3<i32>(
+        i32(sides.x <= min(sides.y, sides.z)),
+        i32(sides.y <= min(sides.z, sides.x)),
+        i32(sides.z <= min(sides.x, sides.y))
+    );
+
+    *hit_dist = min(sides.x, min(sides.y, sides.z));
+    *side_dist += vec3<f32>(*mask) * delta_dist;
+    *map_pos += *mask * ray_step;
+}
+
+// Accumulating chunks composite every solid voxel along the ray front to back instead of stopping at the first one.
+// They are drawn after the opaque chunks, which they cannot see through.
+const RENDER_MODE_ACCUMULATE: u32 = 1u;
+// Opacity from which the voxels further along the ray cannot be seen anymore
+const OPACITY_SATURATION: f32 = 0.99;
+// Same as picker::NO_OBJECT
+const NO_OBJECT: u32 = 0u;
+
+// Translucent voxels are ordered in each pixel by their depth then by the object id of their chunk, and drawn in layers from
+// the furthest to the closest, see ChunkRenderer::render. They are only composited in front of the peel key: the depth of
+// the opaque chunks at first, then the closest voxel composited by the previous layer. Placeholders are bound while the
+// opaque chunks are drawn.
+struct PeelKey {
+    depth: f32,
+    object_id: u32,
+}
+
+@group(2) @binding(0)
+var t_peel_depth: texture_2d<f32>;
+@group(2) @binding(1)
+var t_peel_object: texture_2d<u32>;
+// Chunk drawing the layer in each pixel, it composites its voxels between the bound key and the peel key
+@group(2) @binding(2)
+var t_layer_object: texture_2d<u32>;
+@group(2) @binding(3)
+var t_bound_depth: texture_2d<f32>;
+@group(2) @binding(4)
+var t_bound_object: texture_2d<u32>;
+
+fn key_before(a: PeelKey, b: PeelKey) -> bool {
+    return a.depth < b.depth || (a.depth == b.depth && a.object_id < b.object_id);
+}
+
+fn peel_key(pixel: vec2<i32>) -> PeelKey {
+    return PeelKey(textureLoad(t_peel_depth, pixel, 0).r, textureLoad(t_peel_object, pixel, 0).x);
+}
+
+fn depth_at(ray_pos: vec3<f32>) -> f32 {
+    let local = vec4<f32>(ray_pos.x / f32(chunk.size.x), ray_pos.y / f32(chunk.size.y), ray_pos.z / f32(chunk.size.z), 1.0);
+    let clip = camera.transform * chunk.transform * local;
+    return clip.z / clip.w;
+}
+
+struct FragmentOutput {
+    @location(0) albedo: vec4<f32>,
+    // Emission of the voxels, premultiplied by the coverage like the albedo so translucent chunks blend over it
+    @location(1) emission: vec4<f32>,
+    // Same as the depth, kept where the translucent chunks can read it. Translucent chunks write it to the peel depth instead.
+    @location(2) ray_depth: f32,
+    // World space normal of the face hit packed with pack4x8snorm, w tells the lighting pass whether to light the pixel
+    @location(3) normal: u32,
+    // Object id of the chunk then coordinate of the voxel, read back for picking. The render passes have no target for it,
+    // see fs_pick.
+    @location(4) pick: vec4<u32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// Outward normal of the face of the chunk box the fragment lies on, the face through which the ray enters the chunk
+fn entry_normal(local_position: vec3<f32>) -> vec3<f32> {
+    let to_faces = min(local_position, 1.0 - local_position) * vec3<f32>(chunk.size);
+    let outward = select(vec3<f32>(1.0), vec3<f32>(-1.0), local_position < vec3<f32>(0.5));
+    if (to_faces.x <= to_faces.y && to_faces.x <= to_faces.z) {
+        return vec3<f32>(outward.x, 0.0, 0.0);
+    }
+    if (to_faces.y <= to_faces.z) {
+        return vec3<f32>(0.0, outward.y, 0.0);
+    }
+    return vec3<f32>(0.0, 0.0, outward.z);
+}
+
+// Voxel space normal of the face hit. The DDA mask holds the axis crossed to enter the voxel hit, it is empty when the voxel
+// hit is the one the ray entered the chunk in.
+fn face_normal(mask: vec3<i32>, ray_step: vec3<i32>, local_position: vec3<f32>) -> vec3<i32> {
+    if (all(mask == vec3<i32>(0))) {
+        return vec3<i32>(entry_normal(local_position));
+    }
+    return -mask * ray_step;
+}
+
+// Whether the voxel is solid, voxels outside the chunk are empty
+fn occupied(map_pos: vec3<i32>) -> bool {
+    if (any(map_pos < vec3<i32>(0)) || any(map_pos >= vec3<i32>(chunk.size))) {
+        return false;
+    }
+
+    if (chunk.albedo_storage == ALBEDO_BRICKMAP) {
+        let brick = brick_at(map_pos);
+        return brick != EMPTY_BRICK && is_solid(brick_albedo_at(brick, map_pos));
+    }
+    return is_solid(albedo_at(map_pos));
+}
+
+// Occlusion of a corner of a face from the two voxels beside it and the one diagonal to it, in the layer in front of the face
+fn corner_occlusion(front: vec3<i32>, side_u: vec3<i32>, side_v: vec3<i32>) -> f32 {
+    let u = occupied(front + side_u);
+    let v = occupied(front + side_v);
+    if (u && v) {
+        return 0.0;
+    }
+    return (3.0 - f32(u) - f32(v) - f32(occupied(front + side_u + side_v))) / 3.0;
+}
+
+// Fraction of the ambient light reaching the point hit on the face of the voxel at map_pos, interpolated between its corners
+fn voxel_ambient_occlusion(map_pos: vec3<i32>, normal: vec3<i32>, hit_pos: vec3<f32>) -> f32 {
+    let axis_u = abs(normal).yzx;
+    let axis_v = abs(normal).zxy;
+    let front = map_pos + normal;
+    let in_voxel = clamp(hit_pos - vec3<f32>(map_pos), vec3<f32>(0.0), vec3<f32>(1.0));
+    let uv = vec2<f32>(dot(in_voxel, vec3<f32>(axis_u)), dot(in_voxel, vec3<f32>(axis_v)));
+
+    let low = mix(corner_occlusion(front, -axis_u, -axis_v), corner_occlusion(front, axis_u, -axis_v), uv.x);
+    let high = mix(corner_occlusion(front, -axis_u, axis_v), corner_occlusion(front, axis_u, axis_v), uv.x);
+    return mix(low, high, uv.y);
+}
+
+// Rotates the normal to world space and packs it with the voxel ambient occlusion, see NORMAL_FORMAT
+fn pack_normal(normal: vec3<i32>, occlusion: f32) -> u32 {
+    // The inverse of a rotation is its transpose
+    let world_normal = (transpose(chunk.invert_rotation) * vec4<f32>(vec3<f32>(normal), 0.0)).xyz;
+    return pack4x8snorm(vec4<f32>(world_normal, 0.5 + 0.5 * occlusion));
+}
+
+// Volume chunks map their density through the transfer function every volume_step voxels instead of marching voxel by voxel
+const RENDER_MODE_VOLUME: u32 = 2u;
+// Bounds the samples taken when the volume step is tiny compared to the chunk
+const MAX_VOLUME_SAMPLES: i32 = 2048;
+
+@group(0) @binding(12)
+var t_density: texture_3d<f32>;
+@group(0) @binding(13)
+var s_volume: sampler;
+@group(0) @binding(14)
+var t_transfer_function: texture_2d<f32>;
+// Same as TRANSFER_FUNCTION_SIZE
+const TRANSFER_FUNCTION_SIZE: f32 = 256.0;
+
+// Distance along the ray to where it leaves the chunk, the ray starts on or inside the chunk
+fn exit_distance(ray_pos: vec3<f32>, ray_dir: vec3<f32>) -> f32 {
+    let bounds = select(vec3<f32>(0.0), vec3<f32>(chunk.size), ray_dir > vec3<f32>(0.0));
+    let exits = select(vec3<f32>(3.4e38), (bounds - ray_pos) / ray_dir, ray_dir != vec3<f32>(0.0));
+    return max(min(exits.x, min(exits.y, exits.z)), 0.0);
+}
+
+// Samples are spread volume_step voxels apart, or further apart when the volume step is tiny compared to the chunk
+fn volume_sample_step(exit_dist: f32) -> f32 {
+    return max(chunk.volume_step, exit_dist / f32(MAX_VOLUME_SAMPLES));
+}
+
+// Premultiplied colour given by the transfer function to the density at sample_pos, over a step of `step` voxels
+fn volume_sample(sample_pos: vec3<f32>, step: f32) -> vec4<f32> {
+    let density = textureSampleLevel(t_density, s_volume, sample_pos / vec3<f32>(chunk.size), 0.0).r;
+    // Texel i holds the density i / 255, sampled at its centre
+    let color = textureSampleLevel(t_transfer_function, s_volume, vec2<f32>((density * (TRANSFER_FUNCTION_SIZE - 1.0) + 0.5) / TRANSFER_FUNCTION_SIZE, 0.5), 0.0);
+    // The transfer function gives the opacity of one voxel, a step covers `step` voxels
+    let alpha = 1.0 - pow(1.0 - color.a, step);
+    return vec4<f32>(color.rgb * alpha, alpha);
+}
+
+fn integrate_volume(in: VertexOutput, ray_pos: vec3<f32>, ray_dir: vec3<f32>, bound: PeelKey) -> FragmentOutput {
+    let peel = peel_key(vec2<i32>(in.clip_position.xy));
+    let exit_dist = exit_distance(ray_pos, ray_dir);
+    let step = volume_sample_step(exit_dist);
+
+    var accumulated = vec4<f32>(0.0);
+    var first_hit = false;
+    var first_dist = 0.0;
+
+    // Samples are taken in the middle of each step
+    for (var dist = step * 0.5; dist < exit_dist; dist += step) {
+        let sample_pos = ray_pos + ray_dir * dist;
+        let key = PeelKey(depth_at(sample_pos), in.object_id);
+        if (!key_before(key, peel)) {
+            break;
+        }
+
+        let color = volume_sample(sample_pos, step);
+        if (color.a <= 0.0 || !key_before(bound, key)) {
+            continue;
+        }
+
+        if (!first_hit) {
+            first_hit = true;
+            first_dist = dist;
+        }
+
+        accumulated += (1.0 - accumulated.a) * color;
+        if (accumulated.a >= OPACITY_SATURATION) {
+            break;
+        }
+    }
+
+    if (!first_hit) {
+        discard;
+    }
+
+    let first_pos = ray_pos + ray_dir * first_dist;
+    var out: FragmentOutput;
+    out.albedo = accumulated;
+    out.pick = vec4<u32>(in.object_id, vec3<u32>(clamp_to_chunk(vec3<i32>(floor(first_pos)))));
+    out.depth = depth_at(first_pos);
+    out.ray_depth = out.depth;
+    // Lit by their transfer function alone
+    out.normal = 0u;
+    out.emission = vec4<f32>(0.0, 0.0, 0.0, accumulated.a);
+    return out;
+}
+
+// Traces the chunk, translucent chunks only composite their voxels behind the bound key
+fn trace_chunk(in: VertexOutput, bound: PeelKey) -> FragmentOutput {
     let ray = chunk.invert_rotation * (in.world_position - vec4<f32>(camera.position, 1));
     let ray_dir = normalize(ray);
     let ray_pos =  vec3<f32>(in.local_position.x * f32(chunk.size.x), in.local_position.y * f32(chunk.size.y), in.local_position.z * f32(chunk.size.z));
 
-    var map_pos = vec3<i32>(i32(ray_pos.x), i32(ray_pos.y), i32(ray_pos.z));
+    if (chunk.render_mode == RENDER_MODE_VOLUME) {
+        return integrate_volume(in, ray_pos, ray_dir.xyz, bound);
+    }
+
+    // Rays entering through the far faces of the chunk start on them, in the last voxel
+    var map_pos = clamp_to_chunk(vec3<i32>(i32(ray_pos.x), i32(ray_pos.y), i32(ray_pos.z)));
     
     let delta_dist = abs(vec3<f32>(1.0/ray_dir.x, 1.0/ray_dir.y, 1.0/ray_dir.z)); 
 
@@ -63,33 +432,352 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
      
     var mask = vec3<i32>(0, 0, 0);
     var color = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    // Distance travelled from the chunk surface to the face of the current voxel, in voxels
+    var hit_dist = 0.0;
+
+    let accumulate = chunk.render_mode == RENDER_MODE_ACCUMULATE;
+    let peel = peel_key(vec2<i32>(in.clip_position.xy));
+    // Premultiplied colour of the voxels composited so far, and the first of them which gives the depth and the pick
+    var accumulated = vec4<f32>(0.0);
+    // Emitted by the voxels composited so far, the ones further behind shine through the ones in front by their transparency
+    var accumulated_emission = vec3<f32>(0.0);
+    var first_hit = false;
+    var first_pos = vec3<i32>(0);
+    var first_dist = 0.0;
+    var first_mask = vec3<i32>(0);
+
     for (var i = 0; i < i32(chunk.size.x + chunk.size.y + chunk.size.z); i++) {
-        if (map_pos.x < 0 || map_pos.y < 0 || map_pos.z < 0 || map_pos.x > i32(chunk.size.x) || map_pos.y > i32(chunk.size.y) || map_pos.z > i32(chunk.size.z)) {
+        if (map_pos.x < 0 || map_pos.y < 0 || map_pos.z < 0 || map_pos.x >= i32(chunk.size.x) || map_pos.y >= i32(chunk.size.y) || map_pos.z >= i32(chunk.size.z)) {
+            if (first_hit) {
+                break;
+            }
             discard;
         }
 
-        color = textureSample(t_albedo, c_sampler, vec3<f32>(f32(map_pos.x) / f32(chunk.size.x), f32(map_pos.y) / f32(chunk.size.y), f32(map_pos.z) / f32(chunk.size.z)));
-        if (color.x != 0.0) {
+        if (!read_voxel(&map_pos, &side_dist, &mask, &hit_dist, &color, ray_step, delta_dist)) {
+            continue;
+        }
+
+        if (is_solid(color)) {
+            if (!accumulate) {
+                break;
+            }
+
+            let key = PeelKey(depth_at(ray_pos + ray_dir.xyz * hit_dist), in.object_id);
+            if (!key_before(key, peel)) {
+                break;
+            }
+
+            if (key_before(bound, key)) {
+                if (!first_hit) {
+                    first_hit = true;
+                    first_pos = map_pos;
+                    first_dist = hit_dist;
+                    first_mask = mask;
+                }
+
+                accumulated_emission += (1.0 - accumulated.a) * emission_at(map_pos);
+                accumulated += (1.0 - accumulated.a) * vec4<f32>(color.rgb * color.a, color.a);
+                if (accumulated.a >= OPACITY_SATURATION) {
+                    break;
+                }
+            }
+        }
+
+        step_voxel(&map_pos, &side_dist, &mask, &hit_dist, ray_step, delta_dist);
+    }
+
+    if (accumulate) {
+        if (!first_hit) {
+            discard;
+        }
+
+        var out: FragmentOutput;
+        out.albedo = accumulated;
+        out.pick = vec4<u32>(in.object_id, vec3<u32>(first_pos));
+        out.depth = depth_at(ray_pos + ray_dir.xyz * first_dist);
+        out.ray_depth = out.depth;
+        let first_normal = face_normal(first_mask, ray_step, in.local_position.xyz);
+        out.normal = pack_normal(first_normal, voxel_ambient_occlusion(first_pos, first_normal, ray_pos + ray_dir.xyz * first_dist));
+        out.emission = vec4<f32>(accumulated_emission, accumulated.a);
+        return out;
+    }
+
+    // Project the hit point back to clip space so chunks occlude each other per voxel
+    var out: FragmentOutput;
+    out.albedo = color;
+    out.pick = vec4<u32>(in.object_id, vec3<u32>(map_pos));
+    out.depth = depth_at(ray_pos + ray_dir.xyz * hit_dist);
+    out.ray_depth = out.depth;
+    let normal = face_normal(mask, ray_step, in.local_position.xyz);
+    out.normal = pack_normal(normal, voxel_ambient_occlusion(map_pos, normal, ray_pos + ray_dir.xyz * hit_dist));
+    out.emission = vec4<f32>(emission_at(map_pos), 1.0);
+    return out;
+}
+
+// Fragment shader, also drawing the translucent voxels left in front of the last layer
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    return trace_chunk(in, PeelKey(0.0, NO_OBJECT));
+}
+
+struct PickOutput {
+    @location(0) pick: vec4<u32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// Writes the pick of the first voxel, translucent chunks trace up to the opaque chunks in the peel depth
+@fragment
+fn fs_pick(in: VertexOutput) -> PickOutput {
+    let traced = trace_chunk(in, PeelKey(0.0, NO_OBJECT));
+    return PickOutput(traced.pick, traced.depth);
+}
+
+// Draws a layer of translucent voxels, only the chunk of the layer composites its voxels in each pixel
+@fragment
+fn fs_layer(in: VertexOutput) -> FragmentOutput {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    if (textureLoad(t_layer_object, pixel, 0).x != in.object_id) {
+        discard;
+    }
+    return trace_chunk(in, PeelKey(textureLoad(t_bound_depth, pixel, 0).r, textureLoad(t_bound_object, pixel, 0).x));
+}
+
+struct PeelOutput {
+    @location(0) depth: f32,
+    @location(1) object_id: u32,
+    @builtin(frag_depth) frag_depth: f32,
+}
+
+// Key of the furthest translucent voxel of the chunk in front of the peel key, unless the chunk is the excluded one.
+// The depth test keeps the furthest key of all the chunks, they are drawn by increasing object id so that ties go to the
+// largest one.
+fn furthest_voxel(in: VertexOutput, excluded: u32) -> PeelOutput {
+    if (in.object_id == excluded) {
+        discard;
+    }
+
+    let peel = peel_key(vec2<i32>(in.clip_position.xy));
+    let ray_dir = normalize(chunk.invert_rotation * (in.world_position - vec4<f32>(camera.position, 1))).xyz;
+    let ray_pos = in.local_position.xyz * vec3<f32>(chunk.size);
+    var found = false;
+    var furthest = 0.0;
+
+    if (chunk.render_mode == RENDER_MODE_VOLUME) {
+        let exit_dist = exit_distance(ray_pos, ray_dir);
+        let step = volume_sample_step(exit_dist);
+        for (var dist = step * 0.5; dist < exit_dist; dist += step) {
+            let sample_pos = ray_pos + ray_dir * dist;
+            let depth = depth_at(sample_pos);
+            if (!key_before(PeelKey(depth, in.object_id), peel)) {
+                break;
+            }
+
+            if (volume_sample(sample_pos, step).a > 0.0) {
+                found = true;
+                furthest = depth;
+            }
+        }
+    } else {
+        var map_pos = clamp_to_chunk(vec3<i32>(ray_pos));
+        let delta_dist = abs(1.0 / ray_dir);
+        let ray_step = vec3<i32>(sign(ray_dir));
+        var side_dist = ((vec3<f32>(map_pos) - ray_pos) * vec3<f32>(ray_step) + vec3<f32>(ray_step + 1) / 2.0) * delta_dist;
+        var mask = vec3<i32>(0);
+        var hit_dist = 0.0;
+        var color = vec4<f32>(0.0);
+
+        for (var i = 0; i < i32(chunk.size.x + chunk.size.y + chunk.size.z); i++) {
+            if (any(map_pos < vec3<i32>(0)) || any(map_pos >= vec3<i32>(chunk.size))) {
+                break;
+            }
+
+            if (!read_voxel(&map_pos, &side_dist, &mask, &hit_dist, &color, ray_step, delta_dist)) {
+                continue;
+            }
+
+            if (is_solid(color)) {
+                let depth = depth_at(ray_pos + ray_dir * hit_dist);
+                if (!key_before(PeelKey(depth, in.object_id), peel)) {
+                    break;
+                }
+                found = true;
+                furthest = depth;
+            }
+
+            step_voxel(&map_pos, &side_dist, &mask, &hit_dist, ray_step, delta_dist);
+        }
+    }
+
+    if (!found) {
+        discard;
+    }
+
+    var out: PeelOutput;
+    out.depth = furthest;
+    out.object_id = in.object_id;
+    out.frag_depth = furthest;
+    return out;
+}
+
+// Chunk of the layer in each pixel, the one with the furthest voxel left
+@fragment
+fn fs_furthest(in: VertexOutput) -> PeelOutput {
+    return furthest_voxel(in, NO_OBJECT);
+}
+
+// Bound of the layer in each pixel, the furthest voxel left of the other chunks
+@fragment
+fn fs_bound(in: VertexOutput) -> PeelOutput {
+    return furthest_voxel(in, textureLoad(t_layer_object, vec2<i32>(in.clip_position.xy), 0).x);
+}
+
+// Shadow pass: a fullscreen triangle is drawn per chunk, each pixel of the G-buffer traces rays from the surface it shows
+// towards the shadowed lights through the chunk, and the shadow target is multiplied by the fraction of light that gets through.
+// t_peel_depth holds the depth of the G-buffer during this pass.
+
+// Same as MAX_DIRECTIONAL_LIGHTS
+const MAX_DIRECTIONAL_LIGHTS: u32 = 8u;
+// Offset of the shadow rays along the normal of the surface they leave, in voxels, so it does not shadow itself
+const SHADOW_BIAS: f32 = 0.05;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+const TAU: f32 = 6.28318531;
+
+// Same as lighting_shader.wgsl
+struct DirectionalLight {
+    direction: vec4<f32>,
+    radiance: vec4<f32>,
+    shadow_channel: i32,
+    // Tangent of the angular radius of the light, 0 for hard shadows
+    shadow_cone: f32,
+}
+
+struct LightingUniform {
+    ambient: vec4<f32>,
+    directional_light_count: u32,
+    soft_shadow_samples: u32,
+    ambient_occlusion: u32,
+    directional_lights: array<DirectionalLight, MAX_DIRECTIONAL_LIGHTS>,
+    screen_space_radius: f32,
+    screen_space_intensity: f32,
+}
+
+@group(3) @binding(0)
+var t_g_normal: texture_2d<u32>;
+@group(3) @binding(1)
+var<uniform> lighting: LightingUniform;
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Fraction of the light going along the ray that crosses the chunk, in voxel space. Accumulating chunks let it through by
+// the alpha of their voxels, the others block it at the first solid voxel.
+fn transmittance(origin: vec3<f32>, dir: vec3<f32>) -> f32 {
+    // Clip the ray to the box of the chunk
+    let safe_dir = select(dir, vec3<f32>(1e-7), dir == vec3<f32>(0.0));
+    let to_min = -origin / safe_dir;
+    let to_max = (vec3<f32>(chunk.size) - origin) / safe_dir;
+    let near = min(to_min, to_max);
+    let far = max(to_min, to_max);
+    let enter_dist = max(max(near.x, max(near.y, near.z)), 0.0);
+    let leave_dist = min(far.x, min(far.y, far.z));
+    if (enter_dist >= leave_dist) {
+        return 1.0;
+    }
+
+    let ray_pos = origin + dir * enter_dist;
+    var map_pos = clamp_to_chunk(vec3<i32>(floor(ray_pos)));
+    let delta_dist = abs(1.0 / safe_dir);
+    let ray_step = vec3<i32>(sign(dir));
+    var side_dist = ((vec3<f32>(map_pos) - ray_pos) * vec3<f32>(ray_step) + vec3<f32>(ray_step + 1) / 2.0) * delta_dist;
+    var mask = vec3<i32>(0);
+    var hit_dist = 0.0;
+    var color = vec4<f32>(0.0);
+    var light = 1.0;
+
+    for (var i = 0; i < i32(chunk.size.x + chunk.size.y + chunk.size.z); i++) {
+        if (any(map_pos < vec3<i32>(0)) || any(map_pos >= vec3<i32>(chunk.size))) {
             break;
         }
 
-        mask = vec3<i32>(
-            i32(side_dist.x <= min(side_dist.y, side_dist.z)),
-            i32(side_dist.y <= min(side_dist.z, side_dist.x)),
-            i32(side_dist.z <= min(side_dist.x, side_dist.y))
-        );
+        if (!read_voxel(&map_pos, &side_dist, &mask, &hit_dist, &color, ray_step, delta_dist)) {
+            continue;
+        }
+
+        if (is_solid(color)) {
+            if (chunk.render_mode != RENDER_MODE_ACCUMULATE) {
+                return 0.0;
+            }
+
+            light *= 1.0 - color.a;
+            if (light <= 1.0 - OPACITY_SATURATION) {
+                return 0.0;
+            }
+        }
+
+        step_voxel(&map_pos, &side_dist, &mask, &hit_dist, ray_step, delta_dist);
+    }
+
+    return light;
+}
+
+// Per pixel rotation of the soft shadow rays, trading banding for noise
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_shadow(@builtin(position) clip_position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(clip_position.xy);
+    let normal = unpack4x8snorm(textureLoad(t_g_normal, pixel, 0).x);
+
+    // Nothing lit to shadow
+    if (normal.w == 0.0) {
+        discard;
+    }
+
+    // Back to world space from the depth of the surface
+    let size = vec2<f32>(textureDimensions(t_peel_depth));
+    let ndc = vec2<f32>(clip_position.x / size.x * 2.0 - 1.0, 1.0 - clip_position.y / size.y * 2.0);
+    let world = camera.inverse_transform * vec4<f32>(ndc, textureLoad(t_peel_depth, pixel, 0).r, 1.0);
+    let world_normal = normalize(normal.xyz);
+
+    // Then to the voxel space of the chunk, where the rotation is the only part of the transform left for directions
+    let local = chunk.inverse_transform * vec4<f32>(world.xyz / world.w, 1.0);
+    let origin = local.xyz * vec3<f32>(chunk.size) + (chunk.invert_rotation * vec4<f32>(world_normal, 0.0)).xyz * SHADOW_BIAS;
+    let rotation = interleaved_gradient_noise(clip_position.xy) * TAU;
 
-        side_dist +=  vec3<f32>(
-            f32(mask.x) * delta_dist.x,
-            f32(mask.y) * delta_dist.y,
-            f32(mask.z) * delta_dist.z
-        );
+    var visibility = vec4<f32>(1.0);
+    for (var i = 0u; i < min(lighting.directional_light_count, MAX_DIRECTIONAL_LIGHTS); i++) {
+        let light = lighting.directional_lights[i];
+        // Faces turned away from the light are not lit by it anyway
+        if (light.shadow_channel < 0 || dot(world_normal, -light.direction.xyz) <= 0.0) {
+            continue;
+        }
+
+        let to_light = normalize((chunk.invert_rotation * vec4<f32>(-light.direction.xyz, 0.0)).xyz);
+        if (light.shadow_cone == 0.0) {
+            visibility[light.shadow_channel] = transmittance(origin, to_light);
+            continue;
+        }
 
-        map_pos += vec3<i32>(
-            mask.x * ray_step.x,
-            mask.y * ray_step.y,
-            mask.z * ray_step.z
-        );
+        // Spread the rays over the disk of the light with a Vogel spiral
+        let tangent = normalize(cross(to_light, select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(to_light.y) > 0.9)));
+        let bitangent = cross(to_light, tangent);
+        let samples = max(lighting.soft_shadow_samples, 1u);
+        var light_through = 0.0;
+        for (var ray_index = 0u; ray_index < samples; ray_index++) {
+            let radius = sqrt((f32(ray_index) + 0.5) / f32(samples)) * light.shadow_cone;
+            let angle = f32(ray_index) * GOLDEN_ANGLE + rotation;
+            let dir = normalize(to_light + (tangent * cos(angle) + bitangent * sin(angle)) * radius);
+            light_through += transmittance(origin, dir);
+        }
+        visibility[light.shadow_channel] = light_through / f32(samples);
     }
-    return color;
-}
\ No newline at end of file
+
+    return visibility;
+}
@@ -0,0 +1,169 @@
+// Vertex shader
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+}
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+@group(0) @binding(0)
+var t_albedo: texture_2d<f32>;
+@group(0) @binding(1)
+var t_normal: texture_2d<u32>;
+// Visibility of the shadowed lights, one channel per light
+@group(0) @binding(2)
+var t_shadow: texture_2d<f32>;
+// Written by the screen space ambient occlusion pass
+@group(0) @binding(3)
+var t_ambient_occlusion: texture_2d<f32>;
+@group(0) @binding(4)
+var t_depth: texture_2d<f32>;
+// Emitted colour scaled by the intensity as a fraction of MAX_EMISSION
+@group(0) @binding(5)
+var t_emission: texture_2d<f32>;
+
+// Same as MAX_EMISSION
+const MAX_EMISSION: f32 = 8.0;
+
+// Same as MAX_DIRECTIONAL_LIGHTS
+const MAX_DIRECTIONAL_LIGHTS: u32 = 8u;
+
+struct DirectionalLight {
+    // Direction the light travels in
+    direction: vec4<f32>,
+    radiance: vec4<f32>,
+    // Channel of t_shadow, -1 without shadows
+    shadow_channel: i32,
+    shadow_cone: f32,
+}
+
+struct LightingUniform {
+    ambient: vec4<f32>,
+    directional_light_count: u32,
+    soft_shadow_samples: u32,
+    // AMBIENT_OCCLUSION_* bits
+    ambient_occlusion: u32,
+    directional_lights: array<DirectionalLight, MAX_DIRECTIONAL_LIGHTS>,
+    screen_space_radius: f32,
+    screen_space_intensity: f32,
+}
+
+const AMBIENT_OCCLUSION_VOXEL: u32 = 1u;
+const AMBIENT_OCCLUSION_SCREEN_SPACE: u32 = 2u;
+
+@group(1) @binding(0)
+var<uniform> lighting: LightingUniform;
+
+// Point lights have both cosines at -1 and no direction
+struct LocalLight {
+    position: vec3<f32>,
+    range: f32,
+    radiance: vec3<f32>,
+    cos_inner: f32,
+    direction: vec3<f32>,
+    cos_outer: f32,
+}
+
+@group(1) @binding(1)
+var<storage, read> local_lights: array<LocalLight>;
+// Offset then count of the light indices of each tile, followed by the indices, see LightGrid::contents
+@group(1) @binding(2)
+var<storage, read> light_grid: array<u32>;
+
+// Same as LIGHT_TILE_SIZE
+const LIGHT_TILE_SIZE: u32 = 16u;
+// Local lights are not brighter than this close to a surface, in world units
+const MIN_LIGHT_DISTANCE: f32 = 0.1;
+
+struct CameraUniform {
+    position: vec3<f32>,
+    transform: mat4x4<f32>,
+    inverse_transform: mat4x4<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> camera: CameraUniform;
+
+fn world_position(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let center = vec2<f32>(pixel) + 0.5;
+    let ndc = vec2<f32>(center.x / size.x * 2.0 - 1.0, 1.0 - center.y / size.y * 2.0);
+    let world = camera.inverse_transform * vec4<f32>(ndc, textureLoad(t_depth, pixel, 0).r, 1.0);
+    return world.xyz / world.w;
+}
+
+// Inverse square falloff, windowed to reach zero at the range of the light, within the cone of spot lights
+fn local_light(light: LocalLight, position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let to_light = light.position - position;
+    let distance_squared = dot(to_light, to_light);
+    let range_squared = light.range * light.range;
+    if (distance_squared >= range_squared) {
+        return vec3<f32>(0.0);
+    }
+
+    let direction = to_light * inverseSqrt(max(distance_squared, 1e-8));
+    let fade = saturate(1.0 - pow(distance_squared / range_squared, 2.0));
+    var attenuation = fade * fade / max(distance_squared, MIN_LIGHT_DISTANCE * MIN_LIGHT_DISTANCE);
+    if (light.cos_outer > -1.0) {
+        attenuation *= smoothstep(light.cos_outer, light.cos_inner, dot(-direction, light.direction));
+    }
+
+    return light.radiance * attenuation * max(dot(normal, direction), 0.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let albedo = textureLoad(t_albedo, pixel, 0);
+    let normal = unpack4x8snorm(textureLoad(t_normal, pixel, 0).x);
+    // Emissive voxels shine whatever the lights
+    let emission = textureLoad(t_emission, pixel, 0).rgb * MAX_EMISSION;
+
+    // The background and the volumes are not lit
+    if (normal.w == 0.0) {
+        return vec4<f32>(albedo.rgb + emission, albedo.a);
+    }
+
+    let shadow = textureLoad(t_shadow, pixel, 0);
+    var occlusion = 1.0;
+    if ((lighting.ambient_occlusion & AMBIENT_OCCLUSION_VOXEL) != 0u) {
+        // Stored as 0.5 plus half the occlusion
+        occlusion *= normal.w * 2.0 - 1.0;
+    }
+    if ((lighting.ambient_occlusion & AMBIENT_OCCLUSION_SCREEN_SPACE) != 0u) {
+        occlusion *= textureLoad(t_ambient_occlusion, pixel, 0).r;
+    }
+
+    var light = lighting.ambient.rgb * occlusion;
+    for (var i = 0u; i < min(lighting.directional_light_count, MAX_DIRECTIONAL_LIGHTS); i++) {
+        let directional_light = lighting.directional_lights[i];
+        var visibility = 1.0;
+        if (directional_light.shadow_channel >= 0) {
+            visibility = shadow[directional_light.shadow_channel];
+        }
+        light += directional_light.radiance.rgb * visibility * max(dot(normalize(normal.xyz), -directional_light.direction.xyz), 0.0);
+    }
+
+    let tiles_x = (textureDimensions(t_depth).x + LIGHT_TILE_SIZE - 1u) / LIGHT_TILE_SIZE;
+    let tile = vec2<u32>(pixel) / LIGHT_TILE_SIZE;
+    let tile_index = tile.y * tiles_x + tile.x;
+    let first = light_grid[tile_index * 2u];
+    let count = light_grid[tile_index * 2u + 1u];
+    if (count > 0u) {
+        let position = world_position(pixel);
+        for (var i = 0u; i < count; i++) {
+            light += local_light(local_lights[light_grid[first + i]], position, normalize(normal.xyz));
+        }
+    }
+
+    return vec4<f32>(albedo.rgb * light + emission, albedo.a);
+}
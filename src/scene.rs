@@ -1,42 +1,126 @@
-use std::collections::{HashMap, HashSet, LinkedList};
+use std::{collections::{HashMap, HashSet, LinkedList}, path::Path};
 
 use camera::{Camera, CameraData};
+use lighting::{Lighting, LightingData};
 use chunk::{chunk_content::ChunkContentLoadingError, Chunk, UnloadedChunk};
-use glam::Vec3;
+use glam::{IVec3, UVec3, Vec3};
 use script::Script;
 use sdl2::{keyboard::Scancode, EventPump};
 use uuid::Uuid;
 use wgpu::{core::device::queue, CommandEncoder, Device, Queue};
 
-use crate::{render::{chunk_renderer::{self, ChunkRenderer}, g_buffer::GBuffer}};
+use crate::{render::{bloom_renderer::BloomRenderer, chunk_renderer::{self, ChunkRenderer}, g_buffer::GBuffer, lighting_renderer::LightingRenderer, picker::{Pick, PickTexel, NO_OBJECT}}};
 
 pub mod script;
 pub mod chunk;
 pub mod camera;
+pub mod lighting;
 
 pub const VOXEL_SIZE: f32 = 0.1; 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub chunk: Uuid,
+    pub voxel: UVec3,
+    //Outward normal of the face hit, in the space of the chunk, see VoxelHit::normal
+    pub normal: IVec3,
+    //In world units from the origin of the ray
+    pub distance: f32,
+}
+
 pub struct Scene {
     camera: Camera,
+    lighting: Lighting,
     chunks: HashMap<Uuid, Chunk>,
-    scripts: HashMap<(), Box<dyn Script>>
+    scripts: HashMap<(), Box<dyn Script>>,
+    //Object id given to the next chunk added, ids are never reused so that a late pick cannot name another chunk
+    next_object_id: u32,
 }
 
 impl Scene {
-    pub fn add_chunk(&mut self, chunk: Chunk) {
+    pub fn add_chunk(&mut self, mut chunk: Chunk) {
+        chunk.set_object_id(self.next_object_id);
+        self.next_object_id += 1;
         self.chunks.insert(Uuid::new_v4(), chunk);
     }
 
+    //Chunk rendered with an object id, see Chunk::object_id
+    pub fn chunk_by_object_id(&self, object_id: u32) -> Option<Uuid> {
+        self.chunks.iter()
+            .find(|(_, chunk)| chunk.object_id() == object_id && object_id != NO_OBJECT)
+            .map(|(uuid, _)| *uuid)
+    }
+
     pub fn add_script(&mut self, script: Box<dyn Script>) {
         self.scripts.insert((), script);
     }
 
-    pub fn render(&self, chunk_renderer: &ChunkRenderer, device: &Device, g_buffer: &GBuffer, encoder: &mut CommandEncoder) {
-        for (_uuid, chunk) in self.chunks.iter() {
-            chunk_renderer.render(encoder, device, g_buffer, chunk, &self.camera);
+    pub fn chunks(&self) -> &HashMap<Uuid, Chunk> {
+        &self.chunks
+    }
+
+    pub fn chunks_mut(&mut self) -> &mut HashMap<Uuid, Chunk> {
+        &mut self.chunks
+    }
+
+    //Closest voxel hit by a ray in world space, up to max_distance away
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RaycastHit> {
+        self.chunks.iter()
+            .filter_map(|(uuid, chunk)| chunk.raycast(origin, direction, max_distance).map(|hit| RaycastHit {
+                chunk: *uuid,
+                voxel: hit.voxel,
+                normal: hit.normal,
+                distance: hit.distance,
+            }))
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    pub fn resolve_pick(&self, texel: PickTexel) -> Option<Pick> {
+        self.chunk_by_object_id(texel.object_id).map(|chunk| Pick {
+            chunk,
+            voxel: texel.voxel,
+        })
+    }
+
+    //Uploads the voxels edited since the last call, to be called once per frame before rendering
+    pub fn flush_edits(&mut self, device: &Device, queue: &Queue) {
+        for chunk in self.chunks.values_mut() {
+            chunk.flush_edits(device, queue);
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(&mut self, chunk_renderer: &ChunkRenderer, lighting_renderer: &LightingRenderer, bloom_renderer: &BloomRenderer, device: &Device, queue: &Queue, g_buffer: &GBuffer, encoder: &mut CommandEncoder) {
+        chunk_renderer.render(encoder, device, g_buffer, self.chunks.values(), &self.camera);
+        chunk_renderer.render_shadows(encoder, device, g_buffer, self.chunks.values(), &self.camera, &self.lighting);
+        lighting_renderer.render(encoder, device, queue, g_buffer, &mut self.lighting, &self.camera);
+        bloom_renderer.render(encoder, device, g_buffer, &self.lighting);
+    }
+
+    //Fills the pick texture read back by the picker, to be called after render
+    pub fn render_pick(&self, chunk_renderer: &ChunkRenderer, device: &Device, g_buffer: &GBuffer, encoder: &mut CommandEncoder) {
+        chunk_renderer.render_pick(encoder, device, g_buffer, self.chunks.values(), &self.camera);
+    }
+
+    pub fn lighting_data(&self) -> &LightingData {
+        &self.lighting.data
+    }
+
+    pub fn set_lighting_data(&mut self, queue: &Queue, lighting_data: LightingData) {
+        self.lighting.data = lighting_data;
+        self.lighting.update_uniform_buffer(queue);
+    }
+
+    pub fn set_aspect_ratio(&mut self, queue: &Queue, aspect_ratio: f32) {
+        self.camera.aspect_ratio = aspect_ratio;
+        self.camera.update_uniform_buffer(queue);
+    }
+
+    pub fn set_camera_data(&mut self, queue: &Queue, camera_data: CameraData) {
+        self.camera.data = camera_data;
+        self.camera.update_uniform_buffer(queue);
+    }
+
     pub fn update(& mut self, queue: &Queue, delta_time: f32, event_pump: &EventPump) {
         for script in self.scripts.values_mut().into_iter() {
             script.update(&mut self.chunks, &mut self.camera, delta_time, event_pump, queue);
@@ -48,6 +132,7 @@ impl Scene {
 pub struct UnloadedScene {
     chunks: HashMap<Uuid, UnloadedChunk>,
     camera_data: CameraData,
+    lighting_data: LightingData,
     scripts: HashMap<(), Box<dyn Script>>
 }
 
@@ -56,20 +141,27 @@ impl UnloadedScene {
         Self{
             chunks: HashMap::new(),
             camera_data: camera_data,
+            lighting_data: LightingData::default(),
             scripts: HashMap::new()
         }
     }
 
     pub fn load(self, device: &Device, queue: &Queue, aspect_ratio:f32) -> Result<Scene, ChunkContentLoadingError> {
         let mut chunks = HashMap::<Uuid, Chunk>::new();
+        let mut next_object_id = NO_OBJECT + 1;
         for (uuid, chunk) in self.chunks {
-            chunks.insert(uuid, chunk.load(device, queue)?);
+            let mut chunk = chunk.load(device, queue)?;
+            chunk.set_object_id(next_object_id);
+            next_object_id += 1;
+            chunks.insert(uuid, chunk);
         }
 
         Ok(Scene {
             chunks,
             camera: Camera::new(device, self.camera_data, aspect_ratio),
-            scripts: self.scripts
+            lighting: Lighting::new(device, self.lighting_data),
+            scripts: self.scripts,
+            next_object_id,
         })
     }
 
@@ -77,7 +169,19 @@ impl UnloadedScene {
         self.chunks.insert(Uuid::new_v4(), chunk);
     }
 
+    //Adds every model of a MagicaVoxel file, placed as in the MagicaVoxel scene
+    pub fn add_vox_file(&mut self, path: &Path) -> Result<(), ChunkContentLoadingError> {
+        for chunk in chunk::magica_voxel::read_vox_file(path)? {
+            self.add_chunk(chunk);
+        }
+        Ok(())
+    }
+
     pub fn add_script(&mut self, script: Box<dyn Script>) {
         self.scripts.insert((), script);
     }
+
+    pub fn set_lighting_data(&mut self, lighting_data: LightingData) {
+        self.lighting_data = lighting_data;
+    }
 }
\ No newline at end of file
@@ -71,34 +71,103 @@ impl Camera {
     pub fn move_towards(&mut self, move_direction: Vec3) {
         self.data.position +=  Vec4::new(move_direction.x, move_direction.y, move_direction.z, 1.0).xyz();
     }
+
+    //Moves the camera in its own space: +Z is forward, +X is right and +Y is up
+    pub fn move_relative(&mut self, move_direction: Vec3) {
+        self.move_towards(self.data.rotation * move_direction);
+    }
+
+    pub fn rotate_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        self.data.rotate_yaw_pitch(yaw, pitch);
+    }
+
+    //From world space to clip space
+    pub fn transform(&self) -> Mat4 {
+        self.data.transform(self.aspect_ratio)
+    }
+
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        self.data.look_at(target, up);
+    }
 }
 
+//Pitch is kept slightly below 90° so the camera never flips over when looking straight up or down
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.001;
+
 #[derive(Debug, Copy, Clone)]
 
 pub struct CameraData {
     pub position: Vec3,
+    pub rotation: Quat,
     pub near: f32,
     pub far: f32,
     pub fov: f32,
 }
 
+impl CameraData {
+    pub fn forward(&self) -> Vec3 {
+        self.rotation * Vec3::Z
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.rotation * Vec3::X
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.rotation * Vec3::Y
+    }
+
+    //Yaw turns around the world Y axis, pitch around the camera X axis and roll around the camera Z axis
+    pub fn yaw_pitch_roll(&self) -> (f32, f32, f32) {
+        self.rotation.to_euler(EulerRot::YXZ)
+    }
+
+    pub fn set_yaw_pitch_roll(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        self.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
+    }
+
+    pub fn rotate_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        let (current_yaw, current_pitch, roll) = self.yaw_pitch_roll();
+        self.set_yaw_pitch_roll(current_yaw + yaw, (current_pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH), roll);
+    }
+
+    //From world space to clip space
+    pub fn transform(&self, aspect_ratio: f32) -> Mat4 {
+        let translation = Mat4::from_translation(-self.position);
+        let rotation = Mat4::from_quat(self.rotation.inverse());
+        let perspective = Mat4::perspective_lh(self.fov, aspect_ratio, self.near, self.far);
+
+        OPENGL_TO_WGPU_MATRIX * perspective * rotation * translation
+    }
+
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        //The orientation is undefined when looking along the up vector, keep the previous one
+        if (target - self.position).cross(up).length_squared() == 0.0 {
+            return;
+        }
+
+        let view = Mat4::look_at_lh(self.position, target, up);
+        self.rotation = Quat::from_mat4(&view).inverse().normalize();
+    }
+}
+
 #[repr(C, align(16))]
 #[derive(Debug, Copy, Clone)]
 pub struct CameraUniform {
     pub position: Vec3,
     pub transform: Mat4,
+    //From clip space back to world space, to find the position of G-buffer pixels from their depth
+    pub inverse_transform: Mat4,
 }
 
 impl CameraUniform {
     fn from_data(data: CameraData, aspect_ratio: f32) -> Self {
-        let translation = Mat4::from_translation(-data.position);
-        let perspective = Mat4::perspective_lh(data.fov, aspect_ratio, data.near, data.far);
-
-        let transform = OPENGL_TO_WGPU_MATRIX * perspective * translation;
+        let transform = data.transform(aspect_ratio);
 
         CameraUniform {
             position: data.position,
             transform,
+            inverse_transform: transform.inverse(),
         }
     }
 }
@@ -1,10 +1,26 @@
+pub mod brickmap;
 pub mod chunk_content;
+pub mod chunk_manifest;
+pub mod distance_field;
+pub mod edit_journal;
+pub mod magica_voxel;
+pub mod transfer_function;
+pub mod traversal;
 
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
-use chunk_content::{ChunkContent, ChunkContentLoadingError};
+use brickmap::Brickmap;
+use edit_journal::EditJournal;
+use chunk_manifest::ChunkManifest;
+use chunk_content::{AlbedoStorage, ChunkContent, ChunkContentLoadingError, RawChunkContent, VoxelComponent};
+use transfer_function::TransferFunction;
+use traversal::VoxelHit;
+use crate::render::picker::NO_OBJECT;
 use glam::{Mat4, Quat, UVec3, Vec3};
-use wgpu::{ core::device::queue, util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, Sampler};
+use wgpu::{ core::device::queue, util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, Sampler, Texture, TextureView};
+
+//Distance between two density samples of RenderMode::Volume, in voxels
+pub const DEFAULT_VOLUME_STEP: f32 = 0.5;
 
 pub struct Chunk {
     pub data: ChunkData,
@@ -14,12 +30,22 @@ pub struct Chunk {
     buffer: Buffer,
 
     sampler: Sampler,
+
+    //Written in the pick attachment of the G-buffer, assigned by the scene
+    object_id: u32,
+
+    render_mode: RenderMode,
+
+    //Only used by RenderMode::Volume, the density layer is sampled trilinearly and the transfer function linearly
+    volume_sampler: Sampler,
+    transfer_function: TransferFunction,
+    transfer_function_texture: Texture,
+    transfer_function_view: TextureView,
+    volume_step: f32,
 }
 
 impl Chunk {
-    fn from_file(device: &Device, queue: &Queue, data: ChunkData, content_path: &Path) -> Result<Chunk, ChunkContentLoadingError> {
-        let chunk_content = ChunkContent::from_chunk_file(device, queue, content_path)?;
-        
+    fn from_content(device: &Device, queue: &Queue, data: ChunkData, chunk_content: ChunkContent, render_mode: RenderMode, transfer_function: TransferFunction, volume_step: f32) -> Chunk {
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -32,20 +58,166 @@ impl Chunk {
 
         let buffer = device.create_buffer_init(&BufferInitDescriptor{
             label: Some("Chunk buffer"),
-            contents: unsafe { crate::memory::any_as_u8_slice(&ChunkUniform::from_data_and_dimensions(data, chunk_content.dimensions)) },
+            contents: unsafe { crate::memory::any_as_u8_slice(&ChunkUniform::from_data_and_content(data, &chunk_content, render_mode, volume_step)) },
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
-        Ok(Self {
+        let volume_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let (transfer_function_texture, transfer_function_view) = transfer_function.create_texture(device, queue);
+
+        Self {
             data,
             buffer,
             sampler,
-            chunk_content
+            chunk_content,
+            object_id: NO_OBJECT,
+            render_mode,
+            volume_sampler,
+            transfer_function,
+            transfer_function_texture,
+            transfer_function_view,
+            volume_step,
+        }
+    }
+
+    pub fn object_id(&self) -> u32 {
+        self.object_id
+    }
+
+    pub(crate) fn set_object_id(&mut self, object_id: u32) {
+        self.object_id = object_id;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, queue: &Queue, render_mode: RenderMode) {
+        self.render_mode = render_mode;
+        self.update_uniform_buffer(queue);
+    }
+
+    pub fn transfer_function(&self) -> &TransferFunction {
+        &self.transfer_function
+    }
+
+    pub fn set_transfer_function(&mut self, queue: &Queue, transfer_function: TransferFunction) {
+        transfer_function.write_texture(queue, &self.transfer_function_texture);
+        self.transfer_function = transfer_function;
+    }
+
+    pub fn volume_step(&self) -> f32 {
+        self.volume_step
+    }
+
+    //In voxels, smaller steps are slower but resolve thinner features of the volume
+    pub fn set_volume_step(&mut self, queue: &Queue, volume_step: f32) {
+        self.volume_step = volume_step;
+        self.update_uniform_buffer(queue);
+    }
+
+    //Center of the box of the chunk in world space, accumulating chunks are drawn from the furthest center to the closest
+    pub fn center(&self) -> Vec3 {
+        let manifest = &self.chunk_content.manifest;
+        let local = (manifest.dimensions.as_vec3() / 2.0 - manifest.pivot) * manifest.voxel_size;
+        self.data.position + self.data.rotation * local
+    }
+
+    pub fn content(&self) -> &ChunkContent {
+        &self.chunk_content
+    }
+
+    //RGBA albedo of a voxel, None outside of the chunk
+    pub fn get_voxel(&self, position: UVec3) -> Option<[u8; 4]> {
+        self.chunk_content.albedo_at(position)
+    }
+
+    //Edits only change the CPU copy of the chunk until the scene flushes them, once per frame
+    pub fn set_voxel(&mut self, position: UVec3, color: [u8; 4]) -> bool {
+        self.chunk_content.set_voxel(position, color)
+    }
+
+    //From min included to max excluded, in voxels
+    pub fn fill_box(&mut self, min: UVec3, max: UVec3, color: [u8; 4]) {
+        self.chunk_content.fill_box(min, max, color)
+    }
+
+    //Center and radius in voxels
+    pub fn fill_sphere(&mut self, center: Vec3, radius: f32, color: [u8; 4]) {
+        self.chunk_content.fill_sphere(center, radius, color)
+    }
+
+    //Casts a ray in world space against the voxels of the chunk, considered solid like the shader does.
+    //The normal of the hit is in the space of the chunk, its distance in world units.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<VoxelHit> {
+        let manifest = &self.chunk_content.manifest;
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        let inverse_rotation = self.data.rotation.inverse();
+        let local_origin = inverse_rotation * (origin - self.data.position) / manifest.voxel_size + manifest.pivot;
+        let local_direction = inverse_rotation * direction;
+
+        let content = &self.chunk_content;
+        let hit = traversal::raycast(
+            content.dimensions,
+            |position| content.is_solid(position),
+            //The distance field is only lowered around new solid voxels when the edits are flushed
+            content.distance_field.as_ref().filter(|_| !content.is_dirty()),
+            local_origin,
+            local_direction,
+            max_distance / manifest.voxel_size,
+        )?;
+
+        Some(VoxelHit {
+            distance: hit.distance * manifest.voxel_size,
+            ..hit
         })
     }
 
+    pub fn begin_transaction(&mut self) {
+        self.chunk_content.begin_transaction()
+    }
+
+    pub fn commit_transaction(&mut self) -> bool {
+        self.chunk_content.commit_transaction()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        self.chunk_content.undo()
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.chunk_content.redo()
+    }
+
+    pub fn journal(&self) -> &EditJournal {
+        &self.chunk_content.journal
+    }
+
+    pub fn journal_mut(&mut self) -> &mut EditJournal {
+        &mut self.chunk_content.journal
+    }
+
+    pub fn flush_edits(&mut self, device: &Device, queue: &Queue) {
+        if self.chunk_content.flush(device, queue) {
+            self.update_uniform_buffer(queue);
+        }
+    }
+
     pub fn update_uniform_buffer(&mut self, queue: &Queue) {
-        queue.write_buffer(&self.buffer, 0, unsafe { crate::memory::any_as_u8_slice(&ChunkUniform::from_data_and_dimensions(self.data, self.chunk_content.dimensions)) })
+        queue.write_buffer(&self.buffer, 0, unsafe { crate::memory::any_as_u8_slice(&ChunkUniform::from_data_and_content(self.data, &self.chunk_content, self.render_mode, self.volume_step)) })
     }
 
     pub fn generate_bind_group_layout(device: &Device) -> BindGroupLayout {
@@ -77,6 +249,77 @@ impl Chunk {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
                     count: None,
                 },
+                Self::layer_layout_entry(VoxelComponent::Normal),
+                Self::layer_layout_entry(VoxelComponent::Material),
+                Self::layer_layout_entry(VoxelComponent::Emission),
+                Self::layer_layout_entry(VoxelComponent::Roughness),
+                Self::layer_layout_entry(VoxelComponent::Density),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7, //Albedo index, R8Uint or R16Uint
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8, //Palette
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9, //Brick grid
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10, //Brick atlas
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11, //Distance field
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Uint,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13, //Volume sampler, binding 12 is the density layer
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14, //Transfer function
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
             ],
             label: Some("chunk_renderer_bind_group_layout"),
         })
@@ -99,12 +342,108 @@ impl Chunk {
                     wgpu::BindGroupEntry {
                         binding: 2,
                         resource: wgpu::BindingResource::Sampler(&self.sampler),
-                    }
+                    },
+                    self.layer_bind_group_entry(VoxelComponent::Normal),
+                    self.layer_bind_group_entry(VoxelComponent::Material),
+                    self.layer_bind_group_entry(VoxelComponent::Emission),
+                    self.layer_bind_group_entry(VoxelComponent::Roughness),
+                    self.layer_bind_group_entry(VoxelComponent::Density),
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: wgpu::BindingResource::TextureView(&self.chunk_content.albedo_index_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: wgpu::BindingResource::Buffer(self.chunk_content.palette_buffer.as_entire_buffer_binding()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: wgpu::BindingResource::TextureView(&self.chunk_content.brick_grid_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 10,
+                        resource: wgpu::BindingResource::TextureView(&self.chunk_content.brick_atlas_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 11,
+                        resource: wgpu::BindingResource::TextureView(&self.chunk_content.distance_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 13,
+                        resource: wgpu::BindingResource::Sampler(&self.volume_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 14,
+                        resource: wgpu::BindingResource::TextureView(&self.transfer_function_view),
+                    },
                 ],
                 label: Some("chunk_render_bind_group"),
             }
         )
     }
+
+    //Albedo keeps bindings 1 and 2, the other layers follow from binding 3 in the VOXEL_COMPONENTS order.
+    //Density came after the albedo storages and the distance field, it takes binding 12.
+    fn layer_binding(component: VoxelComponent) -> u32 {
+        match component {
+            VoxelComponent::Density => 12,
+            component => component as u32 + 2,
+        }
+    }
+
+    //Layers are read with textureLoad, material ids are integers and cannot be sampled as floats.
+    //Density is sampled trilinearly by RenderMode::Volume.
+    fn layer_layout_entry(component: VoxelComponent) -> wgpu::BindGroupLayoutEntry {
+        let sample_type = match component {
+            VoxelComponent::Material => wgpu::TextureSampleType::Uint,
+            VoxelComponent::Density => wgpu::TextureSampleType::Float { filterable: true },
+            _ => wgpu::TextureSampleType::Float { filterable: false },
+        };
+
+        wgpu::BindGroupLayoutEntry {
+            binding: Self::layer_binding(component),
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D3,
+                sample_type,
+            },
+            count: None,
+        }
+    }
+
+    fn layer_bind_group_entry(&self, component: VoxelComponent) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding: Self::layer_binding(component),
+            resource: wgpu::BindingResource::TextureView(self.chunk_content.layer_view(component)),
+        }
+    }
+}
+
+//How the ray marcher turns the voxels along a ray into the colour of a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    //The first solid voxel hit, whatever its alpha
+    #[default]
+    FirstHit,
+    //Solid voxels composited front to back by their alpha until they are opaque, for glass, water or smoke.
+    //Overlapping accumulating chunks are composited voxel by voxel, see ChunkRenderer::render.
+    Accumulate,
+    //The density layer sampled every volume step along the ray and mapped through the transfer function of the chunk,
+    //for scans and simulation output. The albedo is ignored. Sorted with the accumulating chunks.
+    Volume,
+}
+
+impl RenderMode {
+    //Drawn after the opaque chunks and blended over them
+    pub fn is_translucent(self) -> bool {
+        self != Self::FirstHit
+    }
+
+    //Value of the render_mode field of the chunk uniform
+    pub fn uniform_value(self) -> u32 {
+        self as u32
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -118,31 +457,160 @@ pub struct ChunkData {
 #[derive(Debug, Copy, Clone)]
 pub struct ChunkUniform {
     pub size: UVec3,
+    //One bit per layer present in the chunk, see VoxelComponent::mask
+    pub layers: u32,
     pub transform: Mat4,
     pub invert_rotation: Mat4,
+    //From world space to the [0, 1] cube of the chunk
+    pub inverse_transform: Mat4,
+    //See AlbedoStorage::uniform_value
+    pub albedo_storage: u32,
+    //CHUNK_FLAG_* bits
+    pub flags: u32,
+    //See ChunkManifest::alpha_threshold
+    pub alpha_threshold: u32,
+    //See RenderMode::uniform_value
+    pub render_mode: u32,
+    //In voxels
+    pub volume_step: f32,
 }
 
+pub const CHUNK_FLAG_DISTANCE_FIELD: u32 = 1;
+
 impl ChunkUniform {
-    fn from_data_and_dimensions(data: ChunkData, dimensions: UVec3) -> Self {
-        let scale = dimensions.as_vec3() * super::VOXEL_SIZE;
-        let transform =Mat4::from_scale_rotation_translation(scale, data.rotation, data.position); 
+    fn from_data_and_content(data: ChunkData, chunk_content: &ChunkContent, render_mode: RenderMode, volume_step: f32) -> Self {
+        let manifest = &chunk_content.manifest;
+        let scale = manifest.dimensions.as_vec3() * manifest.voxel_size;
+        let pivot = Mat4::from_translation(-manifest.pivot * manifest.voxel_size);
+        let transform = Mat4::from_rotation_translation(data.rotation, data.position) * pivot * Mat4::from_scale(scale); 
         ChunkUniform {
-            size: dimensions,
+            size: manifest.dimensions,
+            layers: manifest.components.iter()
+                .filter_map(|name| VoxelComponent::from_name(name))
+                .fold(0, |layers, component| layers | component.mask()),
             transform,
-            invert_rotation: Mat4::from_quat(-data.rotation),
+            invert_rotation: Mat4::from_quat(data.rotation.inverse()),
+            inverse_transform: transform.inverse(),
+            albedo_storage: chunk_content.albedo_storage.uniform_value(),
+            flags: match chunk_content.distance_field {
+                Some(_) => CHUNK_FLAG_DISTANCE_FIELD,
+                None => 0,
+            },
+            alpha_threshold: manifest.alpha_threshold as u32,
+            render_mode: render_mode.uniform_value(),
+            volume_step,
         }
     }
 
 }
 
 
+pub enum UnloadedChunkContent {
+    File(PathBuf),
+    Raw(RawChunkContent),
+    //Always loaded with AlbedoStorage::Brickmap, the albedo stays sparse on the CPU too
+    Brickmap(Brickmap),
+}
+
 pub struct UnloadedChunk {
-    pub content_path: PathBuf,
-    pub chunk_data: ChunkData
+    pub content: UnloadedChunkContent,
+    pub chunk_data: ChunkData,
+    pub albedo_storage: AlbedoStorage,
+    //Replaces the alpha threshold of the manifest when set
+    pub alpha_threshold: Option<u8>,
+    pub render_mode: RenderMode,
+    pub transfer_function: TransferFunction,
+    pub volume_step: f32,
 }
 
 impl UnloadedChunk {
+    pub fn from_file(content_path: PathBuf, chunk_data: ChunkData) -> Self {
+        Self {
+            content: UnloadedChunkContent::File(content_path),
+            chunk_data,
+            albedo_storage: AlbedoStorage::Rgba,
+            alpha_threshold: None,
+            render_mode: RenderMode::FirstHit,
+            transfer_function: TransferFunction::default(),
+            volume_step: DEFAULT_VOLUME_STEP,
+        }
+    }
+
+    //RGBA8 albedo, x varies fastest then y then z
+    pub fn from_raw_data(albedo: Vec<u8>, dimensions: UVec3, chunk_data: ChunkData) -> Self {
+        Self::from_raw_content(RawChunkContent::new(albedo, dimensions), chunk_data)
+    }
+
+    pub fn from_raw_content(raw: RawChunkContent, chunk_data: ChunkData) -> Self {
+        Self {
+            content: UnloadedChunkContent::Raw(raw),
+            chunk_data,
+            albedo_storage: AlbedoStorage::Rgba,
+            alpha_threshold: None,
+            render_mode: RenderMode::FirstHit,
+            transfer_function: TransferFunction::default(),
+            volume_step: DEFAULT_VOLUME_STEP,
+        }
+    }
+
+    //The brickmap is never expanded, neither on the CPU nor on the GPU
+    pub fn from_brickmap(brickmap: Brickmap, chunk_data: ChunkData) -> Self {
+        Self {
+            content: UnloadedChunkContent::Brickmap(brickmap),
+            chunk_data,
+            albedo_storage: AlbedoStorage::Brickmap,
+            alpha_threshold: None,
+            render_mode: RenderMode::FirstHit,
+            transfer_function: TransferFunction::default(),
+            volume_step: DEFAULT_VOLUME_STEP,
+        }
+    }
+
+    pub fn with_albedo_storage(mut self, albedo_storage: AlbedoStorage) -> Self {
+        self.albedo_storage = albedo_storage;
+        self
+    }
+
+    pub fn with_alpha_threshold(mut self, alpha_threshold: u8) -> Self {
+        self.alpha_threshold = Some(alpha_threshold);
+        self
+    }
+
+    pub fn with_render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.render_mode = render_mode;
+        self
+    }
+
+    pub fn with_transfer_function(mut self, transfer_function: TransferFunction) -> Self {
+        self.transfer_function = transfer_function;
+        self
+    }
+
+    pub fn with_volume_step(mut self, volume_step: f32) -> Self {
+        self.volume_step = volume_step;
+        self
+    }
+
     pub fn load(self, device: &Device, queue: &Queue) -> Result<Chunk, ChunkContentLoadingError> {
-        Chunk::from_file(device, queue, self.chunk_data, &self.content_path)
+        let mut raw = match self.content {
+            UnloadedChunkContent::File(content_path) => ChunkContent::read_chunk_file(&content_path)?,
+            UnloadedChunkContent::Raw(raw) => raw,
+            UnloadedChunkContent::Brickmap(brickmap) => {
+                let mut manifest = ChunkManifest::new(brickmap.dimensions);
+                if let Some(alpha_threshold) = self.alpha_threshold {
+                    manifest.alpha_threshold = alpha_threshold;
+                }
+
+                let chunk_content = ChunkContent::from_brickmap(device, queue, brickmap, manifest)?;
+                return Ok(Chunk::from_content(device, queue, self.chunk_data, chunk_content, self.render_mode, self.transfer_function, self.volume_step));
+            },
+        };
+
+        if let Some(alpha_threshold) = self.alpha_threshold {
+            raw.manifest.alpha_threshold = alpha_threshold;
+        }
+
+        let chunk_content = ChunkContent::from_raw_content_with_storage(device, queue, raw, self.albedo_storage)?;
+        Ok(Chunk::from_content(device, queue, self.chunk_data, chunk_content, self.render_mode, self.transfer_function, self.volume_step))
     }
 }
\ No newline at end of file
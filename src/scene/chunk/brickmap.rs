@@ -0,0 +1,257 @@
+use glam::UVec3;
+
+use super::chunk_content::{ChunkContent, ChunkContentLoadingError, RawChunkContent};
+
+//Edge of a brick in voxels
+pub const BRICK_SIZE: u32 = 8;
+pub const BRICK_VOXELS: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE) as usize;
+//Value of the grid cells without a brick
+pub const EMPTY_BRICK: u32 = 0;
+//256 bricks of 8 voxels fill the 2048 texels guaranteed for each axis of a 3D texture
+pub const ATLAS_BRICKS_PER_AXIS: u32 = 256;
+
+const BRICK_BYTES: usize = BRICK_VOXELS * 4;
+
+//Sparse albedo: a grid with one cell per 8x8x8 brick of the chunk, only the bricks with at least one non zero byte are stored.
+//Voxels outside the chunk in the bricks of its last row, column or layer are zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Brickmap {
+    pub dimensions: UVec3,
+    pub grid_dimensions: UVec3,
+    //EMPTY_BRICK or the slot of the brick plus one, x varies fastest then y then z
+    pub grid: Vec<u32>,
+    //RGBA8 voxels of every slot one after the other, x varies fastest then y then z in each brick
+    pub bricks: Vec<u8>,
+    //Zeroed slots of bricks that became empty, reused before adding new slots
+    pub free_slots: Vec<u32>,
+}
+
+impl Brickmap {
+    //A brickmap without any brick, large volumes can be built this way without ever holding their dense albedo
+    pub fn new(dimensions: UVec3) -> Result<Self, ChunkContentLoadingError> {
+        //Rounded up without overflowing near u32::MAX
+        let grid_dimensions = dimensions / BRICK_SIZE + (dimensions % BRICK_SIZE).min(UVec3::ONE);
+
+        match voxel_count(dimensions).and(voxel_count(grid_dimensions)) {
+            Some(cell_count) if cell_count != 0 => Ok(Self {
+                dimensions,
+                grid_dimensions,
+                grid: vec![EMPTY_BRICK; cell_count],
+                bricks: Vec::new(),
+                free_slots: Vec::new(),
+            }),
+            _ => Err(ChunkContentLoadingError::InvalidDimensions),
+        }
+    }
+
+    //RGBA8 albedo, x varies fastest then y then z
+    pub fn from_albedo(albedo: &[u8], dimensions: UVec3) -> Result<Self, ChunkContentLoadingError> {
+        if voxel_count(dimensions).and_then(|count| count.checked_mul(4)) != Some(albedo.len()) {
+            return Err(ChunkContentLoadingError::InvalidDimensions);
+        }
+
+        let mut brickmap = Self::new(dimensions)?;
+        let mut brick = vec![0u8; BRICK_BYTES];
+
+        for brick_z in 0..brickmap.grid_dimensions.z {
+            for brick_y in 0..brickmap.grid_dimensions.y {
+                for brick_x in 0..brickmap.grid_dimensions.x {
+                    let cell = UVec3::new(brick_x, brick_y, brick_z);
+                    let origin = cell * BRICK_SIZE;
+                    brick.fill(0);
+
+                    let size = (dimensions - origin).min(UVec3::splat(BRICK_SIZE));
+                    for z in 0..size.z {
+                        for y in 0..size.y {
+                            let source = dense_index(origin + UVec3::new(0, y, z), dimensions) * 4;
+                            let destination = ((y + z * BRICK_SIZE) * BRICK_SIZE * 4) as usize;
+                            let row = (size.x * 4) as usize;
+                            brick[destination..destination + row].copy_from_slice(&albedo[source..source + row]);
+                        }
+                    }
+
+                    if brick.iter().any(|byte| *byte != 0) {
+                        brickmap.bricks.extend_from_slice(&brick);
+                        let cell_index = brickmap.cell_index(cell);
+                        brickmap.grid[cell_index] = brickmap.slot_count() as u32;
+                    }
+                }
+            }
+        }
+
+        Ok(brickmap)
+    }
+
+    pub fn from_chunk_content(chunk_content: &ChunkContent) -> Self {
+        //The albedo of a loaded chunk has already been checked against its dimensions
+        match chunk_content.albedo.brickmap() {
+            Some(brickmap) => brickmap.clone(),
+            None => Self::from_albedo(&chunk_content.albedo.to_dense(), chunk_content.dimensions).unwrap(),
+        }
+    }
+
+    //Bricks actually stored, freed slots excluded
+    pub fn brick_count(&self) -> usize {
+        self.slot_count() - self.free_slots.len()
+    }
+
+    //Slots in use or free, the atlas holds this many bricks
+    pub fn slot_count(&self) -> usize {
+        self.bricks.len() / BRICK_BYTES
+    }
+
+    //Index of a cell in the grid, x varies fastest then y then z
+    pub fn cell_index(&self, cell: UVec3) -> usize {
+        dense_index(cell, self.grid_dimensions)
+    }
+
+    //EMPTY_BRICK or the slot of the brick plus one
+    pub fn cell(&self, cell: UVec3) -> u32 {
+        self.grid[self.cell_index(cell)]
+    }
+
+    //RGBA8 voxels of the brick stored in a slot
+    pub fn brick(&self, slot: u32) -> &[u8] {
+        &self.bricks[slot as usize * BRICK_BYTES..(slot as usize + 1) * BRICK_BYTES]
+    }
+
+    pub fn voxel(&self, position: UVec3) -> Option<[u8; 4]> {
+        if position.x >= self.dimensions.x || position.y >= self.dimensions.y || position.z >= self.dimensions.z {
+            return None;
+        }
+
+        let brick = self.cell(position / BRICK_SIZE);
+        if brick == EMPTY_BRICK {
+            return Some([0; 4]);
+        }
+
+        let index = brick_voxel_index(brick - 1, position % BRICK_SIZE);
+        Some([self.bricks[index], self.bricks[index + 1], self.bricks[index + 2], self.bricks[index + 3]])
+    }
+
+    //Adds a brick to empty cells receiving a non zero colour, returns false outside of the chunk.
+    //Bricks left empty keep their slot until free_empty_bricks is called on them.
+    pub fn set_voxel(&mut self, position: UVec3, color: [u8; 4]) -> bool {
+        if position.cmpge(self.dimensions).any() {
+            return false;
+        }
+
+        let cell_index = self.cell_index(position / BRICK_SIZE);
+        let brick = match self.grid[cell_index] {
+            EMPTY_BRICK if color == [0; 4] => return true,
+            EMPTY_BRICK => {
+                let brick = self.allocate_slot() + 1;
+                self.grid[cell_index] = brick;
+                brick
+            },
+            brick => brick,
+        };
+
+        let index = brick_voxel_index(brick - 1, position % BRICK_SIZE);
+        self.bricks[index..index + 4].copy_from_slice(&color);
+        true
+    }
+
+    //Frees the bricks without any non zero byte among the cells from min included to max excluded, clipped to the grid
+    pub fn free_empty_bricks(&mut self, min_cell: UVec3, max_cell: UVec3) {
+        let max_cell = max_cell.min(self.grid_dimensions);
+        for z in min_cell.z..max_cell.z {
+            for y in min_cell.y..max_cell.y {
+                for x in min_cell.x..max_cell.x {
+                    let cell_index = self.cell_index(UVec3::new(x, y, z));
+                    let brick = self.grid[cell_index];
+                    if brick != EMPTY_BRICK && self.brick(brick - 1).iter().all(|byte| *byte == 0) {
+                        self.grid[cell_index] = EMPTY_BRICK;
+                        self.free_slots.push(brick - 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn allocate_slot(&mut self) -> u32 {
+        match self.free_slots.pop() {
+            Some(slot) => slot,
+            None => {
+                self.bricks.resize(self.bricks.len() + BRICK_BYTES, 0);
+                self.slot_count() as u32 - 1
+            },
+        }
+    }
+
+    //Dense RGBA8 albedo, x varies fastest then y then z
+    pub fn to_albedo(&self) -> Vec<u8> {
+        let mut albedo = Vec::<u8>::with_capacity(voxel_count(self.dimensions).unwrap_or(0) * 4);
+        for z in 0..self.dimensions.z {
+            for y in 0..self.dimensions.y {
+                for x in 0..self.dimensions.x {
+                    albedo.extend(self.voxel(UVec3::new(x, y, z)).unwrap());
+                }
+            }
+        }
+        albedo
+    }
+
+    pub fn to_raw_content(&self) -> RawChunkContent {
+        RawChunkContent::new(self.to_albedo(), self.dimensions)
+    }
+
+    //Size of the atlas in bricks, bricks fill the atlas along x first then y then z
+    pub fn atlas_dimensions(&self) -> UVec3 {
+        Self::atlas_dimensions_for(self.slot_count())
+    }
+
+    //Smallest atlas holding this many bricks
+    pub fn atlas_dimensions_for(slot_count: usize) -> UVec3 {
+        let count = slot_count.max(1) as u32;
+        let x = count.min(ATLAS_BRICKS_PER_AXIS);
+        let y = count.div_ceil(x).min(ATLAS_BRICKS_PER_AXIS);
+        UVec3::new(x, y, count.div_ceil(x * y))
+    }
+
+    //Texels of an Rgba8Unorm 3D texture of atlas_dimensions bricks
+    pub fn atlas_data(&self) -> Vec<u8> {
+        self.atlas_data_in(self.atlas_dimensions())
+    }
+
+    //Texels of an Rgba8Unorm 3D texture of atlas_bricks bricks, large enough for every slot
+    pub fn atlas_data_in(&self, atlas_bricks: UVec3) -> Vec<u8> {
+        let atlas_size = atlas_bricks * BRICK_SIZE;
+        let mut atlas = vec![0u8; voxel_count(atlas_size).unwrap() * 4];
+
+        for (slot, brick) in self.bricks.chunks_exact(BRICK_BYTES).enumerate() {
+            let origin = atlas_slot_origin(slot as u32, atlas_bricks);
+
+            for (row, voxels) in brick.chunks_exact((BRICK_SIZE * 4) as usize).enumerate() {
+                let (y, z) = (row as u32 % BRICK_SIZE, row as u32 / BRICK_SIZE);
+                let destination = dense_index(origin + UVec3::new(0, y, z), atlas_size) * 4;
+                atlas[destination..destination + voxels.len()].copy_from_slice(voxels);
+            }
+        }
+
+        atlas
+    }
+
+    //Texels of an R32Uint 3D texture of grid_dimensions cells
+    pub fn grid_data(&self) -> Vec<u8> {
+        self.grid.iter().flat_map(|brick| brick.to_le_bytes()).collect()
+    }
+}
+
+//First texel of a slot in an atlas of atlas_bricks bricks
+pub fn atlas_slot_origin(slot: u32, atlas_bricks: UVec3) -> UVec3 {
+    UVec3::new(slot % atlas_bricks.x, (slot / atlas_bricks.x) % atlas_bricks.y, slot / (atlas_bricks.x * atlas_bricks.y)) * BRICK_SIZE
+}
+
+//None when the product overflows, a 1024x1024x1024 chunk already has 2^32 bytes of RGBA albedo
+fn voxel_count(dimensions: UVec3) -> Option<usize> {
+    (dimensions.x as usize).checked_mul(dimensions.y as usize)?.checked_mul(dimensions.z as usize)
+}
+
+fn dense_index(position: UVec3, dimensions: UVec3) -> usize {
+    position.x as usize + (position.y as usize + position.z as usize * dimensions.y as usize) * dimensions.x as usize
+}
+
+fn brick_voxel_index(slot: u32, local: UVec3) -> usize {
+    slot as usize * BRICK_BYTES + ((local.x + local.y * BRICK_SIZE + local.z * BRICK_SIZE * BRICK_SIZE) * 4) as usize
+}
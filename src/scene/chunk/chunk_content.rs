@@ -1,64 +1,808 @@
-use std::{error::Error, fs, io::{self, Read}, path::Path, fmt::Debug};
+use std::{borrow::Cow, collections::HashMap, error::Error, fs, io::{self, Read, Write}, path::Path, fmt::Debug};
 use glam::{UVec3, UVec4, Vec3, Vec4};
-use wgpu::{Device, Extent3d, Origin3d, Queue, Texture, TextureDescriptor, TextureView};
+use image::{DynamicImage, GrayImage, ImageFormat, RgbaImage};
 
-pub const VOXEL_COMPONENTS: [&str; 1] = ["albedo"];
+use super::brickmap::{atlas_slot_origin, Brickmap, BRICK_SIZE, EMPTY_BRICK};
+use super::distance_field::DistanceField;
+use super::edit_journal::{EditJournal, RegionDelta, Transaction, VoxelChange};
+use super::chunk_manifest::{ChunkManifest, MANIFEST_ENTRY};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, Buffer, BufferUsages, Device, Extent3d, Origin3d, Queue, Texture, TextureDescriptor, TextureFormat, TextureView};
+
+pub const VOXEL_COMPONENTS: [&str; 6] = ["albedo", "normal", "material", "emission", "roughness", "density"];
+
+//Radiance of a voxel of full emission intensity, against at most 1 for lit albedo, keep in sync with chunk_shader.wgsl
+pub const MAX_EMISSION: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VoxelComponent {
+    Albedo,
+    //Unit normal remapped from [-1, 1] to [0, 255], alpha is unused
+    Normal,
+    Material,
+    //RGB emitted colour, alpha is the intensity as a fraction of MAX_EMISSION
+    Emission,
+    Roughness,
+    //Scalar density read by RenderMode::Volume, see TransferFunction
+    Density,
+}
+
+impl VoxelComponent {
+    //Same order as VOXEL_COMPONENTS
+    pub const ALL: [VoxelComponent; 6] = [Self::Albedo, Self::Normal, Self::Material, Self::Emission, Self::Roughness, Self::Density];
+
+    pub fn name(self) -> &'static str {
+        VOXEL_COMPONENTS[self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        VOXEL_COMPONENTS.iter().position(|component| *component == name).map(|i| Self::ALL[i])
+    }
+
+    pub fn bytes_per_voxel(self) -> u32 {
+        match self {
+            Self::Albedo | Self::Normal | Self::Emission => 4,
+            Self::Material | Self::Roughness | Self::Density => 1,
+        }
+    }
+
+    pub fn texture_format(self) -> TextureFormat {
+        match self {
+            Self::Albedo | Self::Normal | Self::Emission => TextureFormat::Rgba8Unorm,
+            Self::Material => TextureFormat::R8Uint,
+            Self::Roughness | Self::Density => TextureFormat::R8Unorm,
+        }
+    }
+
+    //Albedo slices keep the legacy names 0 to z - 1, the slices of the other layers are stored as <component>/<z>
+    pub fn slice_name(self, z: u32) -> String {
+        match self {
+            Self::Albedo => format!("{}", z),
+            component => format!("{}/{}", component.name(), z),
+        }
+    }
+
+    //Bit of the component in the layer mask of the chunk uniform
+    pub fn mask(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+//How the albedo is stored on the GPU, the CPU side albedo is RGBA and only stays sparse for Brickmap, see AlbedoData
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlbedoStorage {
+    //One Rgba8Unorm texel per voxel
+    #[default]
+    Rgba,
+    //An R8Uint index texture, or R16Uint above 256 colours, into a palette of packed RGBA8 colours.
+    //Albedos with more than 65536 colours fall back to Rgba.
+    Indexed,
+    //An R32Uint grid of 8x8x8 bricks pointing into an Rgba8Unorm atlas holding only the non empty bricks, see Brickmap
+    Brickmap,
+}
+
+impl AlbedoStorage {
+    //Value of the albedo_storage field of the chunk uniform
+    pub fn uniform_value(self) -> u32 {
+        self as u32
+    }
+}
+
+//Whether the ray marcher stops on an RGBA8 voxel: its alpha is above the alpha threshold of the chunk, see ChunkManifest.
+//Keep in sync with chunk_shader.wgsl.
+pub fn voxel_is_solid(voxel: &[u8], alpha_threshold: u8) -> bool {
+    voxel[3] > alpha_threshold
+}
+
+//Distinct colours of an RGBA albedo in order of first appearance and the palette index of every voxel,
+//None when the albedo has more colours than an R16Uint index can address
+pub fn index_albedo(albedo: &[u8]) -> Option<(Vec<[u8; 4]>, Vec<u16>)> {
+    let mut palette = Vec::<[u8; 4]>::new();
+    let mut palette_indices = HashMap::<[u8; 4], u16>::new();
+    let mut indices = Vec::<u16>::with_capacity(albedo.len() / 4);
+
+    for voxel in albedo.chunks_exact(4) {
+        let color = [voxel[0], voxel[1], voxel[2], voxel[3]];
+        let index = match palette_indices.get(&color) {
+            Some(index) => *index,
+            None => {
+                if palette.len() > u16::MAX as usize {
+                    return None;
+                }
+                let index = palette.len() as u16;
+                palette.push(color);
+                palette_indices.insert(color, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    Some((palette, indices))
+}
+
+//CPU side content of a chunk as stored in a chunk file, every layer is laid out with x varying fastest then y then z
+#[derive(Clone)]
+pub struct RawChunkContent {
+    pub manifest: ChunkManifest,
+    pub albedo: Vec<u8>,
+    //Every component listed in the manifest except albedo
+    pub layers: HashMap<VoxelComponent, Vec<u8>>,
+}
+
+impl RawChunkContent {
+    pub fn new(albedo: Vec<u8>, dimensions: UVec3) -> Self {
+        Self {
+            manifest: ChunkManifest::new(dimensions),
+            albedo,
+            layers: HashMap::new(),
+        }
+    }
+
+    //Adds a layer and lists it in the manifest
+    pub fn with_layer(mut self, component: VoxelComponent, data: Vec<u8>) -> Self {
+        if component == VoxelComponent::Albedo {
+            self.albedo = data;
+            return self;
+        }
+
+        if !self.manifest.components.iter().any(|name| name == component.name()) {
+            self.manifest.components.push(component.name().to_string());
+        }
+        self.layers.insert(component, data);
+        self
+    }
+
+    //Layers in the order of the manifest, checked against the manifest
+    pub fn layer_slices(&self) -> Result<Vec<(VoxelComponent, &[u8])>, ChunkContentLoadingError> {
+        let layers = self.manifest.components.iter()
+            .map(|name| match VoxelComponent::from_name(name) {
+                Some(VoxelComponent::Albedo) => Ok((VoxelComponent::Albedo, self.albedo.as_slice())),
+                Some(component) => match self.layers.get(&component) {
+                    Some(data) => Ok((component, data.as_slice())),
+                    None => Err(ChunkContentLoadingError::InvalidManifest(format!("no data for component `{}`", name))),
+                },
+                None => Err(ChunkContentLoadingError::InvalidManifest(format!("unknown component `{}`", name))),
+            })
+            .collect::<Result<Vec<(VoxelComponent, &[u8])>, ChunkContentLoadingError>>()?;
+
+        if self.layers.contains_key(&VoxelComponent::Albedo) || layers.len() != self.layers.len() + 1 {
+            return Err(ChunkContentLoadingError::InvalidManifest("layers do not match the manifest components".to_string()));
+        }
+
+        check_layers(&self.manifest, &layers)?;
+        Ok(layers)
+    }
+}
+
+//CPU side albedo of a loaded chunk
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlbedoData {
+    //RGBA8, x varies fastest then y then z
+    Dense(Vec<u8>),
+    //Kept for AlbedoStorage::Brickmap so that large mostly empty chunks never hold their dense albedo
+    Sparse(Brickmap),
+}
+
+impl AlbedoData {
+    pub fn voxel(&self, position: UVec3, dimensions: UVec3) -> Option<[u8; 4]> {
+        match self {
+            Self::Dense(albedo) => {
+                if position.cmpge(dimensions).any() {
+                    return None;
+                }
+
+                let index = voxel_offset(position, dimensions);
+                Some([albedo[index], albedo[index + 1], albedo[index + 2], albedo[index + 3]])
+            },
+            Self::Sparse(brickmap) => brickmap.voxel(position),
+        }
+    }
+
+    //Writes a voxel inside the chunk and returns its previous colour
+    fn replace(&mut self, position: UVec3, dimensions: UVec3, color: [u8; 4]) -> [u8; 4] {
+        let before = self.voxel(position, dimensions).unwrap();
+        match self {
+            Self::Dense(albedo) => {
+                let index = voxel_offset(position, dimensions);
+                albedo[index..index + 4].copy_from_slice(&color);
+            },
+            Self::Sparse(brickmap) => {
+                brickmap.set_voxel(position, color);
+            },
+        }
+        before
+    }
+
+    //Expands a sparse albedo, only meant for exports
+    pub fn to_dense(&self) -> Cow<'_, [u8]> {
+        match self {
+            Self::Dense(albedo) => Cow::Borrowed(albedo),
+            Self::Sparse(brickmap) => Cow::Owned(brickmap.to_albedo()),
+        }
+    }
+
+    pub fn brickmap(&self) -> Option<&Brickmap> {
+        match self {
+            Self::Dense(_) => None,
+            Self::Sparse(brickmap) => Some(brickmap),
+        }
+    }
+
+    //Voxels outside the chunk in the bricks of a sparse albedo are zero and never solid
+    fn solid_voxel_count(&self, alpha_threshold: u8) -> usize {
+        let voxels = match self {
+            Self::Dense(albedo) => albedo,
+            Self::Sparse(brickmap) => &brickmap.bricks,
+        };
+        voxels.chunks_exact(4).filter(|voxel| voxel_is_solid(voxel, alpha_threshold)).count()
+    }
+}
+
+pub struct VoxelLayer {
+    pub data: Vec<u8>,
+    pub texture: Texture,
+    pub view: TextureView,
+}
 
 pub struct ChunkContent {
     pub dimensions: UVec3,
+    pub manifest: ChunkManifest,
 
-    pub albedo: Vec<u8>,
+    //Sparse with Brickmap storage, dense RGBA otherwise
+    pub albedo: AlbedoData,
 
+    //Storage actually used, a chunk loaded as Indexed may fall back to Rgba
+    pub albedo_storage: AlbedoStorage,
+    //1x1x1 placeholder unless the storage is Rgba
     pub albedo_texture: Texture,
     pub albedo_view: TextureView,
+
+    //Colours referenced by the index texture, empty with Rgba storage
+    pub palette: Vec<[u8; 4]>,
+    palette_indices: HashMap<[u8; 4], u16>,
+    //1x1x1 placeholder and single colour palette unless the storage is Indexed
+    pub albedo_index_texture: Texture,
+    pub albedo_index_view: TextureView,
+    //Sized for every index of the index texture format so that new colours can be appended in place
+    pub palette_buffer: Buffer,
+
+    //1x1x1 placeholders unless the storage is Brickmap
+    pub brick_grid_texture: Texture,
+    pub brick_grid_view: TextureView,
+    pub brick_atlas_texture: Texture,
+    pub brick_atlas_view: TextureView,
+
+    //Used by the ray marcher to leap across empty space, brickmaps skip their empty bricks instead
+    pub distance_field: Option<DistanceField>,
+    //1x1x1 placeholder without distance field
+    pub distance_texture: Texture,
+    pub distance_view: TextureView,
+
+    //Every layer other than albedo listed in the manifest
+    pub layers: HashMap<VoxelComponent, VoxelLayer>,
+    //1x1x1 textures bound in place of the missing layers
+    placeholders: HashMap<VoxelComponent, (Texture, TextureView)>,
+
+    //Edited boxes of voxels not uploaded yet
+    dirty_regions: Vec<DirtyRegion>,
+    pub journal: EditJournal,
 }
 
+//GPU side of the albedo for one storage, rebuilt as a whole when edits do not fit in it anymore
+struct AlbedoResources {
+    storage: AlbedoStorage,
+    albedo_texture: (Texture, TextureView),
+    palette: Vec<[u8; 4]>,
+    palette_indices: HashMap<[u8; 4], u16>,
+    albedo_index_texture: (Texture, TextureView),
+    palette_buffer: Buffer,
+    brick_grid_texture: (Texture, TextureView),
+    brick_atlas_texture: (Texture, TextureView),
+}
+
+impl AlbedoResources {
+    //A sparse albedo always uses Brickmap storage, a dense one Rgba or Indexed
+    fn new(device: &Device, queue: &Queue, albedo: &AlbedoData, dimensions: UVec3, storage: AlbedoStorage) -> Self {
+        let indexed = match (storage, albedo) {
+            (AlbedoStorage::Indexed, AlbedoData::Dense(albedo)) => index_albedo(albedo),
+            _ => None,
+        };
+
+        let storage = match (albedo, &indexed) {
+            (AlbedoData::Sparse(_), _) => AlbedoStorage::Brickmap,
+            (AlbedoData::Dense(_), None) => AlbedoStorage::Rgba,
+            (AlbedoData::Dense(_), Some(_)) => AlbedoStorage::Indexed,
+        };
+
+        let albedo_texture = match albedo {
+            AlbedoData::Dense(albedo) if storage == AlbedoStorage::Rgba => create_layer_texture(device, queue, VoxelComponent::Albedo, albedo, dimensions),
+            _ => create_layer_texture(device, queue, VoxelComponent::Albedo, &[0; 4], UVec3::ONE),
+        };
+
+        let (palette, albedo_index_texture) = match indexed {
+            Some((palette, indices)) if palette.len() <= 256 => {
+                let bytes: Vec<u8> = indices.iter().map(|index| *index as u8).collect();
+                (palette, create_texture(device, queue, "Chunk albedo index", TextureFormat::R8Uint, 1, &bytes, dimensions))
+            },
+            Some((palette, indices)) => {
+                let bytes: Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+                (palette, create_texture(device, queue, "Chunk albedo index", TextureFormat::R16Uint, 2, &bytes, dimensions))
+            },
+            None => (Vec::new(), create_texture(device, queue, "Chunk albedo index", TextureFormat::R8Uint, 1, &[0], UVec3::ONE)),
+        };
+
+        let (brick_grid_texture, brick_atlas_texture) = match albedo {
+            AlbedoData::Sparse(brickmap) => (
+                create_texture(device, queue, "Chunk brick grid", TextureFormat::R32Uint, 4, &brickmap.grid_data(), brickmap.grid_dimensions),
+                create_texture(device, queue, "Chunk brick atlas", TextureFormat::Rgba8Unorm, 4, &brickmap.atlas_data(), brickmap.atlas_dimensions() * super::brickmap::BRICK_SIZE),
+            ),
+            AlbedoData::Dense(_) => (
+                create_texture(device, queue, "Chunk brick grid", TextureFormat::R32Uint, 4, &[0; 4], UVec3::ONE),
+                create_texture(device, queue, "Chunk brick atlas", TextureFormat::Rgba8Unorm, 4, &[0; 4], UVec3::ONE),
+            ),
+        };
+
+        //Storage buffers cannot be empty
+        let capacity = match albedo_index_texture.0.format() {
+            _ if storage != AlbedoStorage::Indexed => 1,
+            TextureFormat::R8Uint => 1 << 8,
+            _ => 1 << 16,
+        };
+        let mut packed_palette = palette.concat();
+        packed_palette.resize(capacity * 4, 0);
+        let palette_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Chunk palette"),
+            contents: &packed_palette,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+        });
+
+        let palette_indices = palette.iter().enumerate().map(|(i, color)| (*color, i as u16)).collect();
+
+        Self {
+            storage,
+            albedo_texture,
+            palette,
+            palette_indices,
+            albedo_index_texture,
+            palette_buffer,
+            brick_grid_texture,
+            brick_atlas_texture,
+        }
+    }
+}
+
+//Box of voxels from min included to max excluded
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DirtyRegion {
+    min: UVec3,
+    max: UVec3,
+    //Whether an empty voxel of the region became solid, the distance field has to be lowered around it
+    solidified: bool,
+}
+
+impl DirtyRegion {
+    //Touching or overlapping regions are merged
+    fn touches(&self, other: &DirtyRegion) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    fn union(&self, other: &DirtyRegion) -> DirtyRegion {
+        DirtyRegion {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            solidified: self.solidified || other.solidified,
+        }
+    }
+}
+
+//Beyond this many separate regions they are merged into their bounding box, one large upload is cheaper than many small ones
+pub const MAX_DIRTY_REGIONS: usize = 32;
+
 impl ChunkContent {
     pub fn from_raw_data(device: &Device, queue: &Queue, albedo: Vec<u8>, dimensions: UVec3) -> Result<Self, ChunkContentLoadingError> {
-        if dimensions.x == 0 || dimensions.y == 0 || dimensions.z == 0 {
-            return Err(ChunkContentLoadingError::InvalidDimensions);
+        Self::from_raw_content(device, queue, RawChunkContent::new(albedo, dimensions))
+    }
+
+    pub fn from_raw_content(device: &Device, queue: &Queue, raw: RawChunkContent) -> Result<Self, ChunkContentLoadingError> {
+        Self::from_raw_content_with_storage(device, queue, raw, AlbedoStorage::Rgba)
+    }
+
+    //The dense albedo is dropped once converted when the storage is Brickmap
+    pub fn from_raw_content_with_storage(device: &Device, queue: &Queue, raw: RawChunkContent, albedo_storage: AlbedoStorage) -> Result<Self, ChunkContentLoadingError> {
+        raw.layer_slices()?;
+
+        let RawChunkContent { manifest, albedo, layers } = raw;
+        let albedo = match albedo_storage {
+            AlbedoStorage::Brickmap => AlbedoData::Sparse(Brickmap::from_albedo(&albedo, manifest.dimensions)?),
+            _ => AlbedoData::Dense(albedo),
+        };
+
+        Ok(Self::from_albedo_data(device, queue, manifest, albedo, layers, albedo_storage))
+    }
+
+    //Loads a brickmap without ever expanding it, the manifest lists the albedo only
+    pub fn from_brickmap(device: &Device, queue: &Queue, brickmap: Brickmap, manifest: ChunkManifest) -> Result<Self, ChunkContentLoadingError> {
+        manifest.validate()?;
+        if manifest.dimensions != brickmap.dimensions || manifest.components.len() != 1 {
+            return Err(ChunkContentLoadingError::InvalidManifest("the manifest does not match the brickmap".to_string()));
+        }
+
+        Ok(Self::from_albedo_data(device, queue, manifest, AlbedoData::Sparse(brickmap), HashMap::new(), AlbedoStorage::Brickmap))
+    }
+
+    //The layers have already been checked against the manifest
+    fn from_albedo_data(device: &Device, queue: &Queue, manifest: ChunkManifest, albedo: AlbedoData, raw_layers: HashMap<VoxelComponent, Vec<u8>>, albedo_storage: AlbedoStorage) -> Self {
+        let dimensions = manifest.dimensions;
+
+        let resources = AlbedoResources::new(device, queue, &albedo, dimensions, albedo_storage);
+
+        let distance_field = match &albedo {
+            AlbedoData::Dense(albedo) => Some(DistanceField::from_albedo(albedo, dimensions, manifest.alpha_threshold)),
+            AlbedoData::Sparse(_) => None,
+        };
+
+        let (distance_texture, distance_view) = match &distance_field {
+            Some(distance_field) => create_texture(device, queue, "Chunk distance field", TextureFormat::R8Uint, 1, &distance_field.distances, dimensions),
+            None => create_texture(device, queue, "Chunk distance field", TextureFormat::R8Uint, 1, &[0], UVec3::ONE),
+        };
+
+        let mut layers = HashMap::<VoxelComponent, VoxelLayer>::new();
+        for (component, data) in raw_layers {
+            let (texture, view) = create_layer_texture(device, queue, component, &data, dimensions);
+            layers.insert(component, VoxelLayer { data, texture, view });
         }
 
-        let albedo_texture = device.create_texture(&TextureDescriptor {
-                size: Extent3d { width: dimensions.x, height: dimensions.y, depth_or_array_layers: dimensions.z },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D3,
-                format: wgpu::TextureFormat::Rgba8Unorm,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING |  wgpu::TextureUsages::COPY_DST,
-                label: Some("Chunk albedo"),
-                view_formats: &[] 
+        let mut placeholders = HashMap::<VoxelComponent, (Texture, TextureView)>::new();
+        for component in VoxelComponent::ALL.into_iter().skip(1) {
+            if !layers.contains_key(&component) {
+                let placeholder = vec![0; component.bytes_per_voxel() as usize];
+                placeholders.insert(component, create_layer_texture(device, queue, component, &placeholder, UVec3::ONE));
             }
-        );
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &albedo_texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &albedo.as_slice(),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(dimensions.x * 4),
-                rows_per_image: Some(dimensions.y)
-            },
-            Extent3d { width: dimensions.x, height: dimensions.y, depth_or_array_layers: dimensions.z }
-        );
+        }
 
-        let albedo_view = albedo_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        
+        let AlbedoResources {
+            storage: albedo_storage,
+            albedo_texture: (albedo_texture, albedo_view),
+            palette,
+            palette_indices,
+            albedo_index_texture: (albedo_index_texture, albedo_index_view),
+            palette_buffer,
+            brick_grid_texture: (brick_grid_texture, brick_grid_view),
+            brick_atlas_texture: (brick_atlas_texture, brick_atlas_view),
+        } = resources;
 
-        Ok(Self { 
+        Self { 
             dimensions, 
+            manifest,
             albedo, 
+            albedo_storage,
             albedo_texture,
-            albedo_view
-        })
+            albedo_view,
+            palette,
+            palette_indices,
+            albedo_index_texture,
+            albedo_index_view,
+            palette_buffer,
+            brick_grid_texture,
+            brick_grid_view,
+            brick_atlas_texture,
+            brick_atlas_view,
+            distance_field,
+            distance_texture,
+            distance_view,
+            layers,
+            placeholders,
+            dirty_regions: Vec::new(),
+            journal: EditJournal::default(),
+        }
+    }
+
+    fn set_albedo_resources(&mut self, resources: AlbedoResources) {
+        self.albedo_storage = resources.storage;
+        (self.albedo_texture, self.albedo_view) = resources.albedo_texture;
+        self.palette = resources.palette;
+        self.palette_indices = resources.palette_indices;
+        (self.albedo_index_texture, self.albedo_index_view) = resources.albedo_index_texture;
+        self.palette_buffer = resources.palette_buffer;
+        (self.brick_grid_texture, self.brick_grid_view) = resources.brick_grid_texture;
+        (self.brick_atlas_texture, self.brick_atlas_view) = resources.brick_atlas_texture;
+    }
+
+    //Returns false when the voxel is outside of the chunk
+    pub fn set_voxel(&mut self, position: UVec3, color: [u8; 4]) -> bool {
+        if position.cmpge(self.dimensions).any() {
+            return false;
+        }
+
+        self.edit_box(position, position + 1, |_| Some(color));
+        true
+    }
+
+    //Fills the voxels from min included to max excluded, clipped to the chunk
+    pub fn fill_box(&mut self, min: UVec3, max: UVec3, color: [u8; 4]) {
+        self.edit_box(min, max, |_| Some(color));
+    }
+
+    //Fills the voxels whose center is within radius of center, both in voxels
+    pub fn fill_sphere(&mut self, center: Vec3, radius: f32, color: [u8; 4]) {
+        let min = (center - radius).floor().max(Vec3::ZERO).as_uvec3();
+        let max = (center + radius).ceil().max(Vec3::ZERO).as_uvec3() + 1;
+
+        self.edit_box(min, max, |position| match (position.as_vec3() + 0.5).distance_squared(center) <= radius * radius {
+            true => Some(color),
+            false => None,
+        });
+    }
+
+    //Sets the voxels of a box to the colour returned for them, if any, and marks the box as dirty
+    fn edit_box(&mut self, min: UVec3, max: UVec3, color_at: impl Fn(UVec3) -> Option<[u8; 4]>) {
+        let max = max.min(self.dimensions);
+        if min.cmpge(max).any() {
+            return;
+        }
+
+        let alpha_threshold = self.manifest.alpha_threshold;
+        let dimensions = self.dimensions;
+        let mut solidified = false;
+        let mut changes = Vec::<VoxelChange>::new();
+        for_each_voxel(min, max, |position| {
+            if let Some(color) = color_at(position) {
+                let before = self.albedo.replace(position, dimensions, color);
+                if before != color {
+                    solidified |= !voxel_is_solid(&before, alpha_threshold) && voxel_is_solid(&color, alpha_threshold);
+                    changes.push(VoxelChange { index: voxel_index(position, dimensions), before, after: color });
+                }
+            }
+        });
+
+        self.free_empty_bricks(min, max);
+        if changes.is_empty() {
+            return;
+        }
+
+        self.journal.record(RegionDelta { min, max, changes });
+        self.mark_dirty(DirtyRegion { min, max, solidified });
+    }
+
+    //Groups the following edits until the matching commit_transaction so that they are undone and redone together
+    pub fn begin_transaction(&mut self) {
+        self.journal.begin_transaction();
+    }
+
+    pub fn commit_transaction(&mut self) -> bool {
+        self.journal.commit_transaction()
+    }
+
+    //Returns false when there is nothing to undo, an open transaction is committed first
+    pub fn undo(&mut self) -> bool {
+        let Some(transaction) = self.journal.undo().cloned() else {
+            return false;
+        };
+
+        self.apply(&transaction, true);
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(transaction) = self.journal.redo().cloned() else {
+            return false;
+        };
+
+        self.apply(&transaction, false);
+        true
+    }
+
+    //Writes the before values of a transaction, last edit first, or its after values, first edit first
+    fn apply(&mut self, transaction: &Transaction, undo: bool) {
+        let deltas: Box<dyn Iterator<Item = &RegionDelta>> = match undo {
+            true => Box::new(transaction.deltas.iter().rev()),
+            false => Box::new(transaction.deltas.iter()),
+        };
+
+        for delta in deltas {
+            let mut solidified = false;
+            for change in delta.changes.iter() {
+                let (from, to) = match undo {
+                    true => (change.after, change.before),
+                    false => (change.before, change.after),
+                };
+
+                solidified |= !voxel_is_solid(&from, self.manifest.alpha_threshold) && voxel_is_solid(&to, self.manifest.alpha_threshold);
+                self.albedo.replace(voxel_position(change.index, self.dimensions), self.dimensions, to);
+            }
+
+            self.free_empty_bricks(delta.min, delta.max);
+            self.mark_dirty(DirtyRegion { min: delta.min, max: delta.max, solidified });
+        }
+    }
+
+    //Bricks emptied by an edit of the box from min included to max excluded give their slot back
+    fn free_empty_bricks(&mut self, min: UVec3, max: UVec3) {
+        if let AlbedoData::Sparse(brickmap) = &mut self.albedo {
+            brickmap.free_empty_bricks(min / BRICK_SIZE, (max + BRICK_SIZE - 1) / BRICK_SIZE);
+        }
+    }
+
+    fn mark_dirty(&mut self, mut region: DirtyRegion) {
+        while let Some(i) = self.dirty_regions.iter().position(|dirty| dirty.touches(&region)) {
+            region = region.union(&self.dirty_regions.swap_remove(i));
+        }
+
+        self.dirty_regions.push(region);
+
+        if self.dirty_regions.len() > MAX_DIRTY_REGIONS {
+            let bounds = self.dirty_regions.iter().skip(1).fold(self.dirty_regions[0], |bounds, dirty| bounds.union(dirty));
+            self.dirty_regions = vec![bounds];
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty_regions.is_empty()
+    }
+
+    //Uploads the edited boxes of voxels, only the textures of the storage change so bind groups built afterwards see the edits.
+    //Index textures running out of palette entries and brick atlases running out of slots are rebuilt as a whole.
+    //Returns true when the albedo storage changed, an Indexed chunk past 65536 colours falls back to Rgba.
+    pub fn flush(&mut self, device: &Device, queue: &Queue) -> bool {
+        if self.dirty_regions.is_empty() {
+            return false;
+        }
+
+        let regions = std::mem::take(&mut self.dirty_regions);
+        let storage = self.albedo_storage;
+
+        match self.albedo_storage {
+            AlbedoStorage::Rgba => {
+                let AlbedoData::Dense(albedo) = &self.albedo else {
+                    unreachable!("RGBA albedos are dense");
+                };
+
+                for region in regions.iter() {
+                    write_texture_region(queue, &self.albedo_texture, albedo, self.dimensions, 4, region.min, region.max);
+                }
+            },
+            AlbedoStorage::Indexed => self.flush_indexed(device, queue, &regions),
+            AlbedoStorage::Brickmap => self.flush_brickmap(device, queue, &regions),
+        }
+
+        if let Some(distance_field) = &mut self.distance_field {
+            for region in regions.iter().filter(|region| region.solidified) {
+                if let Some((min, max)) = distance_field.lower_around(region.min, region.max) {
+                    write_texture_region(queue, &self.distance_texture, &distance_field.distances, self.dimensions, 1, min, max);
+                }
+            }
+        }
+
+        self.albedo_storage != storage
+    }
+
+    //Writes the grid cells and the bricks touched by the regions, the atlas is only rebuilt when new bricks do not fit in it anymore
+    fn flush_brickmap(&mut self, device: &Device, queue: &Queue, regions: &[DirtyRegion]) {
+        let AlbedoData::Sparse(brickmap) = &self.albedo else {
+            unreachable!("brickmap albedos are sparse");
+        };
+
+        let atlas_size = self.brick_atlas_texture.size();
+        let mut atlas_bricks = UVec3::new(atlas_size.width, atlas_size.height, atlas_size.depth_or_array_layers) / BRICK_SIZE;
+        let grown = brickmap.slot_count() > (atlas_bricks.x * atlas_bricks.y * atlas_bricks.z) as usize;
+        if grown {
+            //Room for as many bricks again so that growing edits do not rebuild it every frame
+            atlas_bricks = Brickmap::atlas_dimensions_for(brickmap.slot_count() * 2);
+            (self.brick_atlas_texture, self.brick_atlas_view) = create_texture(device, queue, "Chunk brick atlas", TextureFormat::Rgba8Unorm, 4, &brickmap.atlas_data_in(atlas_bricks), atlas_bricks * BRICK_SIZE);
+        }
+
+        for region in regions.iter() {
+            let min_cell = region.min / BRICK_SIZE;
+            let max_cell = (region.max + BRICK_SIZE - 1) / BRICK_SIZE;
+
+            let mut cells = Vec::<u8>::new();
+            for_each_voxel(min_cell, max_cell, |cell| {
+                let brick = brickmap.cell(cell);
+                cells.extend(brick.to_le_bytes());
+
+                //Freed bricks are not pointed to anymore, their slot is left as is
+                if brick != EMPTY_BRICK && !grown {
+                    write_texture_box(queue, &self.brick_atlas_texture, brickmap.brick(brick - 1), 4, atlas_slot_origin(brick - 1, atlas_bricks), UVec3::splat(BRICK_SIZE));
+                }
+            });
+
+            write_texture_box(queue, &self.brick_grid_texture, &cells, 4, min_cell, max_cell - min_cell);
+        }
+    }
+
+    fn flush_indexed(&mut self, device: &Device, queue: &Queue, regions: &[DirtyRegion]) {
+        let AlbedoData::Dense(albedo) = &self.albedo else {
+            unreachable!("indexed albedos are dense");
+        };
+
+        let capacity = match self.albedo_index_texture.format() {
+            TextureFormat::R8Uint => 1 << 8,
+            _ => 1 << 16,
+        };
+
+        let first_new_color = self.palette.len();
+        for region in regions.iter() {
+            for_each_voxel(region.min, region.max, |position| {
+                let index = voxel_offset(position, self.dimensions);
+                let color = [albedo[index], albedo[index + 1], albedo[index + 2], albedo[index + 3]];
+                if !self.palette_indices.contains_key(&color) {
+                    self.palette_indices.insert(color, self.palette.len() as u16);
+                    self.palette.push(color);
+                }
+            });
+        }
+
+        //A full palette needs a wider index format, or RGBA storage past 65536 colours
+        if self.palette.len() > capacity {
+            let resources = AlbedoResources::new(device, queue, &self.albedo, self.dimensions, AlbedoStorage::Indexed);
+            self.set_albedo_resources(resources);
+            return;
+        }
+
+        if self.palette.len() > first_new_color {
+            queue.write_buffer(&self.palette_buffer, (first_new_color * 4) as u64, &self.palette[first_new_color..].concat());
+        }
+
+        for region in regions.iter() {
+            let mut indices = Vec::<u8>::new();
+            for_each_voxel(region.min, region.max, |position| {
+                let index = voxel_offset(position, self.dimensions);
+                let color = [albedo[index], albedo[index + 1], albedo[index + 2], albedo[index + 3]];
+                let palette_index = self.palette_indices[&color];
+                match capacity {
+                    256 => indices.push(palette_index as u8),
+                    _ => indices.extend(palette_index.to_le_bytes()),
+                }
+            });
+
+            let size = region.max - region.min;
+            let bytes_per_voxel = indices.len() as u32 / (size.x * size.y * size.z);
+            write_texture_box(queue, &self.albedo_index_texture, &indices, bytes_per_voxel, region.min, size);
+        }
+    }
+
+    pub fn has_layer(&self, component: VoxelComponent) -> bool {
+        component == VoxelComponent::Albedo || self.layers.contains_key(&component)
+    }
+
+    //View to bind for a component, a 1x1x1 placeholder when the chunk does not have this layer
+    pub fn layer_view(&self, component: VoxelComponent) -> &TextureView {
+        if component == VoxelComponent::Albedo {
+            return &self.albedo_view;
+        }
+
+        match self.layers.get(&component) {
+            Some(layer) => &layer.view,
+            None => &self.placeholders[&component].1,
+        }
     }
 
     pub fn from_chunk_file(device: &Device, queue: &Queue, path: &Path) -> Result<Self, ChunkContentLoadingError> {
+        Self::from_chunk_file_with_storage(device, queue, path, AlbedoStorage::Rgba)
+    }
+
+    pub fn from_chunk_file_with_storage(device: &Device, queue: &Queue, path: &Path, albedo_storage: AlbedoStorage) -> Result<Self, ChunkContentLoadingError> {
+        let raw = Self::read_chunk_file(path)?;
+        ChunkContent::from_raw_content_with_storage(device, queue, raw, albedo_storage)
+    }
+
+    pub fn is_solid(&self, position: UVec3) -> bool {
+        self.albedo_at(position).is_some_and(|voxel| voxel_is_solid(&voxel, self.manifest.alpha_threshold))
+    }
+
+    //Voxels the ray marcher can hit
+    pub fn solid_voxel_count(&self) -> usize {
+        self.albedo.solid_voxel_count(self.manifest.alpha_threshold)
+    }
+
+    //RGBA albedo of a voxel, None outside of the chunk
+    pub fn albedo_at(&self, position: UVec3) -> Option<[u8; 4]> {
+        self.albedo.voxel(position, self.dimensions)
+    }
+
+    //Reads the layers and the manifest of a chunk file without touching the GPU.
+    //Legacy chunk files without a manifest get a default one built from their slices.
+    pub fn read_chunk_file(path: &Path) -> Result<RawChunkContent, ChunkContentLoadingError> {
         let chunk_content_file = match fs::File::open(path) {
             Ok(file) => file,
             Err(err) => return Err(ChunkContentLoadingError::FailedToReadChunkFile(err))
@@ -69,71 +813,290 @@ impl ChunkContent {
             Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile)
         };
 
-        let first_image_bytes: Vec<u8> = match chunk_content_zip.by_name("0") {
-            Ok(image0) => {
-                let raw_bytes: Vec<Result<u8, io::Error>> = image0.bytes().collect();
-                let mut bytes = Vec::<u8>::with_capacity(raw_bytes.len());
-                for byte in raw_bytes {
-                    match byte {
-                        Ok(byte) => bytes.push(byte),
-                        Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
-                    }
+        let manifest = match read_entry(&mut chunk_content_zip, MANIFEST_ENTRY)? {
+            Some(manifest_bytes) => match String::from_utf8(manifest_bytes) {
+                Ok(text) => ChunkManifest::parse(&text)?,
+                Err(_) => return Err(ChunkContentLoadingError::InvalidManifest("manifest is not valid UTF-8".to_string())),
+            },
+            None => {
+                let first_image = read_slice(&mut chunk_content_zip, &VoxelComponent::Albedo.slice_name(0), VoxelComponent::Albedo)?;
+
+                //Only the contiguous run of numbered slices is part of the chunk, anything else in the archive is ignored
+                let mut depth = 1;
+                while chunk_content_zip.index_for_name(&VoxelComponent::Albedo.slice_name(depth)).is_some() {
+                    depth += 1;
                 }
 
-                bytes
+                ChunkManifest::new(UVec3::new(first_image.width(), first_image.height(), depth))
             },
-            Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
         };
 
-        let first_image = match image::load_from_memory(first_image_bytes.as_slice()) {
-            Ok(im) => im,
-            Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
+        let dimensions = manifest.dimensions;
+        //Every slice is an entry of the archive, a manifest claiming more is rejected before anything is allocated for them
+        if dimensions.z as usize > chunk_content_zip.len() {
+            return Err(ChunkContentLoadingError::InvalidDimensions);
+        }
+
+        let mut raw = RawChunkContent {
+            albedo: Vec::new(),
+            layers: HashMap::new(),
+            manifest: manifest.clone(),
         };
 
-        let dimensions = UVec3::new(first_image.width(), first_image.height(), chunk_content_zip.len() as u32);
-        
-        let mut albedo_bytes :Vec<u8> = Vec::with_capacity((dimensions.x * dimensions.y * dimensions.z * 4) as usize);
+        for name in manifest.components.iter() {
+            let component = match VoxelComponent::from_name(name) {
+                Some(component) => component,
+                None => return Err(ChunkContentLoadingError::InvalidManifest(format!("unknown component `{}`", name))),
+            };
+
+            let mut bytes :Vec<u8> = Vec::new();
+
+            for i in 0..dimensions.z {
+                let image = read_slice(&mut chunk_content_zip, &component.slice_name(i), component)?;
+                if image.width() != dimensions.x || image.height() != dimensions.y {
+                    return Err(ChunkContentLoadingError::InvalidDimensions);
+                }
+
+                //Reserved once the first slice confirms the width and height of the manifest, validate checked the size
+                if i == 0 {
+                    bytes.reserve_exact(manifest.layer_size(component).unwrap());
+                }
+
+                bytes.extend(image.into_bytes());
+            }
+
+            raw = raw.with_layer(component, bytes);
+        }
         
-        albedo_bytes.extend(match first_image.as_rgba8() {
-            Some(rgba) => rgba.iter(),
-            None =>  return Err(ChunkContentLoadingError::InvalidChunkFile)
-        });
+        Ok(raw)
+    }
 
-        for i in 1..dimensions.z {
-            let image_bytes: Vec<u8> = match chunk_content_zip.by_name(&format!("{}", i)) {
-                Ok(image) => {
-                    let raw_bytes: Vec<Result<u8, io::Error>> = image.bytes().collect();
-                    let mut bytes = Vec::<u8>::with_capacity(raw_bytes.len());
-                    for byte in raw_bytes {
-                        match byte {
-                            Ok(byte) => bytes.push(byte),
-                            Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
-                        }
-                    }
-    
-                    bytes
-                },
-                Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
-            };
-    
-            let image = match image::load_from_memory(image_bytes.as_slice()) {
-                Ok(im) => im,
-                Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
+    pub fn save_to_chunk_file(&self, path: &Path) -> Result<(), ChunkContentSavingError> {
+        let albedo = self.albedo.to_dense();
+        let layers: Vec<(VoxelComponent, &[u8])> = self.manifest.components.iter()
+            .filter_map(|name| VoxelComponent::from_name(name))
+            .map(|component| match self.layers.get(&component) {
+                Some(layer) => (component, layer.data.as_slice()),
+                None => (component, albedo.as_ref()),
+            })
+            .collect();
+
+        write_layers(&self.manifest, &layers, path)
+    }
+
+    pub fn save_to_vox_file(&self, path: &Path) -> Result<(), ChunkContentSavingError> {
+        super::magica_voxel::write_vox_file(&self.albedo.to_dense(), self.dimensions, self.manifest.alpha_threshold, path)
+    }
+
+    //Writes a chunk file: a manifest, the albedo PNG slices named 0 to z - 1 and the slices of the other layers
+    pub fn write_chunk_file(raw: &RawChunkContent, path: &Path) -> Result<(), ChunkContentSavingError> {
+        let layers = match raw.layer_slices() {
+            Ok(layers) => layers,
+            Err(err) => return Err(ChunkContentSavingError::from_loading_error(err)),
+        };
+
+        write_layers(&raw.manifest, &layers, path)
+    }
+}
+
+fn check_layers(manifest: &ChunkManifest, layers: &[(VoxelComponent, &[u8])]) -> Result<(), ChunkContentLoadingError> {
+    manifest.validate()?;
+
+    for (component, data) in layers {
+        if manifest.layer_size(*component) != Some(data.len()) {
+            return Err(ChunkContentLoadingError::InvalidDimensions);
+        }
+    }
+
+    Ok(())
+}
+
+//In u64 as a brickmap chunk can have more than 2^32 voxels, loading rejects the chunks whose voxels do not fit a usize
+fn voxel_index(position: UVec3, dimensions: UVec3) -> u64 {
+    position.x as u64 + (position.y as u64 + position.z as u64 * dimensions.y as u64) * dimensions.x as u64
+}
+
+fn voxel_position(index: u64, dimensions: UVec3) -> UVec3 {
+    let (x, y) = (dimensions.x as u64, dimensions.y as u64);
+    UVec3::new((index % x) as u32, (index / x % y) as u32, (index / (x * y)) as u32)
+}
+
+//Offset of the first byte of an RGBA8 voxel in a dense albedo
+fn voxel_offset(position: UVec3, dimensions: UVec3) -> usize {
+    (position.x as usize + (position.y as usize + position.z as usize * dimensions.y as usize) * dimensions.x as usize) * 4
+}
+
+//Visits the voxels from min included to max excluded with x varying fastest then y then z
+fn for_each_voxel(min: UVec3, max: UVec3, mut visit: impl FnMut(UVec3)) {
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                visit(UVec3::new(x, y, z));
+            }
+        }
+    }
+}
+
+//Uploads the box from min included to max excluded of a texture covering the whole chunk
+fn write_texture_region(queue: &Queue, texture: &Texture, data: &[u8], dimensions: UVec3, bytes_per_voxel: u32, min: UVec3, max: UVec3) {
+    let size = max - min;
+    let row = (size.x * bytes_per_voxel) as usize;
+    let mut region = Vec::<u8>::with_capacity(row * (size.y * size.z) as usize);
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            let start = voxel_offset(UVec3::new(min.x, y, z), dimensions) / 4 * bytes_per_voxel as usize;
+            region.extend_from_slice(&data[start..start + row]);
+        }
+    }
+
+    write_texture_box(queue, texture, &region, bytes_per_voxel, min, size);
+}
+
+fn write_texture_box(queue: &Queue, texture: &Texture, data: &[u8], bytes_per_voxel: u32, origin: UVec3, size: UVec3) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d { x: origin.x, y: origin.y, z: origin.z },
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(size.x * bytes_per_voxel),
+            rows_per_image: Some(size.y),
+        },
+        Extent3d { width: size.x, height: size.y, depth_or_array_layers: size.z },
+    );
+}
+
+fn create_layer_texture(device: &Device, queue: &Queue, component: VoxelComponent, data: &[u8], dimensions: UVec3) -> (Texture, TextureView) {
+    create_texture(device, queue, &format!("Chunk {}", component.name()), component.texture_format(), component.bytes_per_voxel(), data, dimensions)
+}
+
+fn create_texture(device: &Device, queue: &Queue, label: &str, format: TextureFormat, bytes_per_voxel: u32, data: &[u8], dimensions: UVec3) -> (Texture, TextureView) {
+    let texture = device.create_texture(&TextureDescriptor {
+            size: Extent3d { width: dimensions.x, height: dimensions.y, depth_or_array_layers: dimensions.z },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING |  wgpu::TextureUsages::COPY_DST,
+            label: Some(label),
+            view_formats: &[] 
+        }
+    );
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(dimensions.x * bytes_per_voxel),
+            rows_per_image: Some(dimensions.y)
+        },
+        Extent3d { width: dimensions.x, height: dimensions.y, depth_or_array_layers: dimensions.z }
+    );
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn write_layers(manifest: &ChunkManifest, layers: &[(VoxelComponent, &[u8])], path: &Path) -> Result<(), ChunkContentSavingError> {
+    if let Err(err) = check_layers(manifest, layers) {
+        return Err(ChunkContentSavingError::from_loading_error(err));
+    }
+
+    let dimensions = manifest.dimensions;
+
+    let chunk_content_file = match fs::File::create(path) {
+        Ok(file) => file,
+        Err(err) => return Err(ChunkContentSavingError::FailedToWriteChunkFile(err))
+    };
+
+    let mut chunk_content_zip = zip::ZipWriter::new(chunk_content_file);
+    //PNG slices are already compressed
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    if let Err(err) = chunk_content_zip.start_file(MANIFEST_ENTRY, zip::write::SimpleFileOptions::default()) {
+        return Err(ChunkContentSavingError::from_zip_error(err));
+    }
+
+    if let Err(err) = chunk_content_zip.write_all(manifest.serialize().as_bytes()) {
+        return Err(ChunkContentSavingError::FailedToWriteChunkFile(err));
+    }
+
+    for (component, data) in layers {
+        let slice_size = (dimensions.x * dimensions.y * component.bytes_per_voxel()) as usize;
+
+        for (i, slice) in data.chunks(slice_size).enumerate() {
+            let mut image_bytes = io::Cursor::new(Vec::<u8>::new());
+            let encoded = match component.bytes_per_voxel() {
+                4 => RgbaImage::from_raw(dimensions.x, dimensions.y, slice.to_vec()).map(|image| image.write_to(&mut image_bytes, ImageFormat::Png)),
+                _ => GrayImage::from_raw(dimensions.x, dimensions.y, slice.to_vec()).map(|image| image.write_to(&mut image_bytes, ImageFormat::Png)),
             };
-    
-            albedo_bytes.extend(match image.as_rgba8() {
-                Some(rgba) => rgba.iter(),
-                None =>  return Err(ChunkContentLoadingError::InvalidChunkFile)
-            });
+
+            match encoded {
+                Some(Ok(_)) => {},
+                Some(Err(_)) => return Err(ChunkContentSavingError::FailedToEncodeSlice),
+                None => return Err(ChunkContentSavingError::InvalidDimensions),
+            }
+
+            if let Err(err) = chunk_content_zip.start_file(component.slice_name(i as u32), options) {
+                return Err(ChunkContentSavingError::from_zip_error(err));
+            }
+
+            if let Err(err) = chunk_content_zip.write_all(image_bytes.get_ref()) {
+                return Err(ChunkContentSavingError::FailedToWriteChunkFile(err));
+            }
         }
-        
-        ChunkContent::from_raw_data(device, queue, albedo_bytes, dimensions)
+    }
+
+    match chunk_content_zip.finish() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(ChunkContentSavingError::from_zip_error(err)),
+    }
+}
+
+fn read_entry<R: io::Read + io::Seek>(chunk_content_zip: &mut zip::ZipArchive<R>, name: &str) -> Result<Option<Vec<u8>>, ChunkContentLoadingError> {
+    let mut entry = match chunk_content_zip.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(_) => return Err(ChunkContentLoadingError::InvalidChunkFile),
+    };
+
+    let mut bytes = Vec::<u8>::with_capacity(entry.size() as usize);
+    match entry.read_to_end(&mut bytes) {
+        Ok(_) => Ok(Some(bytes)),
+        Err(_) => Err(ChunkContentLoadingError::InvalidChunkFile),
+    }
+}
+
+//Slices of 4 bytes components are RGBA8 PNGs, single byte components are grayscale PNGs
+fn read_slice<R: io::Read + io::Seek>(chunk_content_zip: &mut zip::ZipArchive<R>, name: &str, component: VoxelComponent) -> Result<DynamicImage, ChunkContentLoadingError> {
+    let image_bytes = match read_entry(chunk_content_zip, name)? {
+        Some(bytes) => bytes,
+        None => return Err(ChunkContentLoadingError::InvalidChunkFile),
+    };
+
+    match (image::load_from_memory(image_bytes.as_slice()), component.bytes_per_voxel()) {
+        (Ok(image @ DynamicImage::ImageRgba8(_)), 4) => Ok(image),
+        (Ok(image @ DynamicImage::ImageLuma8(_)), 1) => Ok(image),
+        _ => Err(ChunkContentLoadingError::InvalidChunkFile),
     }
 }
 
 pub enum ChunkContentLoadingError {
     InvalidDimensions,
     InvalidChunkFile,
+    InvalidManifest(String),
+    UnsupportedFormatVersion(u32),
+    InvalidVoxFile(String),
     FailedToReadChunkFile(std::io::Error),
 }
 
@@ -142,7 +1105,44 @@ impl Debug for ChunkContentLoadingError {
         match self {
             Self::InvalidDimensions => write!(f, "InvalidDimensions"),
             Self::InvalidChunkFile => write!(f, "InvalidChunkFile"),
+            Self::InvalidManifest(arg0) => f.debug_tuple("InvalidManifest").field(arg0).finish(),
+            Self::UnsupportedFormatVersion(arg0) => f.debug_tuple("UnsupportedFormatVersion").field(arg0).finish(),
+            Self::InvalidVoxFile(arg0) => f.debug_tuple("InvalidVoxFile").field(arg0).finish(),
             Self::FailedToReadChunkFile(arg0) => f.debug_tuple("FailedToReadChunkFile").field(arg0).finish(),
         }
     }
 }
+
+pub enum ChunkContentSavingError {
+    InvalidDimensions,
+    InvalidManifest(ChunkContentLoadingError),
+    FailedToEncodeSlice,
+    FailedToWriteChunkFile(std::io::Error),
+}
+
+impl ChunkContentSavingError {
+    fn from_loading_error(err: ChunkContentLoadingError) -> Self {
+        match err {
+            ChunkContentLoadingError::InvalidDimensions => Self::InvalidDimensions,
+            err => Self::InvalidManifest(err),
+        }
+    }
+
+    fn from_zip_error(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => Self::FailedToWriteChunkFile(err),
+            err => Self::FailedToWriteChunkFile(io::Error::other(err)),
+        }
+    }
+}
+
+impl Debug for ChunkContentSavingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidDimensions => write!(f, "InvalidDimensions"),
+            Self::InvalidManifest(arg0) => f.debug_tuple("InvalidManifest").field(arg0).finish(),
+            Self::FailedToEncodeSlice => write!(f, "FailedToEncodeSlice"),
+            Self::FailedToWriteChunkFile(arg0) => f.debug_tuple("FailedToWriteChunkFile").field(arg0).finish(),
+        }
+    }
+}
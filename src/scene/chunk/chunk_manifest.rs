@@ -0,0 +1,183 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use glam::{UVec3, Vec3};
+
+use super::chunk_content::{ChunkContentLoadingError, VoxelComponent, VOXEL_COMPONENTS};
+use crate::scene::VOXEL_SIZE;
+
+//Name of the manifest entry inside a chunk file, archives without it are read as legacy chunk files
+pub const MANIFEST_ENTRY: &str = "manifest";
+pub const FORMAT_VERSION: u32 = 1;
+
+//Metadata stored next to the slices of a chunk file, written as one `key = value` pair per line:
+//
+//  format_version = 1
+//  dimensions = 16 16 16
+//  voxel_size = 0.1
+//  components = albedo normal roughness
+//  pivot = 8 0 8
+//  alpha_threshold = 0
+//  property.author = someone
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkManifest {
+    pub format_version: u32,
+    pub dimensions: UVec3,
+    pub voxel_size: f32,
+    pub components: Vec<String>,
+    //Point of the chunk, in voxels, placed at the chunk position
+    pub pivot: Vec3,
+    //Voxels are solid when their alpha is above this, 0 makes every voxel with a non zero alpha solid
+    pub alpha_threshold: u8,
+    pub properties: BTreeMap<String, String>,
+}
+
+impl ChunkManifest {
+    pub fn new(dimensions: UVec3) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            dimensions,
+            voxel_size: VOXEL_SIZE,
+            components: vec![VoxelComponent::Albedo.name().to_string()],
+            pivot: Vec3::ZERO,
+            alpha_threshold: 0,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ChunkContentLoadingError> {
+        let mut format_version = None;
+        let mut dimensions = None;
+        let mut manifest = Self::new(UVec3::ZERO);
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(invalid(format!("expected `key = value`, found `{}`", line))),
+            };
+
+            match key {
+                "format_version" => format_version = Some(parse_value::<u32>(key, value)?),
+                "dimensions" => {
+                    let [x, y, z] = parse_vector::<u32>(key, value)?;
+                    dimensions = Some(UVec3::new(x, y, z));
+                },
+                "voxel_size" => manifest.voxel_size = parse_value(key, value)?,
+                "components" => manifest.components = value.split_whitespace().map(str::to_string).collect(),
+                "pivot" => manifest.pivot = Vec3::from_array(parse_vector(key, value)?),
+                "alpha_threshold" => manifest.alpha_threshold = parse_value(key, value)?,
+                _ => match key.strip_prefix("property.") {
+                    Some(property) if !property.is_empty() => {
+                        manifest.properties.insert(property.to_string(), value.to_string());
+                    },
+                    _ => return Err(invalid(format!("unknown key `{}`", key))),
+                },
+            }
+        }
+
+        manifest.format_version = match format_version {
+            Some(version) => version,
+            None => return Err(invalid("missing `format_version`".to_string())),
+        };
+
+        manifest.dimensions = match dimensions {
+            Some(dimensions) => dimensions,
+            None => return Err(invalid("missing `dimensions`".to_string())),
+        };
+
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    pub fn validate(&self) -> Result<(), ChunkContentLoadingError> {
+        if self.format_version == 0 || self.format_version > FORMAT_VERSION {
+            return Err(ChunkContentLoadingError::UnsupportedFormatVersion(self.format_version));
+        }
+
+        if self.dimensions.x == 0 || self.dimensions.y == 0 || self.dimensions.z == 0 {
+            return Err(ChunkContentLoadingError::InvalidDimensions);
+        }
+
+        if !(self.voxel_size.is_finite() && self.voxel_size > 0.0) {
+            return Err(invalid(format!("invalid voxel size {}", self.voxel_size)));
+        }
+
+        if !self.pivot.is_finite() {
+            return Err(invalid("pivot is not finite".to_string()));
+        }
+
+        if !self.components.iter().any(|component| component == VoxelComponent::Albedo.name()) {
+            return Err(invalid("the albedo component is required".to_string()));
+        }
+
+        for (i, component) in self.components.iter().enumerate() {
+            if !VOXEL_COMPONENTS.contains(&component.as_str()) {
+                return Err(invalid(format!("unknown component `{}`", component)));
+            }
+
+            if self.components[..i].contains(component) {
+                return Err(invalid(format!("component `{}` is listed twice", component)));
+            }
+        }
+
+        //Layers are indexed in usize, the dimensions of a crafted manifest could overflow it
+        for component in self.components.iter().filter_map(|name| VoxelComponent::from_name(name)) {
+            if self.layer_size(component).is_none() {
+                return Err(ChunkContentLoadingError::InvalidDimensions);
+            }
+        }
+
+        for (key, value) in self.properties.iter() {
+            if key.contains(['=', '\n']) || key.trim() != key || value.contains('\n') || value.trim() != value {
+                return Err(invalid(format!("property `{}` cannot be stored in a manifest", key)));
+            }
+        }
+
+        Ok(())
+    }
+
+    //Bytes of the dense layer of a component, None when the product overflows
+    pub fn layer_size(&self, component: VoxelComponent) -> Option<usize> {
+        (self.dimensions.x as usize).checked_mul(self.dimensions.y as usize)?
+            .checked_mul(self.dimensions.z as usize)?
+            .checked_mul(component.bytes_per_voxel() as usize)
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        //Writing into a String cannot fail
+        writeln!(text, "format_version = {}", self.format_version).unwrap();
+        writeln!(text, "dimensions = {} {} {}", self.dimensions.x, self.dimensions.y, self.dimensions.z).unwrap();
+        writeln!(text, "voxel_size = {}", self.voxel_size).unwrap();
+        writeln!(text, "components = {}", self.components.join(" ")).unwrap();
+        writeln!(text, "pivot = {} {} {}", self.pivot.x, self.pivot.y, self.pivot.z).unwrap();
+        writeln!(text, "alpha_threshold = {}", self.alpha_threshold).unwrap();
+        for (key, value) in self.properties.iter() {
+            writeln!(text, "property.{} = {}", key, value).unwrap();
+        }
+        text
+    }
+}
+
+fn invalid(reason: String) -> ChunkContentLoadingError {
+    ChunkContentLoadingError::InvalidManifest(reason)
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ChunkContentLoadingError> {
+    match value.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(invalid(format!("invalid value `{}` for `{}`", value, key))),
+    }
+}
+
+fn parse_vector<T: std::str::FromStr>(key: &str, value: &str) -> Result<[T; 3], ChunkContentLoadingError> {
+    let values: Vec<&str> = value.split_whitespace().collect();
+    match values.as_slice() {
+        [x, y, z] => Ok([parse_value(key, x)?, parse_value(key, y)?, parse_value(key, z)?]),
+        _ => Err(invalid(format!("`{}` expects three values, found `{}`", key, value))),
+    }
+}
@@ -0,0 +1,151 @@
+use glam::{IVec3, UVec3};
+
+use super::chunk_content::voxel_is_solid;
+
+//Distances are stored in an R8Uint texture, voxels further than this from any solid voxel are clamped to it
+pub const MAX_DISTANCE: u8 = u8::MAX;
+
+//Chebyshev distance from every voxel to the closest solid voxel, 0 for solid voxels.
+//All the voxels less than `distance` away from an empty voxel on every axis are empty, which lets a ray leap across them.
+//The outside of the chunk counts as empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceField {
+    pub dimensions: UVec3,
+    //x varies fastest then y then z
+    pub distances: Vec<u8>,
+}
+
+impl DistanceField {
+    //Two pass chamfer transform over the 26 neighbours of each voxel, exact for the Chebyshev distance
+    pub fn from_albedo(albedo: &[u8], dimensions: UVec3, alpha_threshold: u8) -> Self {
+        let size = dimensions.as_ivec3();
+        let index = |position: IVec3| (position.x + position.y * size.x + position.z * size.x * size.y) as usize;
+
+        let mut distances: Vec<u32> = albedo.chunks_exact(4)
+            .map(|voxel| if voxel_is_solid(voxel, alpha_threshold) { 0 } else { MAX_DISTANCE as u32 })
+            .collect();
+
+        //Neighbours visited before a voxel in raster order, negated for the backward pass
+        let mut previous = Vec::<IVec3>::with_capacity(13);
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    if (z, y, x) < (0, 0, 0) {
+                        previous.push(IVec3::new(x, y, z));
+                    }
+                }
+            }
+        }
+
+        let mut relax = |position: IVec3, offsets: &mut dyn Iterator<Item = IVec3>| {
+            let mut distance = distances[index(position)];
+            for offset in offsets {
+                let neighbour = position + offset;
+                if neighbour.cmpge(IVec3::ZERO).all() && neighbour.cmplt(size).all() {
+                    distance = distance.min(distances[index(neighbour)] + 1);
+                }
+            }
+            distances[index(position)] = distance;
+        };
+
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    relax(IVec3::new(x, y, z), &mut previous.iter().copied());
+                }
+            }
+        }
+
+        for z in (0..size.z).rev() {
+            for y in (0..size.y).rev() {
+                for x in (0..size.x).rev() {
+                    relax(IVec3::new(x, y, z), &mut previous.iter().map(|offset| -*offset));
+                }
+            }
+        }
+
+        Self {
+            dimensions,
+            distances: distances.into_iter().map(|distance| distance.min(MAX_DISTANCE as u32) as u8).collect(),
+        }
+    }
+
+    //Lowers the distances as if the whole box from min included to max excluded had become solid, which keeps them conservative
+    //after voxels of the box became solid. Voxels that became empty keep their distance, the field then only skips less.
+    //Returns the box of voxels whose distance changed.
+    //Neighbouring distances differ by at most one, so a voxel k voxels away from the box can only change if one in every
+    //closer shell around the box did: the shells are lowered outwards until one of them is left unchanged.
+    pub fn lower_around(&mut self, min: UVec3, max: UVec3) -> Option<(UVec3, UVec3)> {
+        let mut changed: Option<(UVec3, UVec3)> = None;
+
+        for k in 0..MAX_DISTANCE as u32 {
+            let shell_min = min.saturating_sub(UVec3::splat(k));
+            let shell_max = (max + k).min(self.dimensions);
+            let mut lowered = false;
+
+            for z in shell_min.z..shell_max.z {
+                for y in shell_min.y..shell_max.y {
+                    //Rows crossing the inside of the shell can only have its two ends in it
+                    let row_distance = axis_distance(y, min.y, max.y).max(axis_distance(z, min.z, max.z));
+                    let step = match row_distance == k {
+                        true => 1,
+                        false => (shell_max.x - shell_min.x - 1).max(1),
+                    };
+
+                    for x in (shell_min.x..shell_max.x).step_by(step as usize) {
+                        let distance = row_distance.max(axis_distance(x, min.x, max.x));
+                        if distance != k {
+                            continue;
+                        }
+
+                        let position = UVec3::new(x, y, z);
+                        let distance = distance as u8;
+
+                        let index = self.index(position);
+                        if distance < self.distances[index] {
+                            self.distances[index] = distance;
+                            lowered = true;
+                            changed = Some(match changed {
+                                Some((changed_min, changed_max)) => (changed_min.min(position), changed_max.max(position + 1)),
+                                None => (position, position + 1),
+                            });
+                        }
+                    }
+                }
+            }
+
+            if !lowered {
+                break;
+            }
+        }
+
+        changed
+    }
+
+    pub fn distance(&self, position: UVec3) -> Option<u8> {
+        if position.x >= self.dimensions.x || position.y >= self.dimensions.y || position.z >= self.dimensions.z {
+            return None;
+        }
+
+        Some(self.distances[self.index(position)])
+    }
+
+    fn index(&self, position: UVec3) -> usize {
+        position.x as usize + (position.y as usize + position.z as usize * self.dimensions.y as usize) * self.dimensions.x as usize
+    }
+
+    //Empty box of voxels centered on a voxel, None when the ray cannot leap further than the voxel itself
+    pub fn empty_box(&self, position: UVec3) -> Option<(IVec3, IVec3)> {
+        let radius = self.distance(position)? as i32 - 1;
+        if radius <= 0 {
+            return None;
+        }
+
+        Some((position.as_ivec3() - radius, position.as_ivec3() + radius))
+    }
+}
+
+//Distance along one axis from a coordinate to the range from min included to max excluded
+fn axis_distance(coordinate: u32, min: u32, max: u32) -> u32 {
+    min.saturating_sub(coordinate).max(coordinate.saturating_sub(max - 1))
+}
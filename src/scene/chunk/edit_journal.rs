@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+
+use glam::UVec3;
+
+//History kept by default for each chunk, in bytes
+pub const DEFAULT_HISTORY_LIMIT: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxelChange {
+    //Index of the voxel in the chunk, x varies fastest then y then z
+    pub index: u64,
+    pub before: [u8; 4],
+    pub after: [u8; 4],
+}
+
+//Voxels changed by one edit, only the voxels whose value actually changed are stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionDelta {
+    //Box of the edit, from min included to max excluded
+    pub min: UVec3,
+    pub max: UVec3,
+    pub changes: Vec<VoxelChange>,
+}
+
+impl RegionDelta {
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.changes.len() * std::mem::size_of::<VoxelChange>()
+    }
+}
+
+//Edits undone and redone together
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transaction {
+    pub deltas: Vec<RegionDelta>,
+}
+
+impl Transaction {
+    pub fn memory_size(&self) -> usize {
+        std::mem::size_of::<Self>() + self.deltas.iter().map(RegionDelta::memory_size).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.iter().all(|delta| delta.changes.is_empty())
+    }
+}
+
+//Undo and redo stacks of the edits of a chunk.
+//Edits made outside of a transaction are undone one by one, transactions can be nested and are committed by the outermost commit.
+pub struct EditJournal {
+    undo: VecDeque<Transaction>,
+    redo: Vec<Transaction>,
+    open: Option<Transaction>,
+    //Set when the open transaction alone outgrew the limit, it cannot be undone in part so it is dropped along with its next edits
+    open_overflowed: bool,
+    depth: u32,
+    memory_limit: usize,
+    //Undo and redo stacks and open transaction
+    memory_size: usize,
+}
+
+impl Default for EditJournal {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl EditJournal {
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            open: None,
+            open_overflowed: false,
+            depth: 0,
+            memory_limit,
+            memory_size: 0,
+        }
+    }
+
+    pub fn begin_transaction(&mut self) {
+        self.depth += 1;
+        if self.open.is_none() {
+            let transaction = Transaction::default();
+            self.memory_size += transaction.memory_size();
+            self.open = Some(transaction);
+        }
+    }
+
+    //Returns false when no transaction is open
+    pub fn commit_transaction(&mut self) -> bool {
+        if self.depth == 0 {
+            return false;
+        }
+
+        self.depth -= 1;
+        if self.depth == 0 {
+            if let Some(transaction) = self.open.take() {
+                self.memory_size -= transaction.memory_size();
+                if !std::mem::take(&mut self.open_overflowed) {
+                    self.push_undo(transaction);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.depth > 0
+    }
+
+    //A new edit makes the undone edits impossible to redo.
+    //Edits of an open transaction count against the limit as they are recorded, the oldest history makes room for them.
+    pub fn record(&mut self, delta: RegionDelta) {
+        if delta.changes.is_empty() {
+            return;
+        }
+
+        self.memory_size -= self.redo.drain(..).map(|transaction| transaction.memory_size()).sum::<usize>();
+
+        match &mut self.open {
+            Some(_) if self.open_overflowed => {},
+            Some(transaction) => {
+                self.memory_size += delta.memory_size();
+                transaction.deltas.push(delta);
+                self.evict();
+            },
+            None => self.push_undo(Transaction { deltas: vec![delta] }),
+        }
+    }
+
+    //Commits the open transactions, if any, and returns the last transaction to undo, which becomes redoable
+    pub fn undo(&mut self) -> Option<&Transaction> {
+        self.close_transactions();
+
+        let transaction = self.undo.pop_back()?;
+        self.redo.push(transaction);
+        self.redo.last()
+    }
+
+    //Returns the last undone transaction, which becomes undoable again
+    pub fn redo(&mut self) -> Option<&Transaction> {
+        self.close_transactions();
+
+        let transaction = self.redo.pop()?;
+        self.undo.push_back(transaction);
+        self.undo.back()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.open.as_ref().is_some_and(|transaction| !transaction.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = None;
+        self.open_overflowed = false;
+        self.depth = 0;
+        self.memory_size = 0;
+    }
+
+    //Bytes used by the undo and redo stacks and by the open transaction
+    pub fn memory_size(&self) -> usize {
+        self.memory_size
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.evict();
+    }
+
+    fn close_transactions(&mut self) {
+        while self.commit_transaction() {}
+    }
+
+    fn push_undo(&mut self, transaction: Transaction) {
+        if transaction.is_empty() {
+            return;
+        }
+
+        self.memory_size += transaction.memory_size();
+        self.undo.push_back(transaction);
+        self.evict();
+    }
+
+    //Forgets the oldest edits until the history fits in the limit, a single transaction larger than the limit is not kept either.
+    //An open transaction larger than the limit is dropped until it is committed.
+    fn evict(&mut self) {
+        while self.memory_size > self.memory_limit {
+            let oldest = match self.undo.pop_front() {
+                Some(oldest) => oldest,
+                None => match self.redo.is_empty() {
+                    true => break,
+                    false => self.redo.remove(0),
+                },
+            };
+            self.memory_size -= oldest.memory_size();
+        }
+
+        if self.memory_size > self.memory_limit {
+            if let Some(transaction) = &mut self.open {
+                self.memory_size -= transaction.memory_size();
+                transaction.deltas = Vec::new();
+                self.memory_size += transaction.memory_size();
+                self.open_overflowed = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,629 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use glam::{Mat3, Quat, UVec3, Vec3};
+
+use super::chunk_content::{voxel_is_solid, ChunkContentLoadingError, ChunkContentSavingError, RawChunkContent};
+use super::{ChunkData, UnloadedChunk};
+
+//MagicaVoxel files are Z up and right handed, egde is Y up and left handed: swapping Y and Z converts between both
+const VOX_TO_EGDE: Mat3 = Mat3::from_cols(Vec3::X, Vec3::Z, Vec3::Y);
+
+pub const VOX_VERSION: i32 = 150;
+//MagicaVoxel models cannot be larger than this on any axis
+pub const MAX_MODEL_SIZE: u32 = 256;
+//Colour index 0 is empty, leaving 255 usable palette entries
+pub const MAX_PALETTE_SIZE: usize = 255;
+
+//Palette used by MagicaVoxel when a file has no RGBA chunk, indexed by colour index (0 is empty)
+pub fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let mut i = 1;
+
+    //6x6x6 colour cube without black, blue varies fastest
+    let levels = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    for r in levels {
+        for g in levels {
+            for b in levels {
+                if r == 0 && g == 0 && b == 0 {
+                    continue;
+                }
+                palette[i] = [r, g, b, 0xff];
+                i += 1;
+            }
+        }
+    }
+
+    //Red, green, blue and grey ramps
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channel in 0..4 {
+        for level in ramp {
+            palette[i] = match channel {
+                0 => [level, 0, 0, 0xff],
+                1 => [0, level, 0, 0xff],
+                2 => [0, 0, level, 0xff],
+                _ => [level, level, level, 0xff],
+            };
+            i += 1;
+        }
+    }
+
+    palette
+}
+
+pub fn read_vox_file(path: &Path) -> Result<Vec<UnloadedChunk>, ChunkContentLoadingError> {
+    match fs::read(path) {
+        Ok(bytes) => parse_vox(&bytes),
+        Err(err) => Err(ChunkContentLoadingError::FailedToReadChunkFile(err)),
+    }
+}
+
+//Every shape of the scene graph becomes one chunk placed by its nTRN transforms, files without a scene graph get one chunk per model at the origin
+pub fn parse_vox(bytes: &[u8]) -> Result<Vec<UnloadedChunk>, ChunkContentLoadingError> {
+    let mut reader = VoxReader { bytes, position: 0 };
+
+    if reader.read_bytes(4)? != b"VOX " {
+        return Err(invalid("missing VOX header"));
+    }
+    reader.read_i32()?;
+
+    let (main_id, main_content, main_children) = reader.read_chunk()?;
+    if main_id != *b"MAIN" || !main_content.is_empty() {
+        return Err(invalid("missing MAIN chunk"));
+    }
+
+    let mut sizes = Vec::<UVec3>::new();
+    let mut models = Vec::<Vec<[u8; 4]>>::new();
+    let mut palette = default_palette();
+    let mut nodes = HashMap::<i32, SceneNode>::new();
+
+    let mut children = VoxReader { bytes: main_children, position: 0 };
+    while !children.is_at_end() {
+        let (id, content, _) = children.read_chunk()?;
+        let mut content = VoxReader { bytes: content, position: 0 };
+
+        match &id {
+            b"SIZE" => {
+                let size = UVec3::new(content.read_u32()?, content.read_u32()?, content.read_u32()?);
+                if size.x == 0 || size.y == 0 || size.z == 0 || size.cmpgt(UVec3::splat(MAX_MODEL_SIZE)).any() {
+                    return Err(ChunkContentLoadingError::InvalidDimensions);
+                }
+                sizes.push(size);
+            },
+            b"XYZI" => {
+                let count = content.read_u32()?;
+                //Checked before allocating, a corrupted count could otherwise ask for gigabytes
+                if count as usize > content.remaining() / 4 {
+                    return Err(invalid("more voxels than the XYZI chunk holds"));
+                }
+                let mut voxels = Vec::<[u8; 4]>::with_capacity(count as usize);
+                for _ in 0..count {
+                    let voxel = content.read_bytes(4)?;
+                    voxels.push([voxel[0], voxel[1], voxel[2], voxel[3]]);
+                }
+                models.push(voxels);
+            },
+            b"RGBA" => {
+                //The colour of index i is stored at entry i - 1
+                for entry in palette.iter_mut().skip(1) {
+                    let color = content.read_bytes(4)?;
+                    *entry = [color[0], color[1], color[2], color[3]];
+                }
+            },
+            b"nTRN" => {
+                let node_id = content.read_i32()?;
+                content.read_dict()?;
+                let child = content.read_i32()?;
+                content.read_i32()?; //Reserved
+                content.read_i32()?; //Layer
+                let frame_count = content.read_i32()?;
+
+                //Only the first frame is used, the others are animation keys
+                let frame = if frame_count > 0 { content.read_dict()? } else { HashMap::new() };
+                nodes.insert(node_id, SceneNode::Transform { transform: VoxTransform::from_frame(&frame)?, child });
+            },
+            b"nGRP" => {
+                let node_id = content.read_i32()?;
+                content.read_dict()?;
+                let child_count = content.read_i32()?;
+                let mut group_children = Vec::<i32>::new();
+                for _ in 0..child_count {
+                    group_children.push(content.read_i32()?);
+                }
+                nodes.insert(node_id, SceneNode::Group(group_children));
+            },
+            b"nSHP" => {
+                let node_id = content.read_i32()?;
+                content.read_dict()?;
+                let model_count = content.read_i32()?;
+                let mut shape_models = Vec::<usize>::new();
+                for _ in 0..model_count {
+                    shape_models.push(content.read_i32()? as usize);
+                    content.read_dict()?;
+                }
+                nodes.insert(node_id, SceneNode::Shape(shape_models));
+            },
+            //Materials, layers, cameras, notes...
+            _ => {},
+        }
+    }
+
+    if sizes.len() != models.len() {
+        return Err(invalid("every SIZE chunk needs a XYZI chunk"));
+    }
+
+    let contents = sizes.iter()
+        .zip(models.iter())
+        .map(|(size, voxels)| model_to_raw_content(*size, voxels, &palette))
+        .collect::<Result<Vec<RawChunkContent>, ChunkContentLoadingError>>()?;
+
+    let mut placed_models = Vec::<(usize, VoxTransform)>::new();
+    if nodes.is_empty() {
+        placed_models.extend((0..contents.len()).map(|model| (model, VoxTransform::IDENTITY)));
+    } else {
+        collect_shapes(&nodes, 0, VoxTransform::IDENTITY, 0, &mut placed_models)?;
+    }
+
+    let mut chunks = Vec::<UnloadedChunk>::new();
+    for (model, transform) in placed_models {
+        let raw = match contents.get(model) {
+            Some(raw) => raw.clone(),
+            None => return Err(invalid("shape references a missing model")),
+        };
+
+        chunks.push(place_model(raw, transform));
+    }
+
+    Ok(chunks)
+}
+
+pub fn write_vox_file(albedo: &[u8], dimensions: UVec3, alpha_threshold: u8, path: &Path) -> Result<(), ChunkContentSavingError> {
+    let bytes = export_vox(albedo, dimensions, alpha_threshold)?;
+    match fs::write(path, bytes) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(ChunkContentSavingError::FailedToWriteChunkFile(err)),
+    }
+}
+
+//Voxels that are not solid under the alpha threshold are left empty, see voxel_is_solid.
+//The colours of the others are quantized into a palette of at most 255 entries.
+//Volumes larger than 256 voxels on an axis are split into several models placed so that the file reimports at the same place.
+pub fn export_vox(albedo: &[u8], dimensions: UVec3, alpha_threshold: u8) -> Result<Vec<u8>, ChunkContentSavingError> {
+    //Computed in usize, a 1024x1024x1024 chunk already has 2^32 bytes of RGBA albedo
+    let size = (dimensions.x as usize).checked_mul(dimensions.y as usize)
+        .and_then(|count| count.checked_mul(dimensions.z as usize))
+        .and_then(|count| count.checked_mul(4));
+    if dimensions.x == 0 || dimensions.y == 0 || dimensions.z == 0 || size != Some(albedo.len()) {
+        return Err(ChunkContentSavingError::InvalidDimensions);
+    }
+
+    let voxel_at = |position: UVec3| {
+        let index = (position.x as usize + (position.y as usize + position.z as usize * dimensions.y as usize) * dimensions.x as usize) * 4;
+        [albedo[index], albedo[index + 1], albedo[index + 2], albedo[index + 3]]
+    };
+
+    let mut color_counts = HashMap::<[u8; 4], u32>::new();
+    for color in albedo.chunks_exact(4) {
+        if voxel_is_solid(color, alpha_threshold) {
+            *color_counts.entry([color[0], color[1], color[2], color[3]]).or_insert(0) += 1;
+        }
+    }
+
+    let palette = quantize(&color_counts, MAX_PALETTE_SIZE);
+    let color_indices: HashMap<[u8; 4], u8> = color_counts.keys()
+        .map(|color| (*color, nearest_palette_index(&palette, *color)))
+        .collect();
+
+    let vox_dimensions = (VOX_TO_EGDE * dimensions.as_vec3()).as_uvec3();
+    let model_counts = (vox_dimensions + MAX_MODEL_SIZE - 1) / MAX_MODEL_SIZE;
+
+    let mut models = Vec::<u8>::new();
+    let mut scene_graph = Vec::<u8>::new();
+    let mut model_ids = Vec::<i32>::new();
+
+    for block_z in 0..model_counts.z {
+        for block_y in 0..model_counts.y {
+            for block_x in 0..model_counts.x {
+                let origin = UVec3::new(block_x, block_y, block_z) * MAX_MODEL_SIZE;
+                let size = (vox_dimensions - origin).min(UVec3::splat(MAX_MODEL_SIZE));
+
+                let mut voxels = Vec::<u8>::new();
+                for z in 0..size.z {
+                    for y in 0..size.y {
+                        for x in 0..size.x {
+                            let local = UVec3::new(x, y, z);
+                            let color = voxel_at((VOX_TO_EGDE * (origin + local).as_vec3()).as_uvec3());
+                            if voxel_is_solid(&color, alpha_threshold) {
+                                voxels.extend([x as u8, y as u8, z as u8, color_indices[&color]]);
+                            }
+                        }
+                    }
+                }
+
+                let mut size_content = Vec::<u8>::new();
+                write_ints(&mut size_content, &[size.x as i32, size.y as i32, size.z as i32]);
+                write_chunk(&mut models, b"SIZE", &size_content, &[]);
+
+                let mut xyzi_content = Vec::<u8>::new();
+                write_ints(&mut xyzi_content, &[(voxels.len() / 4) as i32]);
+                xyzi_content.extend(voxels);
+                write_chunk(&mut models, b"XYZI", &xyzi_content, &[]);
+
+                //MagicaVoxel places the voxel at the floor of the half size of a model at its translation
+                let model_id = model_ids.len() as i32;
+                let translation = (origin + size / 2).as_ivec3();
+                let transform_id = 2 + model_id * 2;
+                write_transform(&mut scene_graph, transform_id, transform_id + 1, 0, &[("_t", &format!("{} {} {}", translation.x, translation.y, translation.z))]);
+                write_shape(&mut scene_graph, transform_id + 1, model_id);
+                model_ids.push(transform_id);
+            }
+        }
+    }
+
+    let mut palette_content = Vec::<u8>::with_capacity(256 * 4);
+    for i in 0..256 {
+        palette_content.extend(palette.get(i).copied().unwrap_or([0, 0, 0, 0]));
+    }
+
+    //Root transform and a group holding one transform and shape per model
+    let mut main_children = models;
+    write_transform(&mut main_children, 0, 1, -1, &[]);
+    let mut group_content = Vec::<u8>::new();
+    write_ints(&mut group_content, &[1]);
+    write_dict(&mut group_content, &[]);
+    write_ints(&mut group_content, &[model_ids.len() as i32]);
+    write_ints(&mut group_content, &model_ids);
+    write_chunk(&mut main_children, b"nGRP", &group_content, &[]);
+    main_children.extend(scene_graph);
+    write_chunk(&mut main_children, b"RGBA", &palette_content, &[]);
+
+    let mut bytes = b"VOX ".to_vec();
+    write_ints(&mut bytes, &[VOX_VERSION]);
+    write_chunk(&mut bytes, b"MAIN", &[], &main_children);
+    Ok(bytes)
+}
+
+//Median cut: the box of colours with the widest channel is split at its weighted median until there are enough boxes
+fn quantize(color_counts: &HashMap<[u8; 4], u32>, max_colors: usize) -> Vec<[u8; 4]> {
+    let mut colors: Vec<([u8; 4], u32)> = color_counts.iter().map(|(color, count)| (*color, *count)).collect();
+    //Sorted so the palette does not depend on the hash map order
+    colors.sort();
+
+    if colors.len() <= max_colors {
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    let channel_range = |colors: &[([u8; 4], u32)], channel: usize| {
+        let min = colors.iter().map(|(color, _)| color[channel]).min().unwrap_or(0);
+        let max = colors.iter().map(|(color, _)| color[channel]).max().unwrap_or(0);
+        max - min
+    };
+
+    let widest_channel = |colors: &[([u8; 4], u32)]| {
+        (0..4).map(|channel| (channel_range(colors, channel), channel)).max().unwrap_or((0, 0))
+    };
+
+    let mut boxes = vec![colors];
+    while boxes.len() < max_colors {
+        let split = boxes.iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (widest_channel(colors), i))
+            .max();
+
+        let ((range, channel), i) = match split {
+            Some(split) => split,
+            None => break,
+        };
+        if range == 0 {
+            break;
+        }
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_by_key(|(color, _)| color[channel]);
+
+        let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+        let mut accumulated = 0;
+        let mut median = 1;
+        for (j, (_, count)) in colors.iter().enumerate() {
+            accumulated += *count as u64;
+            if accumulated * 2 >= total {
+                median = (j + 1).clamp(1, colors.len() - 1);
+                break;
+            }
+        }
+
+        let upper = colors.split_off(median);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes.iter()
+        .map(|colors| {
+            let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+            let mut average = [0; 4];
+            for (channel, value) in average.iter_mut().enumerate() {
+                let sum: u64 = colors.iter().map(|(color, count)| color[channel] as u64 * *count as u64).sum();
+                *value = ((sum + total / 2) / total) as u8;
+            }
+            average
+        })
+        .collect()
+}
+
+//Palette entry i is colour index i + 1
+fn nearest_palette_index(palette: &[[u8; 4]], color: [u8; 4]) -> u8 {
+    let distance = |entry: &[u8; 4]| -> u32 {
+        entry.iter().zip(color.iter()).map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32).sum()
+    };
+
+    let nearest = palette.iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    nearest as u8 + 1
+}
+
+fn write_ints(bytes: &mut Vec<u8>, values: &[i32]) {
+    for value in values {
+        bytes.extend(value.to_le_bytes());
+    }
+}
+
+fn write_dict(bytes: &mut Vec<u8>, pairs: &[(&str, &str)]) {
+    write_ints(bytes, &[pairs.len() as i32]);
+    for (key, value) in pairs {
+        write_ints(bytes, &[key.len() as i32]);
+        bytes.extend(key.as_bytes());
+        write_ints(bytes, &[value.len() as i32]);
+        bytes.extend(value.as_bytes());
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend(id);
+    write_ints(bytes, &[content.len() as i32, children.len() as i32]);
+    bytes.extend(content);
+    bytes.extend(children);
+}
+
+fn write_transform(bytes: &mut Vec<u8>, node_id: i32, child: i32, layer: i32, frame: &[(&str, &str)]) {
+    let mut content = Vec::<u8>::new();
+    write_ints(&mut content, &[node_id]);
+    write_dict(&mut content, &[]);
+    //Child, reserved id, layer and frame count
+    write_ints(&mut content, &[child, -1, layer, 1]);
+    write_dict(&mut content, frame);
+    write_chunk(bytes, b"nTRN", &content, &[]);
+}
+
+fn write_shape(bytes: &mut Vec<u8>, node_id: i32, model_id: i32) {
+    let mut content = Vec::<u8>::new();
+    write_ints(&mut content, &[node_id]);
+    write_dict(&mut content, &[]);
+    write_ints(&mut content, &[1, model_id]);
+    write_dict(&mut content, &[]);
+    write_chunk(bytes, b"nSHP", &content, &[]);
+}
+
+fn model_to_raw_content(size: UVec3, voxels: &[[u8; 4]], palette: &[[u8; 4]; 256]) -> Result<RawChunkContent, ChunkContentLoadingError> {
+    let dimensions = (VOX_TO_EGDE * size.as_vec3()).as_uvec3();
+    let mut albedo = vec![0; dimensions.x as usize * dimensions.y as usize * dimensions.z as usize * 4];
+
+    for voxel in voxels {
+        let position = UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32);
+        if position.cmpge(size).any() {
+            return Err(invalid("voxel outside of its model"));
+        }
+
+        //Colour index 0 is empty
+        if voxel[3] == 0 {
+            continue;
+        }
+
+        let position = (VOX_TO_EGDE * position.as_vec3()).as_uvec3();
+        let index = (position.x as usize + position.y as usize * dimensions.x as usize + position.z as usize * dimensions.x as usize * dimensions.y as usize) * 4;
+        albedo[index..index + 4].copy_from_slice(&palette[voxel[3] as usize]);
+    }
+
+    let mut raw = RawChunkContent::new(albedo, dimensions);
+    //MagicaVoxel rotates models around the voxel at the floor of their half size
+    raw.manifest.pivot = (dimensions / 2).as_vec3() + 0.5;
+    Ok(raw)
+}
+
+//Converts a MagicaVoxel transform into the chunk placement, mirrored models are flipped along their x axis
+fn place_model(mut raw: RawChunkContent, transform: VoxTransform) -> UnloadedChunk {
+    let mut rotation = VOX_TO_EGDE * transform.rotation * VOX_TO_EGDE;
+    let translation = VOX_TO_EGDE * transform.translation;
+
+    if rotation.determinant() < 0.0 {
+        let dimensions = raw.manifest.dimensions;
+        for z in 0..dimensions.z {
+            for y in 0..dimensions.y {
+                let row = ((y * dimensions.x + z * dimensions.x * dimensions.y) * 4) as usize;
+                let row = &mut raw.albedo[row..row + (dimensions.x * 4) as usize];
+                for x in 0..(dimensions.x / 2) as usize {
+                    let mirrored = dimensions.x as usize - 1 - x;
+                    for channel in 0..4 {
+                        row.swap(x * 4 + channel, mirrored * 4 + channel);
+                    }
+                }
+            }
+        }
+
+        raw.manifest.pivot.x = dimensions.x as f32 - raw.manifest.pivot.x;
+        rotation *= Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0));
+    }
+
+    //Voxel cells of the MagicaVoxel world start at integer coordinates, their centres are half a voxel further
+    let position = (translation + 0.5) * raw.manifest.voxel_size;
+
+    UnloadedChunk::from_raw_content(raw, ChunkData {
+        position,
+        rotation: Quat::from_mat3(&rotation).normalize(),
+    })
+}
+
+fn collect_shapes(nodes: &HashMap<i32, SceneNode>, node_id: i32, transform: VoxTransform, depth: u32, placed_models: &mut Vec<(usize, VoxTransform)>) -> Result<(), ChunkContentLoadingError> {
+    //A well formed scene graph is a tree, this guards against cycles
+    if depth > 1024 {
+        return Err(invalid("scene graph is too deep"));
+    }
+
+    match nodes.get(&node_id) {
+        Some(SceneNode::Transform { transform: node_transform, child }) => {
+            collect_shapes(nodes, *child, transform.then(node_transform), depth + 1, placed_models)
+        },
+        Some(SceneNode::Group(children)) => {
+            for child in children {
+                collect_shapes(nodes, *child, transform, depth + 1, placed_models)?;
+            }
+            Ok(())
+        },
+        Some(SceneNode::Shape(models)) => {
+            placed_models.extend(models.iter().map(|model| (*model, transform)));
+            Ok(())
+        },
+        None => Err(invalid("scene graph references a missing node")),
+    }
+}
+
+enum SceneNode {
+    Transform { transform: VoxTransform, child: i32 },
+    Group(Vec<i32>),
+    Shape(Vec<usize>),
+}
+
+//Transform of a nTRN node in MagicaVoxel coordinates: x -> rotation * x + translation
+#[derive(Debug, Clone, Copy)]
+struct VoxTransform {
+    rotation: Mat3,
+    translation: Vec3,
+}
+
+impl VoxTransform {
+    const IDENTITY: Self = Self { rotation: Mat3::IDENTITY, translation: Vec3::ZERO };
+
+    fn from_frame(frame: &HashMap<String, String>) -> Result<Self, ChunkContentLoadingError> {
+        let rotation = match frame.get("_r") {
+            Some(value) => match value.trim().parse::<u8>() {
+                Ok(packed) => unpack_rotation(packed)?,
+                Err(_) => return Err(invalid("invalid rotation")),
+            },
+            None => Mat3::IDENTITY,
+        };
+
+        let translation = match frame.get("_t") {
+            Some(value) => {
+                let values = value.split_whitespace()
+                    .map(|value| value.parse::<i32>())
+                    .collect::<Result<Vec<i32>, _>>();
+                match values.as_deref() {
+                    Ok([x, y, z]) => Vec3::new(*x as f32, *y as f32, *z as f32),
+                    _ => return Err(invalid("invalid translation")),
+                }
+            },
+            None => Vec3::ZERO,
+        };
+
+        Ok(Self { rotation, translation })
+    }
+
+    //Applies `child` first, then `self`
+    fn then(&self, child: &VoxTransform) -> Self {
+        Self {
+            rotation: self.rotation * child.rotation,
+            translation: self.rotation * child.translation + self.translation,
+        }
+    }
+}
+
+//Bits 0-1 and 2-3 give the column of the non zero entry of the first two rows, bits 4 to 6 the sign of each row
+fn unpack_rotation(packed: u8) -> Result<Mat3, ChunkContentLoadingError> {
+    let first = (packed & 0b11) as usize;
+    let second = ((packed >> 2) & 0b11) as usize;
+    if first > 2 || second > 2 || first == second {
+        return Err(invalid("invalid rotation"));
+    }
+    let third = 3 - first - second;
+
+    let mut rows = [Vec3::ZERO; 3];
+    for (row, column) in [first, second, third].into_iter().enumerate() {
+        rows[row][column] = if packed & (1 << (4 + row)) != 0 { -1.0 } else { 1.0 };
+    }
+
+    Ok(Mat3::from_cols(rows[0], rows[1], rows[2]).transpose())
+}
+
+fn invalid(reason: &str) -> ChunkContentLoadingError {
+    ChunkContentLoadingError::InvalidVoxFile(reason.to_string())
+}
+
+type VoxChunk<'a> = ([u8; 4], &'a [u8], &'a [u8]);
+
+struct VoxReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> VoxReader<'a> {
+    fn is_at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.position)
+    }
+
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], ChunkContentLoadingError> {
+        match self.bytes.get(self.position..self.position.saturating_add(count)) {
+            Some(bytes) => {
+                self.position += count;
+                Ok(bytes)
+            },
+            None => Err(invalid("unexpected end of file")),
+        }
+    }
+
+    fn read_i32(&mut self) -> Result<i32, ChunkContentLoadingError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ChunkContentLoadingError> {
+        match u32::try_from(self.read_i32()?) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(invalid("negative size")),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<String, ChunkContentLoadingError> {
+        let length = self.read_u32()? as usize;
+        match String::from_utf8(self.read_bytes(length)?.to_vec()) {
+            Ok(string) => Ok(string),
+            Err(_) => Err(invalid("string is not valid UTF-8")),
+        }
+    }
+
+    fn read_dict(&mut self) -> Result<HashMap<String, String>, ChunkContentLoadingError> {
+        let count = self.read_u32()?;
+        let mut dict = HashMap::<String, String>::new();
+        for _ in 0..count {
+            let key = self.read_string()?;
+            dict.insert(key, self.read_string()?);
+        }
+        Ok(dict)
+    }
+
+    //Returns the id, the content and the children of the next chunk
+    fn read_chunk(&mut self) -> Result<VoxChunk<'a>, ChunkContentLoadingError> {
+        let id = self.read_bytes(4)?;
+        let content_size = self.read_u32()? as usize;
+        let children_size = self.read_u32()? as usize;
+        let content = self.read_bytes(content_size)?;
+        let children = self.read_bytes(children_size)?;
+        Ok(([id[0], id[1], id[2], id[3]], content, children))
+    }
+}
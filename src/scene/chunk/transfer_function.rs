@@ -0,0 +1,101 @@
+use wgpu::{Device, Extent3d, Queue, Texture, TextureFormat, TextureView};
+
+//Number of texels of the transfer function texture, one per density value of the R8 density layer
+pub const TRANSFER_FUNCTION_SIZE: u32 = 256;
+pub const TRANSFER_FUNCTION_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+//Colour and opacity at a density in [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlPoint {
+    pub density: f32,
+    pub color: [u8; 4],
+}
+
+impl ControlPoint {
+    pub fn new(density: f32, color: [u8; 4]) -> Self {
+        Self { density, color }
+    }
+}
+
+//Maps the density of a volume to a colour and an opacity, interpolated linearly between control points.
+//The opacity is the one of a voxel-long segment of that density, the ray marcher corrects it for its step size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferFunction {
+    //Sorted by density
+    points: Vec<ControlPoint>,
+}
+
+impl Default for TransferFunction {
+    //Transparent black at density 0 up to opaque white at density 1
+    fn default() -> Self {
+        Self::new(vec![ControlPoint::new(0.0, [0, 0, 0, 0]), ControlPoint::new(1.0, [255, 255, 255, 255])])
+    }
+}
+
+impl TransferFunction {
+    //Densities before the first point and after the last one take their colour, no points at all is fully transparent
+    pub fn new(mut points: Vec<ControlPoint>) -> Self {
+        points.sort_by(|a, b| a.density.total_cmp(&b.density));
+        Self { points }
+    }
+
+    pub fn points(&self) -> &[ControlPoint] {
+        &self.points
+    }
+
+    pub fn evaluate(&self, density: f32) -> [u8; 4] {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return [0; 4];
+        };
+
+        if density <= first.density {
+            return first.color;
+        }
+
+        match self.points.windows(2).find(|pair| density <= pair[1].density) {
+            Some([from, to]) => {
+                let t = (density - from.density) / (to.density - from.density);
+                std::array::from_fn(|i| (from.color[i] as f32 + (to.color[i] as f32 - from.color[i] as f32) * t).round() as u8)
+            },
+            _ => last.color,
+        }
+    }
+
+    //RGBA8 texels of the transfer function texture, texel i is the density i / (TRANSFER_FUNCTION_SIZE - 1)
+    pub fn texels(&self) -> Vec<u8> {
+        (0..TRANSFER_FUNCTION_SIZE)
+            .flat_map(|i| self.evaluate(i as f32 / (TRANSFER_FUNCTION_SIZE - 1) as f32))
+            .collect()
+    }
+
+    pub fn create_texture(&self, device: &Device, queue: &Queue) -> (Texture, TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Chunk transfer function"),
+            size: Extent3d { width: TRANSFER_FUNCTION_SIZE, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            //A row of a 2D texture, 1D textures are not supported by every backend
+            dimension: wgpu::TextureDimension::D2,
+            format: TRANSFER_FUNCTION_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.write_texture(queue, &texture);
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn write_texture(&self, queue: &Queue, texture: &Texture) {
+        queue.write_texture(
+            texture.as_image_copy(),
+            &self.texels(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(TRANSFER_FUNCTION_SIZE * 4),
+                rows_per_image: Some(1),
+            },
+            texture.size(),
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use glam::{IVec3, UVec3, Vec3};
+
+use super::distance_field::DistanceField;
+
+//CPU version of the voxel by voxel traversal of chunk_shader.wgsl, in voxel units with the chunk spanning [0, dimensions].
+//Keep both in sync: the CPU queries and the step count benchmark rely on it behaving like the shader.
+#[derive(Debug, Clone, Copy)]
+pub struct VoxelTraversal {
+    //Voxel the ray is in
+    pub position: IVec3,
+    //Distance from the origin to the face through which the ray entered the current voxel
+    pub distance: f32,
+    //Axes crossed to enter the current voxel, zero for the voxel of the origin
+    pub mask: IVec3,
+    pub step: IVec3,
+    side_dist: Vec3,
+    delta_dist: Vec3,
+}
+
+impl VoxelTraversal {
+    //The direction has to be normalized for distances to be in voxels
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        let position = origin.floor().as_ivec3();
+        let step = IVec3::new(sign(direction.x), sign(direction.y), sign(direction.z));
+        let delta_dist = direction.recip().abs();
+
+        let side_dist = Vec3::new(
+            side(position.x, origin.x, step.x, delta_dist.x),
+            side(position.y, origin.y, step.y, delta_dist.y),
+            side(position.z, origin.z, step.z, delta_dist.z),
+        );
+
+        Self {
+            position,
+            distance: 0.0,
+            mask: IVec3::ZERO,
+            step,
+            side_dist,
+            delta_dist,
+        }
+    }
+
+    //Moves to the next voxel along the ray, crossing several axes at once when the ray goes exactly through an edge or a corner
+    pub fn step(&mut self) {
+        let min = self.side_dist.min_element();
+        self.mask = IVec3::new(
+            (self.side_dist.x <= min) as i32,
+            (self.side_dist.y <= min) as i32,
+            (self.side_dist.z <= min) as i32,
+        );
+
+        self.distance = min;
+        self.side_dist += self.mask.as_vec3() * self.delta_dist;
+        self.position += self.mask * self.step;
+    }
+
+    //Moves to the first voxel after the box of voxels [box_min, box_max], stepping each axis as many times as step would.
+    //The box is expected to contain the current voxel and only empty voxels.
+    pub fn leap(&mut self, box_min: IVec3, box_max: IVec3) {
+        let crossings = IVec3::select(self.step.cmpgt(IVec3::ZERO), box_max - self.position + 1, self.position - box_min + 1).max(IVec3::ONE);
+        let exits = self.side_dist + (crossings - 1).as_vec3() * self.delta_dist;
+        let exit_dist = exits.min_element();
+        let exit_axes = exits.cmple(Vec3::splat(exit_dist));
+
+        let mut steps = (((Vec3::splat(exit_dist) - self.side_dist) / self.delta_dist).floor().as_ivec3() + 1).clamp(IVec3::ZERO, crossings);
+        steps = IVec3::select(exit_axes, crossings, steps);
+        steps = IVec3::select(self.step.cmpeq(IVec3::ZERO), IVec3::ZERO, steps);
+
+        self.mask = IVec3::select(exit_axes, IVec3::ONE, IVec3::ZERO);
+        self.distance = exit_dist;
+        self.side_dist += steps.as_vec3() * self.delta_dist;
+        self.position += steps * self.step;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct March {
+    //Traversal stopped in the solid voxel hit, None when the ray left the chunk
+    pub hit: Option<VoxelTraversal>,
+    //Voxels visited, the work done by the ray marcher
+    pub steps: u32,
+}
+
+//Marches from a point on or in the chunk like chunk_shader.wgsl does, leaping with the distance field when there is one
+pub fn march(dimensions: UVec3, is_solid: impl Fn(UVec3) -> bool, distance_field: Option<&DistanceField>, origin: Vec3, direction: Vec3) -> March {
+    let mut traversal = VoxelTraversal::new(origin, direction);
+    let mut steps = 0;
+
+    while traversal.position.cmpge(IVec3::ZERO).all() && traversal.position.cmplt(dimensions.as_ivec3()).all() {
+        steps += 1;
+        let position = traversal.position.as_uvec3();
+        if is_solid(position) {
+            return March { hit: Some(traversal), steps };
+        }
+
+        match distance_field.and_then(|distance_field| distance_field.empty_box(position)) {
+            Some((box_min, box_max)) => traversal.leap(box_min, box_max),
+            None => traversal.step(),
+        }
+    }
+
+    March { hit: None, steps }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoxelHit {
+    pub voxel: UVec3,
+    //Outward normal of the face the ray entered the voxel through, zero when the ray starts inside the voxel.
+    //Several axes are set when the ray enters exactly through an edge or a corner, voxel + normal is always the voxel the ray came from.
+    pub normal: IVec3,
+    //Distance along the ray from the origin to the face of the voxel
+    pub distance: f32,
+}
+
+//Traversal of a ray entering the chunk starts this far past the entry point, in voxels, so that its first voxel is inside the chunk
+pub const ENTRY_OFFSET: f32 = 1e-3;
+
+//Casts a ray from anywhere, in voxel units, and returns the first solid voxel less than max_distance away
+pub fn raycast(dimensions: UVec3, is_solid: impl Fn(UVec3) -> bool, distance_field: Option<&DistanceField>, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<VoxelHit> {
+    let entry = ray_box_entry(origin, direction, Vec3::ZERO, dimensions.as_vec3())?;
+    if entry > max_distance {
+        return None;
+    }
+
+    let start = if entry > 0.0 { entry + ENTRY_OFFSET } else { 0.0 };
+    let traversal = march(dimensions, is_solid, distance_field, origin + direction * start, direction).hit?;
+
+    let hit = match traversal.mask == IVec3::ZERO {
+        //The first voxel is hit on the face of the chunk the ray entered through
+        true => VoxelHit {
+            voxel: traversal.position.as_uvec3(),
+            normal: if entry > 0.0 { entry_normal(origin, direction, dimensions) } else { IVec3::ZERO },
+            distance: entry,
+        },
+        false => VoxelHit {
+            voxel: traversal.position.as_uvec3(),
+            normal: -traversal.mask * traversal.step,
+            distance: start + traversal.distance,
+        },
+    };
+
+    if hit.distance <= max_distance {
+        Some(hit)
+    } else {
+        None
+    }
+}
+
+//Distance along a ray to the point where it enters a box, 0 when the origin is inside it
+pub fn ray_box_entry(origin: Vec3, direction: Vec3, box_min: Vec3, box_max: Vec3) -> Option<f32> {
+    let inverse = direction.recip();
+    let t0 = (box_min - origin) * inverse;
+    let t1 = (box_max - origin) * inverse;
+
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element();
+
+    if near <= far {
+        Some(near)
+    } else {
+        None
+    }
+}
+
+//Same as the WGSL sign, 0 for 0
+fn sign(value: f32) -> i32 {
+    if value > 0.0 {
+        1
+    } else if value < 0.0 {
+        -1
+    } else {
+        0
+    }
+}
+
+//Outward normal of the face of the chunk through which a ray from outside enters it, the axis of the last slab entered
+fn entry_normal(origin: Vec3, direction: Vec3, dimensions: UVec3) -> IVec3 {
+    let inverse = direction.recip();
+    let near = ((Vec3::ZERO - origin) * inverse).min((dimensions.as_vec3() - origin) * inverse);
+
+    let axis = match (near.x >= near.y && near.x >= near.z, near.y >= near.z) {
+        (true, _) => 0,
+        (false, true) => 1,
+        (false, false) => 2,
+    };
+
+    let mut normal = IVec3::ZERO;
+    normal[axis] = -sign(direction[axis]);
+    normal
+}
+
+fn side(position: i32, origin: f32, step: i32, delta_dist: f32) -> f32 {
+    if step == 0 {
+        return f32::INFINITY;
+    }
+
+    ((position as f32 - origin) * step as f32 + (step + 1) as f32 / 2.0) * delta_dist
+}
@@ -0,0 +1,446 @@
+use glam::{Vec3, Vec4};
+use wgpu::{util::{BufferInitDescriptor, DeviceExt}, BindGroup, BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, Device, Queue};
+
+use self::light_grid::LightGrid;
+
+pub mod light_grid;
+
+//Directional lights past this are ignored, keep in sync with lighting_shader.wgsl
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 8;
+//Each shadowed light takes a channel of the shadow texture of the G-buffer, the lights past this are not shadowed
+pub const MAX_SHADOWED_LIGHTS: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Shadows {
+    #[default]
+    None,
+    //A single ray towards the light
+    Hard,
+    //Rays spread over the cone of a light of this angular radius, in radians, the sun is about 0.0047.
+    //The fraction of rays that reach the light gives the penumbra, see ShadowQuality.
+    Soft { angular_radius: f32 },
+}
+
+//Number of rays traced per pixel for soft shadows, hard shadows always trace one
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShadowQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl ShadowQuality {
+    pub fn soft_shadow_samples(self) -> u32 {
+        match self {
+            Self::Low => 4,
+            Self::Medium => 8,
+            Self::High => 16,
+        }
+    }
+}
+
+//Darkens the ambient light where the surroundings of a surface hide it
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct AmbientOcclusion {
+    //Each corner of the voxel faces is darkened by the solid voxels around it, interpolated across the face.
+    //Computed by the ray marcher from the albedo of the chunk the face belongs to.
+    pub voxel: bool,
+    //Hemisphere sampling of the G-buffer around each pixel, which also catches the contacts between chunks
+    pub screen_space: Option<ScreenSpaceAmbientOcclusion>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenSpaceAmbientOcclusion {
+    //Radius of the hemisphere sampled around each pixel, in world units
+    pub radius: f32,
+    //Fraction of the ambient light removed from fully occluded pixels
+    pub intensity: f32,
+}
+
+impl Default for ScreenSpaceAmbientOcclusion {
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            intensity: 1.0,
+        }
+    }
+}
+
+//Glow spreading around the pixels brighter than the threshold, like the emissive voxels. The bright parts of the lit image are
+//blurred through a chain of downsampled copies then added back to it, see BloomRenderer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bloom {
+    //Brightness from which pixels glow, the lit albedo only goes past 1 where the lights add up
+    pub threshold: f32,
+    //Width of the smooth transition below the threshold, as a fraction of it
+    pub knee: f32,
+    //Fraction of the blurred light added to the image
+    pub intensity: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.1,
+            intensity: 0.5,
+        }
+    }
+}
+
+//Light coming from infinitely far away, like the sun
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalLight {
+    //Direction the light travels in, from the light towards the scene
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    //Cast by every chunk but the volumes, see RenderMode::Volume. Accumulating chunks let light through by their alpha.
+    pub shadows: Shadows,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            direction,
+            color,
+            intensity,
+            shadows: Shadows::None,
+        }
+    }
+
+    pub fn with_shadows(mut self, shadows: Shadows) -> Self {
+        self.shadows = shadows;
+        self
+    }
+}
+
+//Light shining in every direction from a point, like a torch
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    //Falls off with the square of the distance, this is the intensity one world unit away
+    pub intensity: f32,
+    //Distance at which the light has faded out, the lighting pass only evaluates the light within it
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            position,
+            color,
+            intensity,
+            range,
+        }
+    }
+}
+
+//Light shining in a cone from a point, like a lamp
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    //Direction the cone points to
+    pub direction: Vec3,
+    pub color: Vec3,
+    //Same falloff as PointLight
+    pub intensity: f32,
+    pub range: f32,
+    //Angles between the direction and the edges of the cone, in radians. The light fades out from the inner edge to the outer one.
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl SpotLight {
+    //The light starts fading out at three quarters of the angle of the cone
+    pub fn new(position: Vec3, direction: Vec3, color: Vec3, intensity: f32, range: f32, angle: f32) -> Self {
+        Self {
+            position,
+            direction,
+            color,
+            intensity,
+            range,
+            inner_angle: angle * 0.75,
+            outer_angle: angle,
+        }
+    }
+
+    pub fn with_inner_angle(mut self, inner_angle: f32) -> Self {
+        self.inner_angle = inner_angle;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightingData {
+    //Added to every lit pixel whatever its normal
+    pub ambient_color: Vec3,
+    pub ambient_intensity: f32,
+    pub directional_lights: Vec<DirectionalLight>,
+    //Culled into tiles of the screen every frame, see LightGrid
+    pub point_lights: Vec<PointLight>,
+    pub spot_lights: Vec<SpotLight>,
+    pub shadow_quality: ShadowQuality,
+    pub ambient_occlusion: AmbientOcclusion,
+    pub bloom: Option<Bloom>,
+}
+
+impl Default for LightingData {
+    //A full white ambient light and no directional light, which renders the albedo unchanged
+    fn default() -> Self {
+        Self {
+            ambient_color: Vec3::ONE,
+            ambient_intensity: 1.0,
+            directional_lights: Vec::new(),
+            point_lights: Vec::new(),
+            spot_lights: Vec::new(),
+            shadow_quality: ShadowQuality::default(),
+            ambient_occlusion: AmbientOcclusion::default(),
+            bloom: None,
+        }
+    }
+}
+
+impl LightingData {
+    //Whether any light needs the shadow pass
+    pub fn has_shadows(&self) -> bool {
+        self.directional_lights.iter().take(MAX_DIRECTIONAL_LIGHTS).any(|light| light.shadows != Shadows::None)
+    }
+}
+
+pub struct Lighting {
+    pub data: LightingData,
+    pub buffer: Buffer,
+    //Rewritten every frame with the local lights and the grid they were culled into, see write_light_grid
+    local_light_buffer: Buffer,
+    light_grid_buffer: Buffer,
+}
+
+impl Lighting {
+    pub fn new(device: &Device, data: LightingData) -> Self {
+        let buffer = device.create_buffer_init(&BufferInitDescriptor{
+            label: Some("Lighting buffer"),
+            contents: unsafe { crate::memory::any_as_u8_slice(&LightingUniform::from_data(&data)) },
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            data,
+            buffer,
+            //Storage bindings cannot be empty
+            local_light_buffer: Self::create_storage_buffer(device, "Local light buffer", std::mem::size_of::<LocalLightUniform>() as u64),
+            light_grid_buffer: Self::create_storage_buffer(device, "Light grid buffer", std::mem::size_of::<u32>() as u64),
+        }
+    }
+
+    pub fn update_uniform_buffer(&mut self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, unsafe { crate::memory::any_as_u8_slice(&LightingUniform::from_data(&self.data)) })
+    }
+
+    pub fn generate_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry{
+                    binding: 0, //Lighting uniform
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                Self::storage_layout_entry(1), //Local lights
+                Self::storage_layout_entry(2), //Light grid
+            ],
+            label: Some("lighting_bind_group_layout"),
+        })
+    }
+
+    fn storage_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+
+    fn create_storage_buffer(device: &Device, label: &str, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    //Replaces the buffer by a larger one when the contents do not fit, buffers are doubled so that growing lights do not
+    //reallocate every frame
+    fn write_storage_buffer(device: &Device, queue: &Queue, buffer: &mut Buffer, label: &str, contents: &[u8]) {
+        if contents.is_empty() {
+            return;
+        }
+
+        if contents.len() as u64 > buffer.size() {
+            *buffer = Self::create_storage_buffer(device, label, (contents.len() as u64).next_power_of_two());
+        }
+        queue.write_buffer(buffer, 0, contents);
+    }
+
+    //Uploads the local lights along with the grid they were culled into for the frame
+    pub fn write_light_grid(&mut self, device: &Device, queue: &Queue, light_grid: &LightGrid) {
+        Self::write_storage_buffer(device, queue, &mut self.local_light_buffer, "Local light buffer", bytemuck::cast_slice(light_grid.lights()));
+        Self::write_storage_buffer(device, queue, &mut self.light_grid_buffer, "Light grid buffer", bytemuck::cast_slice(&light_grid.contents()));
+    }
+
+    //Bound to the local lights of the last call to write_light_grid
+    pub fn generate_bind_group(&self, device: &Device, layout: &BindGroupLayout) -> BindGroup {
+        device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(self.buffer.as_entire_buffer_binding())
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.local_light_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.light_grid_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("lighting_bind_group"),
+            }
+        )
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DirectionalLightUniform {
+    //Normalized, w is unused
+    pub direction: Vec4,
+    //Colour multiplied by the intensity, w is unused
+    pub radiance: Vec4,
+    //Channel of the shadow texture, -1 without shadows
+    pub shadow_channel: i32,
+    //Tangent of the angular radius, 0 for hard shadows
+    pub shadow_cone: f32,
+}
+
+//Point lights are spot lights whose cone covers every direction, their cosines are both -1
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LocalLightUniform {
+    pub position: Vec3,
+    pub range: f32,
+    //Colour multiplied by the intensity
+    pub radiance: Vec3,
+    //Cosine of the inner angle
+    pub cos_inner: f32,
+    //Normalized, zero for point lights
+    pub direction: Vec3,
+    //Cosine of the outer angle
+    pub cos_outer: f32,
+}
+
+//Only f32 fields without padding, 48 bytes like the struct of the lighting shader. Implemented by hand as the derive leaves
+//unused items behind.
+unsafe impl bytemuck::Zeroable for LocalLightUniform {}
+unsafe impl bytemuck::Pod for LocalLightUniform {}
+
+impl From<&PointLight> for LocalLightUniform {
+    fn from(light: &PointLight) -> Self {
+        Self {
+            position: light.position,
+            range: light.range,
+            radiance: light.color * light.intensity,
+            cos_inner: -1.0,
+            direction: Vec3::ZERO,
+            cos_outer: -1.0,
+        }
+    }
+}
+
+impl From<&SpotLight> for LocalLightUniform {
+    fn from(light: &SpotLight) -> Self {
+        Self {
+            position: light.position,
+            range: light.range,
+            radiance: light.color * light.intensity,
+            cos_inner: light.inner_angle.cos(),
+            direction: light.direction.normalize_or_zero(),
+            //Kept below the inner cosine so the fade is never empty
+            cos_outer: light.outer_angle.cos().min(light.inner_angle.cos() - 1e-4),
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone)]
+pub struct LightingUniform {
+    //Colour multiplied by the intensity, w is unused
+    pub ambient: Vec4,
+    pub directional_light_count: u32,
+    //Rays traced per pixel for soft shadows
+    pub soft_shadow_samples: u32,
+    //AMBIENT_OCCLUSION_* bits
+    pub ambient_occlusion: u32,
+    pub directional_lights: [DirectionalLightUniform; MAX_DIRECTIONAL_LIGHTS],
+    pub screen_space_radius: f32,
+    pub screen_space_intensity: f32,
+}
+
+pub const AMBIENT_OCCLUSION_VOXEL: u32 = 1;
+pub const AMBIENT_OCCLUSION_SCREEN_SPACE: u32 = 2;
+
+impl LightingUniform {
+    fn from_data(data: &LightingData) -> Self {
+        let mut directional_lights = [DirectionalLightUniform::default(); MAX_DIRECTIONAL_LIGHTS];
+        let screen_space = data.ambient_occlusion.screen_space.unwrap_or_default();
+        let mut shadow_channels = 0..MAX_SHADOWED_LIGHTS as i32;
+        for (uniform, light) in directional_lights.iter_mut().zip(data.directional_lights.iter()) {
+            let shadow_cone = match light.shadows {
+                Shadows::Soft { angular_radius } => angular_radius.tan(),
+                _ => 0.0,
+            };
+            let shadow_channel = match light.shadows {
+                Shadows::None => None,
+                _ => shadow_channels.next(),
+            };
+
+            *uniform = DirectionalLightUniform {
+                direction: light.direction.normalize_or_zero().extend(0.0),
+                radiance: (light.color * light.intensity).extend(0.0),
+                shadow_channel: shadow_channel.unwrap_or(-1),
+                shadow_cone,
+            };
+        }
+
+        LightingUniform {
+            ambient: (data.ambient_color * data.ambient_intensity).extend(0.0),
+            directional_light_count: data.directional_lights.len().min(MAX_DIRECTIONAL_LIGHTS) as u32,
+            soft_shadow_samples: data.shadow_quality.soft_shadow_samples(),
+            ambient_occlusion: match data.ambient_occlusion.voxel {
+                true => AMBIENT_OCCLUSION_VOXEL,
+                false => 0,
+            } | match data.ambient_occlusion.screen_space {
+                Some(_) => AMBIENT_OCCLUSION_SCREEN_SPACE,
+                None => 0,
+            },
+            directional_lights,
+            screen_space_radius: screen_space.radius,
+            screen_space_intensity: screen_space.intensity,
+        }
+    }
+}
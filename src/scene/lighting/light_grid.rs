@@ -0,0 +1,118 @@
+use glam::{Mat4, UVec2, Vec2, Vec3, Vec4Swizzles};
+
+use super::{LightingData, LocalLightUniform};
+
+//Size of the square tiles of the screen the local lights are culled into, in pixels, keep in sync with lighting_shader.wgsl
+pub const LIGHT_TILE_SIZE: u32 = 16;
+
+//The local lights of a frame culled into tiles of the screen, each tile lists the lights whose range reaches its pixels.
+//The lighting pass then only evaluates the lights of the tile of each pixel.
+pub struct LightGrid {
+    tiles: UVec2,
+    //Lights on screen, point lights then spot lights
+    lights: Vec<LocalLightUniform>,
+    //Indices into lights of the lights of each tile, tile by tile row by row
+    tile_lights: Vec<Vec<u32>>,
+}
+
+impl LightGrid {
+    //Culls the bounding sphere of each light against the tiles, view_projection is the transform of the camera
+    pub fn new(data: &LightingData, view_projection: Mat4, screen_size: UVec2) -> Self {
+        let tiles = (screen_size + LIGHT_TILE_SIZE - 1) / LIGHT_TILE_SIZE;
+        let mut tile_lights = vec![Vec::new(); (tiles.x * tiles.y) as usize];
+        let mut lights = Vec::new();
+
+        let local_lights = data.point_lights.iter().map(LocalLightUniform::from)
+            .chain(data.spot_lights.iter().map(LocalLightUniform::from));
+        for light in local_lights {
+            let Some((min, max)) = Self::covered_tiles(light.position, light.range, view_projection, screen_size, tiles) else {
+                continue;
+            };
+
+            let index = lights.len() as u32;
+            lights.push(light);
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    tile_lights[(y * tiles.x + x) as usize].push(index);
+                }
+            }
+        }
+
+        Self {
+            tiles,
+            lights,
+            tile_lights,
+        }
+    }
+
+    //Range of tiles covered by the screen rectangle of the box around the sphere, None when it is off screen
+    fn covered_tiles(center: Vec3, radius: f32, view_projection: Mat4, screen_size: UVec2, tiles: UVec2) -> Option<(UVec2, UVec2)> {
+        let corners = (0..8).map(|i| {
+            let corner = center + Vec3::new(
+                if i & 1 == 0 { -radius } else { radius },
+                if i & 2 == 0 { -radius } else { radius },
+                if i & 4 == 0 { -radius } else { radius },
+            );
+            view_projection * corner.extend(1.0)
+        });
+
+        let mut min = Vec2::MAX;
+        let mut max = Vec2::MIN;
+        let mut behind = 0;
+        for clip in corners {
+            //Corners behind the camera do not project, the box is then only culled when it is behind the camera as a whole
+            if clip.w <= 0.0 {
+                behind += 1;
+                continue;
+            }
+            let ndc = clip.xy() / clip.w;
+            min = min.min(ndc);
+            max = max.max(ndc);
+        }
+
+        match behind {
+            8 => return None,
+            0 => {
+                if max.x < -1.0 || max.y < -1.0 || min.x > 1.0 || min.y > 1.0 {
+                    return None;
+                }
+            },
+            _ => return Some((UVec2::ZERO, tiles - 1)),
+        }
+
+        //Clip space has y up and the screen y down
+        let to_tile = |ndc: Vec2| {
+            let pixel = Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) * screen_size.as_vec2();
+            (pixel / LIGHT_TILE_SIZE as f32).floor().max(Vec2::ZERO).as_uvec2().min(tiles - 1)
+        };
+        let (top_left, bottom_right) = (to_tile(Vec2::new(min.x, max.y)), to_tile(Vec2::new(max.x, min.y)));
+        Some((top_left, bottom_right))
+    }
+
+    pub fn tiles(&self) -> UVec2 {
+        self.tiles
+    }
+
+    pub fn lights(&self) -> &[LocalLightUniform] {
+        &self.lights
+    }
+
+    pub fn tile_lights(&self, tile: UVec2) -> &[u32] {
+        &self.tile_lights[(tile.y * self.tiles.x + tile.x) as usize]
+    }
+
+    //Layout of the light grid storage buffer: the offset in the buffer of the light indices of each tile then their count,
+    //followed by the light indices themselves
+    pub fn contents(&self) -> Vec<u32> {
+        let header_size = self.tile_lights.len() as u32 * 2;
+        let mut contents = Vec::with_capacity(header_size as usize + self.tile_lights.iter().map(Vec::len).sum::<usize>());
+        let mut offset = header_size;
+        for lights in self.tile_lights.iter() {
+            contents.push(offset);
+            contents.push(lights.len() as u32);
+            offset += lights.len() as u32;
+        }
+        contents.extend(self.tile_lights.iter().flatten());
+        contents
+    }
+}
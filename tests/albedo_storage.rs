@@ -0,0 +1,26 @@
+use egde::scene::chunk::chunk_content::index_albedo;
+
+#[test]
+fn palette_keeps_first_appearance_order() {
+    let albedo = [
+        10, 20, 30, 255,
+        0, 0, 0, 0,
+        10, 20, 30, 255,
+        1, 2, 3, 4,
+    ];
+
+    let (palette, indices) = index_albedo(&albedo).unwrap();
+    assert_eq!(palette, vec![[10, 20, 30, 255], [0, 0, 0, 0], [1, 2, 3, 4]]);
+    assert_eq!(indices, vec![0, 1, 0, 2]);
+}
+
+#[test]
+fn palette_is_limited_to_r16_indices() {
+    let colors = |count: u32| -> Vec<u8> { (0..count).flat_map(|i| [i as u8, (i >> 8) as u8, (i >> 16) as u8, 255]).collect() };
+
+    let (palette, indices) = index_albedo(&colors(65536)).unwrap();
+    assert_eq!(palette.len(), 65536);
+    assert_eq!(indices[65535], 65535);
+
+    assert!(index_albedo(&colors(65537)).is_none());
+}
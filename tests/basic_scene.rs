@@ -11,6 +11,7 @@ fn basic_scene_test() {
     
     let camera_data = CameraData {
         position: Vec3::new(0., 0., 0.),
+        rotation: Quat::IDENTITY,
         near: 0.01,
         far: 100.0,
         fov: 100.0 * consts::PI / 180.0,
@@ -18,13 +19,13 @@ fn basic_scene_test() {
 
     let mut scene = UnloadedScene::new(camera_data);
 
-    scene.add_chunk(UnloadedChunk{
-        content_path: PathBuf::from("C:/Users/igolt/Desktop/T-Rex.zip"),
-        chunk_data: ChunkData {
+    scene.add_chunk(UnloadedChunk::from_file(
+        PathBuf::from("C:/Users/igolt/Desktop/T-Rex.zip"),
+        ChunkData {
             position: Vec3::new(0., 0., 0.),
             rotation: Quat::from_euler(EulerRot::XYZ, 0., 0., 0.),
         },
-    });
+    ));
 
     scene. add_script(Box::new(simple_camera_controller::CameraController{}));
 
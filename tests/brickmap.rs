@@ -0,0 +1,100 @@
+mod procedural_volumes;
+
+use egde::scene::chunk::{brickmap::{Brickmap, BRICK_SIZE, EMPTY_BRICK}, chunk_content::ChunkContentLoadingError};
+use glam::UVec3;
+
+#[test]
+fn dense_round_trip() {
+    //Not a multiple of the brick size on any axis
+    let dimensions = UVec3::new(13, 9, 20);
+    let albedo = procedural_volumes::coordinate_gradient(dimensions);
+
+    let brickmap = Brickmap::from_albedo(&albedo, dimensions).unwrap();
+    assert_eq!(brickmap.grid_dimensions, UVec3::new(2, 2, 3));
+    assert_eq!(brickmap.brick_count(), 12);
+    assert_eq!(brickmap.to_albedo(), albedo);
+    assert_eq!(brickmap.to_raw_content().manifest.dimensions, dimensions);
+}
+
+#[test]
+fn empty_bricks_are_not_stored() {
+    let dimensions = UVec3::splat(32);
+    let mut albedo = vec![0u8; 32 * 32 * 32 * 4];
+    let filled = UVec3::new(17, 3, 30);
+    let index = ((filled.x + filled.y * 32 + filled.z * 32 * 32) * 4) as usize;
+    albedo[index..index + 4].copy_from_slice(&[1, 2, 3, 4]);
+
+    let brickmap = Brickmap::from_albedo(&albedo, dimensions).unwrap();
+    assert_eq!(brickmap.brick_count(), 1);
+    assert_eq!(brickmap.grid.iter().filter(|brick| **brick != EMPTY_BRICK).count(), 1);
+    //Brick (2, 0, 3) of a 4x4x4 grid
+    assert_eq!(brickmap.grid[2 + 3 * 16], 1);
+    assert_eq!(brickmap.voxel(filled), Some([1, 2, 3, 4]));
+    assert_eq!(brickmap.voxel(UVec3::ZERO), Some([0; 4]));
+    assert_eq!(brickmap.voxel(UVec3::splat(32)), None);
+    assert_eq!(brickmap.to_albedo(), albedo);
+}
+
+#[test]
+fn atlas_holds_every_brick() {
+    let dimensions = UVec3::new(8 * 300, 8, 8);
+    let albedo = procedural_volumes::checkerboard(dimensions, [9, 8, 7, 6]);
+
+    let brickmap = Brickmap::from_albedo(&albedo, dimensions).unwrap();
+    assert_eq!(brickmap.brick_count(), 300);
+    assert_eq!(brickmap.atlas_dimensions(), UVec3::new(256, 2, 1));
+
+    //Brick 257 starts the second row of the atlas
+    let atlas = brickmap.atlas_data();
+    let atlas_size = brickmap.atlas_dimensions() * BRICK_SIZE;
+    let texel = |position: UVec3| {
+        let index = ((position.x + position.y * atlas_size.x + position.z * atlas_size.x * atlas_size.y) * 4) as usize;
+        [atlas[index], atlas[index + 1], atlas[index + 2], atlas[index + 3]]
+    };
+    assert_eq!(texel(UVec3::new(8, 8, 0)), brickmap.voxel(UVec3::new(257 * 8, 0, 0)).unwrap());
+    assert_eq!(texel(UVec3::new(9, 8, 0)), brickmap.voxel(UVec3::new(257 * 8 + 1, 0, 0)).unwrap());
+    assert_eq!(brickmap.grid_data().len(), 300 * 4);
+}
+
+#[test]
+fn invalid_dimensions_are_rejected() {
+    assert!(matches!(Brickmap::from_albedo(&[0; 16], UVec3::new(2, 2, 2)), Err(ChunkContentLoadingError::InvalidDimensions)));
+    assert!(matches!(Brickmap::from_albedo(&[], UVec3::ZERO), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
+
+#[test]
+fn editing_allocates_and_frees_bricks() {
+    let mut brickmap = Brickmap::new(UVec3::new(20, 8, 8)).unwrap();
+    assert_eq!(brickmap.brick_count(), 0);
+
+    assert!(brickmap.set_voxel(UVec3::new(17, 1, 2), [1, 2, 3, 4]));
+    assert!(brickmap.set_voxel(UVec3::ZERO, [0; 4]));
+    assert!(!brickmap.set_voxel(UVec3::new(20, 0, 0), [1, 2, 3, 4]));
+    assert_eq!(brickmap.brick_count(), 1);
+    assert_eq!(brickmap.cell(UVec3::new(2, 0, 0)), 1);
+    assert_eq!(brickmap.voxel(UVec3::new(17, 1, 2)), Some([1, 2, 3, 4]));
+
+    assert!(brickmap.set_voxel(UVec3::new(17, 1, 2), [0; 4]));
+    brickmap.free_empty_bricks(UVec3::ZERO, UVec3::splat(u32::MAX));
+    assert_eq!(brickmap.brick_count(), 0);
+    assert_eq!(brickmap.cell(UVec3::new(2, 0, 0)), EMPTY_BRICK);
+
+    //The freed slot is reused
+    assert!(brickmap.set_voxel(UVec3::new(3, 3, 3), [5, 6, 7, 8]));
+    assert_eq!(brickmap.slot_count(), 1);
+    assert_eq!(brickmap.cell(UVec3::ZERO), 1);
+}
+
+//1024^3 RGBA voxels are 2^32 bytes, one more than u32 can count
+#[test]
+fn large_dimensions_do_not_overflow() {
+    let dimensions = UVec3::splat(1024);
+    let mut brickmap = Brickmap::new(dimensions).unwrap();
+    assert_eq!(brickmap.grid.len(), 128 * 128 * 128);
+    assert!(brickmap.set_voxel(UVec3::splat(1023), [1, 2, 3, 4]));
+    assert_eq!(brickmap.voxel(UVec3::splat(1023)), Some([1, 2, 3, 4]));
+    assert_eq!(brickmap.brick_count(), 1);
+
+    assert!(matches!(Brickmap::from_albedo(&[], dimensions), Err(ChunkContentLoadingError::InvalidDimensions)));
+    assert!(matches!(Brickmap::new(UVec3::splat(u32::MAX)), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
@@ -0,0 +1,164 @@
+mod procedural_volumes;
+
+use std::{io::Write, path::PathBuf};
+
+use egde::scene::chunk::{chunk_content::{ChunkContent, ChunkContentLoadingError, ChunkContentSavingError, RawChunkContent, VoxelComponent}, chunk_manifest::{ChunkManifest, FORMAT_VERSION, MANIFEST_ENTRY}};
+use glam::{UVec3, Vec3};
+use image::{ImageFormat, RgbaImage};
+
+fn temporary_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+//Writes an archive entry by entry, to build files the writer would never produce
+fn write_archive(path: &PathBuf, entries: &[(&str, Vec<u8>)]) {
+    let mut archive = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, bytes) in entries {
+        archive.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+        archive.write_all(bytes).unwrap();
+    }
+    archive.finish().unwrap();
+}
+
+fn png_slice(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+    let image = RgbaImage::from_fn(width, height, |_, _| image::Rgba(color));
+    let mut bytes = std::io::Cursor::new(Vec::<u8>::new());
+    image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+#[test]
+fn chunk_file_round_trip() {
+    let dimensions = UVec3::new(7, 5, 3);
+    //Alpha varies too so the round trip covers every channel
+    let albedo = procedural_volumes::generate(dimensions, |position| {
+        [(position.x * 37) as u8, (position.y * 51) as u8, (position.z * 85) as u8, (position.x * 13 + position.y * 29 + position.z * 41) as u8]
+    });
+
+    let mut raw = RawChunkContent::new(albedo.clone(), dimensions);
+    raw.manifest.voxel_size = 0.25;
+    raw.manifest.pivot = Vec3::new(3.5, 0.0, 1.5);
+    raw.manifest.alpha_threshold = 96;
+    raw.manifest.properties.insert("author".to_string(), "procedural generator".to_string());
+
+    let path = temporary_path("round_trip.zip");
+    ChunkContent::write_chunk_file(&raw, &path).unwrap();
+
+    let loaded = ChunkContent::read_chunk_file(&path).unwrap();
+    assert_eq!(loaded.manifest, raw.manifest);
+    assert_eq!(loaded.albedo, albedo);
+    assert!(loaded.layers.is_empty());
+}
+
+#[test]
+fn chunk_file_round_trip_with_layers() {
+    let dimensions = UVec3::new(4, 3, 2);
+    let albedo = procedural_volumes::solid_box(dimensions, [200, 100, 50, 255]);
+    let normal = procedural_volumes::generate(dimensions, |position| [128, 255, (position.z * 255) as u8, 0]);
+    let material: Vec<u8> = (0..dimensions.x * dimensions.y * dimensions.z).map(|i| i as u8).collect();
+    let roughness: Vec<u8> = (0..dimensions.x * dimensions.y * dimensions.z).map(|i| 255 - i as u8 * 7).collect();
+
+    let raw = RawChunkContent::new(albedo.clone(), dimensions)
+        .with_layer(VoxelComponent::Normal, normal.clone())
+        .with_layer(VoxelComponent::Material, material.clone())
+        .with_layer(VoxelComponent::Roughness, roughness.clone());
+    assert_eq!(raw.manifest.components, vec!["albedo", "normal", "material", "roughness"]);
+
+    let path = temporary_path("round_trip_layers.zip");
+    ChunkContent::write_chunk_file(&raw, &path).unwrap();
+
+    let loaded = ChunkContent::read_chunk_file(&path).unwrap();
+    assert_eq!(loaded.manifest, raw.manifest);
+    assert_eq!(loaded.albedo, albedo);
+    assert_eq!(loaded.layers.len(), 3);
+    assert_eq!(loaded.layers[&VoxelComponent::Normal], normal);
+    assert_eq!(loaded.layers[&VoxelComponent::Material], material);
+    assert_eq!(loaded.layers[&VoxelComponent::Roughness], roughness);
+}
+
+#[test]
+fn chunk_file_rejects_missing_layer_data() {
+    let dimensions = UVec3::new(2, 2, 2);
+    let mut raw = RawChunkContent::new(procedural_volumes::solid_box(dimensions, [255, 255, 255, 255]), dimensions);
+    raw.manifest.components.push("emission".to_string());
+
+    let result = ChunkContent::write_chunk_file(&raw, &temporary_path("missing_layer.zip"));
+    assert!(matches!(result, Err(ChunkContentSavingError::InvalidManifest(ChunkContentLoadingError::InvalidManifest(_)))));
+}
+
+#[test]
+fn chunk_file_rejects_mismatched_albedo() {
+    let dimensions = UVec3::new(4, 4, 4);
+    let albedo = procedural_volumes::solid_box(UVec3::new(4, 4, 3), [255, 255, 255, 255]);
+
+    let result = ChunkContent::write_chunk_file(&RawChunkContent::new(albedo, dimensions), &temporary_path("mismatched.zip"));
+    assert!(matches!(result, Err(ChunkContentSavingError::InvalidDimensions)));
+}
+
+#[test]
+fn legacy_chunk_file_ignores_stray_entries() {
+    let path = temporary_path("legacy.zip");
+    write_archive(&path, &[
+        ("0", png_slice(3, 2, [255, 0, 0, 255])),
+        ("1", png_slice(3, 2, [0, 255, 0, 255])),
+        ("readme.txt", b"not a slice".to_vec()),
+        ("3", png_slice(3, 2, [0, 0, 255, 255])),
+    ]);
+
+    let loaded = ChunkContent::read_chunk_file(&path).unwrap();
+    assert_eq!(loaded.manifest, ChunkManifest::new(UVec3::new(3, 2, 2)));
+    assert_eq!(loaded.albedo.len(), 3 * 2 * 2 * 4);
+    assert_eq!(&loaded.albedo[..4], &[255, 0, 0, 255]);
+    assert_eq!(&loaded.albedo[loaded.albedo.len() - 4..], &[0, 255, 0, 255]);
+}
+
+#[test]
+fn manifest_dimensions_must_match_slices() {
+    let manifest = ChunkManifest::new(UVec3::new(4, 2, 1));
+
+    let path = temporary_path("mismatched_manifest.zip");
+    write_archive(&path, &[
+        (MANIFEST_ENTRY, manifest.serialize().into_bytes()),
+        ("0", png_slice(3, 2, [255, 255, 255, 255])),
+    ]);
+
+    assert!(matches!(ChunkContent::read_chunk_file(&path), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
+
+//Nothing is allocated for the slices a crafted manifest claims before they are read
+#[test]
+fn manifest_dimensions_larger_than_the_archive_are_rejected() {
+    let manifest = ChunkManifest::new(UVec3::new(4096, 4096, 4096));
+
+    let path = temporary_path("oversized_manifest.zip");
+    write_archive(&path, &[
+        (MANIFEST_ENTRY, manifest.serialize().into_bytes()),
+        ("0", png_slice(1, 1, [255, 255, 255, 255])),
+    ]);
+
+    assert!(matches!(ChunkContent::read_chunk_file(&path), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
+
+#[test]
+fn manifest_from_a_newer_format_is_rejected() {
+    let path = temporary_path("newer_format.zip");
+    write_archive(&path, &[
+        (MANIFEST_ENTRY, format!("format_version = {}\ndimensions = 1 1 1\n", FORMAT_VERSION + 1).into_bytes()),
+        ("0", png_slice(1, 1, [255, 255, 255, 255])),
+    ]);
+
+    assert!(matches!(ChunkContent::read_chunk_file(&path), Err(ChunkContentLoadingError::UnsupportedFormatVersion(version)) if version == FORMAT_VERSION + 1));
+}
+
+#[test]
+fn manifest_parsing_errors() {
+    assert!(matches!(ChunkManifest::parse("dimensions = 1 1 1"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1 1\ncolour = red"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1 1\ncomponents = normal"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1 1\ncomponents = albedo metalness"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1 1\nvoxel_size = -1"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 1 1 1\nalpha_threshold = 256"), Err(ChunkContentLoadingError::InvalidManifest(_))));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 0 1 1"), Err(ChunkContentLoadingError::InvalidDimensions)));
+    assert!(matches!(ChunkManifest::parse("format_version = 1\ndimensions = 4294967295 4294967295 4294967295"), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
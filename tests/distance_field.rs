@@ -0,0 +1,158 @@
+mod procedural_volumes;
+
+use egde::scene::chunk::{chunk_content::voxel_is_solid, distance_field::{DistanceField, MAX_DISTANCE}, traversal::{march, ray_box_entry}};
+use glam::{IVec3, UVec3, Vec3};
+
+fn is_solid(albedo: &[u8], dimensions: UVec3, position: UVec3) -> bool {
+    let index = ((position.x + position.y * dimensions.x + position.z * dimensions.x * dimensions.y) * 4) as usize;
+    voxel_is_solid(&albedo[index..index + 4], 0)
+}
+
+//A few boxes and lone voxels scattered in an otherwise empty volume
+fn scattered(dimensions: UVec3) -> Vec<u8> {
+    procedural_volumes::generate(dimensions, |position| {
+        let hash = position.x.wrapping_mul(73_856_093) ^ position.y.wrapping_mul(19_349_663) ^ position.z.wrapping_mul(83_492_791);
+        let in_box = position.cmpge(UVec3::new(14, 3, 5)).all() && position.cmplt(UVec3::new(19, 9, 8)).all();
+        if in_box || hash % 211 == 0 {
+            [255, 255, 255, 255]
+        } else {
+            [0, 0, 0, 0]
+        }
+    })
+}
+
+#[test]
+fn distances_match_brute_force() {
+    let dimensions = UVec3::new(21, 12, 17);
+    let albedo = scattered(dimensions);
+    let distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+
+    let solid: Vec<IVec3> = (0..dimensions.z)
+        .flat_map(|z| (0..dimensions.y).flat_map(move |y| (0..dimensions.x).map(move |x| UVec3::new(x, y, z))))
+        .filter(|position| is_solid(&albedo, dimensions, *position))
+        .map(|position| position.as_ivec3())
+        .collect();
+    assert!(!solid.is_empty());
+
+    for z in 0..dimensions.z {
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                let position = UVec3::new(x, y, z);
+                let expected = solid.iter().map(|solid| (*solid - position.as_ivec3()).abs().max_element()).min().unwrap();
+                assert_eq!(distance_field.distance(position), Some(expected.min(MAX_DISTANCE as i32) as u8), "at {}", position);
+            }
+        }
+    }
+}
+
+#[test]
+fn empty_volume_is_at_max_distance() {
+    let dimensions = UVec3::splat(4);
+    let distance_field = DistanceField::from_albedo(&vec![0; 4 * 4 * 4 * 4], dimensions, 0);
+    assert!(distance_field.distances.iter().all(|distance| *distance == MAX_DISTANCE));
+    assert_eq!(distance_field.distance(UVec3::splat(4)), None);
+}
+
+#[test]
+fn leaping_hits_the_same_voxels_in_fewer_steps() {
+    let dimensions = UVec3::new(48, 32, 40);
+    let albedo = scattered(dimensions);
+    let distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+    let size = dimensions.as_vec3();
+
+    let mut dense_steps = 0;
+    let mut leaping_steps = 0;
+    let mut hits = 0;
+
+    for i in 0..400 {
+        let angle = i as f32 * 0.618;
+        let eye = size * 0.5 + Vec3::new(angle.cos() * 60.0, (i % 7) as f32 * 8.0 - 24.0, angle.sin() * 60.0);
+        let target = Vec3::new((i * 7 % 48) as f32, (i * 3 % 32) as f32, (i * 11 % 40) as f32) + 0.5;
+        let direction = (target - eye).normalize();
+
+        let entry = ray_box_entry(eye, direction, Vec3::ZERO, size).unwrap();
+        let origin = eye + direction * (entry + 1e-3);
+
+        let dense = march(dimensions, |position| is_solid(&albedo, dimensions, position), None, origin, direction);
+        let leaping = march(dimensions, |position| is_solid(&albedo, dimensions, position), Some(&distance_field), origin, direction);
+
+        assert_eq!(dense.hit.map(|hit| hit.position), leaping.hit.map(|hit| hit.position), "ray {}", i);
+        if let (Some(dense_hit), Some(leaping_hit)) = (dense.hit, leaping.hit) {
+            assert_eq!(dense_hit.mask, leaping_hit.mask, "ray {}", i);
+            assert!((dense_hit.distance - leaping_hit.distance).abs() < 1e-3, "ray {}", i);
+            hits += 1;
+        }
+
+        dense_steps += dense.steps;
+        leaping_steps += leaping.steps;
+    }
+
+    assert!(hits > 100);
+    assert!(leaping_steps * 2 < dense_steps, "{} steps with the distance field, {} without", leaping_steps, dense_steps);
+}
+
+#[test]
+fn lowering_around_new_solid_voxels_stays_conservative() {
+    let dimensions = UVec3::new(21, 12, 17);
+    let mut albedo = scattered(dimensions);
+    let mut distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+
+    //A box becomes solid, one of its voxels stays empty
+    let (min, max) = (UVec3::new(3, 2, 9), UVec3::new(6, 4, 11));
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let index = ((x + y * dimensions.x + z * dimensions.x * dimensions.y) * 4) as usize;
+                let color = if (x, y, z) == (4, 3, 10) { [0, 0, 0, 0] } else { [255, 255, 255, 255] };
+                albedo[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    }
+
+    let (changed_min, changed_max) = distance_field.lower_around(min, max).unwrap();
+    assert!(changed_min.cmple(min).all() && changed_max.cmpge(max).all());
+
+    let exact = DistanceField::from_albedo(&albedo, dimensions, 0);
+    for (lowered, exact) in distance_field.distances.iter().zip(exact.distances.iter()) {
+        assert!(lowered <= exact);
+    }
+
+    //Lowering again around the same box changes nothing
+    assert_eq!(distance_field.lower_around(min, max), None);
+}
+
+#[test]
+fn lowering_around_a_solid_box_matches_the_exact_field() {
+    let dimensions = UVec3::new(21, 12, 17);
+    let mut albedo = scattered(dimensions);
+    let mut distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+
+    let (min, max) = (UVec3::new(3, 2, 9), UVec3::new(6, 4, 11));
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let index = ((x + y * dimensions.x + z * dimensions.x * dimensions.y) * 4) as usize;
+                albedo[index..index + 4].copy_from_slice(&[255, 255, 255, 255]);
+            }
+        }
+    }
+
+    distance_field.lower_around(min, max).unwrap();
+    assert_eq!(distance_field, DistanceField::from_albedo(&albedo, dimensions, 0));
+}
+
+//Only the voxels further from solid voxels than from the edit change, their box is uploaded and nothing past it is walked
+#[test]
+fn lowering_stops_where_the_distances_are_already_small() {
+    let dimensions = UVec3::splat(256);
+    let mut distance_field = DistanceField {
+        dimensions,
+        distances: vec![2; 256 * 256 * 256],
+    };
+
+    let voxel = UVec3::splat(128);
+    assert_eq!(distance_field.lower_around(voxel, voxel + 1), Some((voxel - 1, voxel + 2)));
+    assert_eq!(distance_field.distance(voxel), Some(0));
+    assert_eq!(distance_field.distance(voxel + 1), Some(1));
+    assert_eq!(distance_field.distance(voxel + 2), Some(2));
+}
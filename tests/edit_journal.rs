@@ -0,0 +1,140 @@
+use egde::scene::chunk::edit_journal::{EditJournal, RegionDelta, VoxelChange};
+use glam::UVec3;
+
+fn delta(index: u64, before: u8, after: u8) -> RegionDelta {
+    RegionDelta {
+        min: UVec3::ZERO,
+        max: UVec3::ONE,
+        changes: vec![VoxelChange { index, before: [before; 4], after: [after; 4] }],
+    }
+}
+
+fn after_values(journal: &mut EditJournal) -> Option<Vec<u8>> {
+    journal.undo().map(|transaction| transaction.deltas.iter().map(|delta| delta.changes[0].after[0]).collect())
+}
+
+#[test]
+fn edits_are_undone_one_by_one_outside_transactions() {
+    let mut journal = EditJournal::default();
+    journal.record(delta(0, 0, 1));
+    journal.record(delta(0, 1, 2));
+
+    assert_eq!(after_values(&mut journal), Some(vec![2]));
+    assert_eq!(after_values(&mut journal), Some(vec![1]));
+    assert_eq!(after_values(&mut journal), None);
+    assert!(journal.can_redo());
+}
+
+#[test]
+fn transactions_group_edits_and_nest() {
+    let mut journal = EditJournal::default();
+    journal.begin_transaction();
+    journal.record(delta(0, 0, 1));
+    journal.begin_transaction();
+    journal.record(delta(1, 0, 2));
+    assert!(journal.commit_transaction());
+    assert!(journal.in_transaction());
+    journal.record(delta(2, 0, 3));
+    assert!(journal.commit_transaction());
+    assert!(!journal.commit_transaction());
+
+    assert_eq!(after_values(&mut journal), Some(vec![1, 2, 3]));
+    assert!(!journal.can_undo());
+}
+
+#[test]
+fn undo_commits_open_transactions() {
+    let mut journal = EditJournal::default();
+    journal.begin_transaction();
+    journal.record(delta(0, 0, 1));
+    assert!(journal.can_undo());
+
+    assert_eq!(after_values(&mut journal), Some(vec![1]));
+    assert!(!journal.in_transaction());
+}
+
+#[test]
+fn new_edits_clear_redo() {
+    let mut journal = EditJournal::default();
+    journal.record(delta(0, 0, 1));
+    journal.undo();
+    assert!(journal.can_redo());
+
+    journal.record(delta(0, 0, 5));
+    assert!(!journal.can_redo());
+    assert!(journal.redo().is_none());
+    assert_eq!(after_values(&mut journal), Some(vec![5]));
+}
+
+#[test]
+fn redo_replays_undone_edits() {
+    let mut journal = EditJournal::default();
+    journal.record(delta(0, 0, 1));
+    journal.record(delta(0, 1, 2));
+    journal.undo();
+    journal.undo();
+
+    assert_eq!(journal.redo().unwrap().deltas[0].changes[0].after, [1; 4]);
+    assert_eq!(journal.redo().unwrap().deltas[0].changes[0].after, [2; 4]);
+    assert!(journal.redo().is_none());
+}
+
+#[test]
+fn oldest_edits_are_evicted_past_the_limit() {
+    let size = delta(0, 0, 1).memory_size() + std::mem::size_of::<egde::scene::chunk::edit_journal::Transaction>();
+    let mut journal = EditJournal::new(size * 3);
+
+    for i in 0..5 {
+        journal.record(delta(0, i, i + 1));
+    }
+    assert_eq!(journal.memory_size(), size * 3);
+
+    assert_eq!(after_values(&mut journal), Some(vec![5]));
+    assert_eq!(after_values(&mut journal), Some(vec![4]));
+    assert_eq!(after_values(&mut journal), Some(vec![3]));
+    assert_eq!(after_values(&mut journal), None);
+
+    //A transaction larger than the limit is not kept
+    journal.set_memory_limit(size / 2);
+    assert_eq!(journal.memory_size(), 0);
+    journal.record(delta(0, 0, 1));
+    assert!(!journal.can_undo());
+}
+
+#[test]
+fn open_transactions_count_against_the_limit() {
+    let transaction_size = std::mem::size_of::<egde::scene::chunk::edit_journal::Transaction>();
+    let delta_size = delta(0, 0, 1).memory_size();
+    let mut journal = EditJournal::new(transaction_size * 2 + delta_size * 3);
+
+    journal.record(delta(0, 0, 1));
+    journal.begin_transaction();
+    for i in 0..3 {
+        journal.record(delta(1, i, i + 1));
+    }
+    //The edit recorded before the transaction made room for it
+    assert_eq!(journal.memory_size(), transaction_size + delta_size * 3);
+
+    //Past the limit the transaction is dropped and stops growing
+    for i in 3..100 {
+        journal.record(delta(1, i, i + 1));
+        assert!(journal.memory_size() <= journal.memory_limit());
+    }
+    assert!(journal.commit_transaction());
+    assert_eq!(journal.memory_size(), 0);
+    assert!(!journal.can_undo());
+
+    //The next transaction is recorded again
+    journal.begin_transaction();
+    journal.record(delta(2, 0, 1));
+    assert!(journal.commit_transaction());
+    assert_eq!(after_values(&mut journal), Some(vec![1]));
+}
+
+#[test]
+fn empty_deltas_are_ignored() {
+    let mut journal = EditJournal::default();
+    journal.record(RegionDelta { min: UVec3::ZERO, max: UVec3::ONE, changes: Vec::new() });
+    assert!(!journal.can_undo());
+    assert_eq!(journal.memory_size(), 0);
+}
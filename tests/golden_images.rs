@@ -0,0 +1,905 @@
+mod headless_fixture;
+mod procedural_volumes;
+
+use std::{env, path::PathBuf};
+
+use egde::{headless::HeadlessGame, scene::{camera::CameraData, chunk::{brickmap::Brickmap, chunk_content::{AlbedoData, AlbedoStorage, RawChunkContent, VoxelComponent}, transfer_function::{ControlPoint, TransferFunction}, Chunk, ChunkData, RenderMode, UnloadedChunk}, lighting::{AmbientOcclusion, Bloom, DirectionalLight, LightingData, PointLight, ScreenSpaceAmbientOcclusion, ShadowQuality, Shadows, SpotLight}, UnloadedScene}};
+use glam::{Quat, UVec3, Vec3};
+use image::RgbaImage;
+
+const WIDTH: u32 = 96;
+const HEIGHT: u32 = 96;
+
+//Maximum difference allowed on any channel of any pixel, absorbs rounding differences between adapters
+const TOLERANCE: u8 = 2;
+
+//Set this variable to overwrite the reference images with the current output
+const BLESS_VARIABLE: &str = "EGDE_BLESS";
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.png", name))
+}
+
+fn camera_looking_at(position: Vec3, target: Vec3) -> CameraData {
+    let mut camera_data = CameraData {
+        position,
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.look_at(target, Vec3::Y);
+    camera_data
+}
+
+fn chunk_at(position: Vec3, rotation: Quat) -> ChunkData {
+    ChunkData {
+        position,
+        rotation,
+    }
+}
+
+fn headless_game() -> Option<HeadlessGame> {
+    headless_fixture::headless_game("Golden images", WIDTH, HEIGHT)
+}
+
+fn render(scene: UnloadedScene) -> Option<RgbaImage> {
+    let mut game = headless_game()?;
+    game.load_scene(scene).unwrap();
+    Some(game.render())
+}
+
+//Renders a first frame before editing so that the edits are uploaded into textures that are already in use
+fn render_edited(scene: UnloadedScene, edit: impl FnOnce(&mut Chunk)) -> Option<RgbaImage> {
+    let mut game = headless_game()?;
+    game.load_scene(scene).unwrap();
+    game.render();
+    edit(game.scene_mut().unwrap().chunks_mut().values_mut().next().unwrap());
+    Some(game.render())
+}
+
+fn assert_matches_golden(name: &str, scene: UnloadedScene) {
+    if let Some(actual) = render(scene) {
+        compare_with_golden(name, actual);
+    }
+}
+
+fn compare_with_golden(name: &str, actual: RgbaImage) {
+    let path = golden_path(name);
+    if env::var_os(BLESS_VARIABLE).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        actual.save(&path).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&path) {
+        Ok(image) => image.to_rgba8(),
+        Err(err) => panic!("Missing reference image {:?} ({}), run the tests with {}=1 to create it", path, err, BLESS_VARIABLE),
+    };
+
+    assert_eq!(expected.dimensions(), actual.dimensions(), "{}: reference image has different dimensions", name);
+
+    let mismatches = expected.pixels()
+        .zip(actual.pixels())
+        .filter(|(expected, actual)| expected.0.iter().zip(actual.0.iter()).any(|(e, a)| e.abs_diff(*a) > TOLERANCE))
+        .count();
+
+    if mismatches > 0 {
+        let actual_path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.actual.png", name));
+        actual.save(&actual_path).unwrap();
+        panic!("{}: {} pixels differ from {:?} by more than {}, output written to {:?}", name, mismatches, path, TOLERANCE, actual_path);
+    }
+}
+
+#[test]
+fn solid_box() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.2, 1.0, -0.8), Vec3::splat(0.4)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(dimensions, [200, 60, 40, 255]),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    assert_matches_golden("solid_box", scene);
+}
+
+#[test]
+fn sphere() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.8, 0.8, -1.6), Vec3::splat(0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(16, [80, 200, 120, 255]),
+        UVec3::splat(16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    assert_matches_golden("sphere", scene);
+}
+
+#[test]
+fn coordinate_gradient() {
+    let dimensions = UVec3::new(16, 8, 12);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(2.4, 1.6, -1.2), Vec3::new(0.8, 0.4, 0.6)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::coordinate_gradient(dimensions),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    assert_matches_golden("coordinate_gradient", scene);
+}
+
+#[test]
+fn checkerboard() {
+    let dimensions = UVec3::splat(8);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.2, 1.2, -0.8), Vec3::splat(0.4)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::checkerboard(dimensions, [255, 255, 255, 255]),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    assert_matches_golden("checkerboard", scene);
+}
+
+#[test]
+fn rotated_chunk() {
+    let dimensions = UVec3::new(12, 4, 8);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.0, 1.2, -2.0), Vec3::ZERO));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::coordinate_gradient(dimensions),
+        dimensions,
+        chunk_at(Vec3::new(-0.6, -0.2, -0.4), Quat::from_rotation_y(30.0_f32.to_radians())),
+    ));
+
+    assert_matches_golden("rotated_chunk", scene);
+}
+
+#[test]
+fn interpenetrating_chunks() {
+    let dimensions = UVec3::splat(8);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.6, 1.2, -1.2), Vec3::splat(0.6)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(dimensions, [220, 40, 40, 255]),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(8, [40, 40, 220, 255]),
+        dimensions,
+        chunk_at(Vec3::splat(0.4), Quat::IDENTITY),
+    ));
+
+    assert_matches_golden("interpenetrating_chunks", scene);
+}
+
+//Indexed albedos must render exactly like their RGBA counterparts
+#[test]
+fn indexed_checkerboard() {
+    let dimensions = UVec3::splat(8);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.2, 1.2, -0.8), Vec3::splat(0.4)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::checkerboard(dimensions, [255, 255, 255, 255]),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ).with_albedo_storage(AlbedoStorage::Indexed));
+
+    assert_matches_golden("checkerboard", scene);
+}
+
+//Every voxel has its own colour, more than an R8Uint index can address
+#[test]
+fn indexed_coordinate_gradient() {
+    let dimensions = UVec3::new(16, 8, 12);
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(2.4, 1.6, -1.2), Vec3::new(0.8, 0.4, 0.6)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::coordinate_gradient(dimensions),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ).with_albedo_storage(AlbedoStorage::Indexed));
+
+    assert_matches_golden("coordinate_gradient", scene);
+}
+
+//Two small boxes in a chunk that is mostly empty bricks
+fn sparse_boxes(storage: AlbedoStorage) -> UnloadedScene {
+    let dimensions = UVec3::new(40, 24, 40);
+    let albedo = procedural_volumes::generate(dimensions, |position| {
+        if position.cmpge(UVec3::new(2, 2, 2)).all() && position.cmplt(UVec3::new(7, 7, 7)).all() {
+            [220, 120, 40, 255]
+        } else if position.cmpge(UVec3::new(29, 13, 27)).all() && position.cmplt(UVec3::new(37, 21, 36)).all() {
+            [40, 120, 220, 255]
+        } else {
+            [0, 0, 0, 0]
+        }
+    });
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(5.0, 3.5, -2.0), Vec3::new(2.0, 1.2, 2.0)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(albedo, dimensions, chunk_at(Vec3::ZERO, Quat::IDENTITY)).with_albedo_storage(storage));
+    scene
+}
+
+#[test]
+fn sparse_boxes_dense() {
+    assert_matches_golden("sparse_boxes", sparse_boxes(AlbedoStorage::Rgba));
+}
+
+//Skipping empty bricks must not change the image
+#[test]
+fn sparse_boxes_brickmap() {
+    assert_matches_golden("sparse_boxes", sparse_boxes(AlbedoStorage::Brickmap));
+}
+
+#[test]
+fn brickmap_sphere() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.8, 0.8, -1.6), Vec3::splat(0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(16, [80, 200, 120, 255]),
+        UVec3::splat(16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ).with_albedo_storage(AlbedoStorage::Brickmap));
+
+    assert_matches_golden("sphere", scene);
+}
+
+//Built voxel by voxel and loaded without ever holding a dense albedo
+#[test]
+fn sparse_brickmap_sphere() {
+    let sphere = procedural_volumes::sphere(16, [80, 200, 120, 255]);
+    let mut brickmap = Brickmap::new(UVec3::splat(16)).unwrap();
+    for (i, color) in sphere.chunks_exact(4).enumerate() {
+        let i = i as u32;
+        brickmap.set_voxel(UVec3::new(i % 16, i / 16 % 16, i / 256), [color[0], color[1], color[2], color[3]]);
+    }
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.8, 0.8, -1.6), Vec3::splat(0.8)));
+    scene.add_chunk(UnloadedChunk::from_brickmap(brickmap, chunk_at(Vec3::ZERO, Quat::IDENTITY)));
+
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(scene).unwrap();
+    let chunk = game.scene_mut().unwrap().chunks().values().next().unwrap();
+    assert!(matches!(chunk.content().albedo, AlbedoData::Sparse(_)));
+    assert_eq!(chunk.content().albedo_storage, AlbedoStorage::Brickmap);
+
+    compare_with_golden("sphere", game.render());
+}
+
+fn empty_chunk_scene(camera_data: CameraData, dimensions: UVec3, storage: AlbedoStorage) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(camera_data);
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        vec![0; (dimensions.x * dimensions.y * dimensions.z * 4) as usize],
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ).with_albedo_storage(storage));
+    scene
+}
+
+#[test]
+fn edited_solid_box() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let scene = empty_chunk_scene(camera_looking_at(Vec3::new(1.2, 1.0, -0.8), Vec3::splat(0.4)), dimensions, AlbedoStorage::Rgba);
+
+    let Some(actual) = render_edited(scene, |chunk| {
+        //Two touching boxes, merged into a single upload
+        chunk.fill_box(UVec3::ZERO, UVec3::new(8, 4, 8), [200, 60, 40, 255]);
+        chunk.fill_box(UVec3::new(0, 4, 0), UVec3::splat(100), [200, 60, 40, 255]);
+    }) else {
+        return;
+    };
+
+    compare_with_golden("solid_box", actual);
+}
+
+fn assert_edited_sphere_matches(storage: AlbedoStorage) {
+    let scene = empty_chunk_scene(camera_looking_at(Vec3::new(0.8, 0.8, -1.6), Vec3::splat(0.8)), UVec3::splat(16), storage);
+
+    let Some(actual) = render_edited(scene, |chunk| {
+        chunk.fill_sphere(Vec3::splat(8.0), 8.0, [80, 200, 120, 255]);
+        assert_eq!(chunk.get_voxel(UVec3::splat(8)), Some([80, 200, 120, 255]));
+        assert_eq!(chunk.get_voxel(UVec3::ZERO), Some([0, 0, 0, 0]));
+    }) else {
+        return;
+    };
+
+    compare_with_golden("sphere", actual);
+}
+
+#[test]
+fn edited_sphere() {
+    assert_edited_sphere_matches(AlbedoStorage::Rgba);
+}
+
+#[test]
+fn edited_indexed_sphere() {
+    assert_edited_sphere_matches(AlbedoStorage::Indexed);
+}
+
+#[test]
+fn edited_brickmap_sphere() {
+    assert_edited_sphere_matches(AlbedoStorage::Brickmap);
+}
+
+//More colours than an R16Uint index can address, the chunk falls back to Rgba storage and must render like a chunk loaded as Rgba
+#[test]
+fn edited_indexed_chunk_past_r16_colours() {
+    let dimensions = UVec3::new(64, 64, 17);
+    let camera_data = camera_looking_at(Vec3::new(3.2, 5.0, -4.0), Vec3::new(3.2, 3.2, 0.85));
+    let colors = procedural_volumes::generate(dimensions, |position| [(position.x * 4) as u8, (position.y * 4) as u8, (position.z * 15) as u8, 255]);
+
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(empty_chunk_scene(camera_data, dimensions, AlbedoStorage::Indexed)).unwrap();
+    game.render();
+    let chunk = game.scene_mut().unwrap().chunks_mut().values_mut().next().unwrap();
+    for (i, color) in colors.chunks_exact(4).enumerate() {
+        let i = i as u32;
+        chunk.set_voxel(UVec3::new(i % dimensions.x, i / dimensions.x % dimensions.y, i / (dimensions.x * dimensions.y)), [color[0], color[1], color[2], color[3]]);
+    }
+    let edited = game.render();
+    assert_eq!(game.scene_mut().unwrap().chunks().values().next().unwrap().content().albedo_storage, AlbedoStorage::Rgba);
+
+    let mut scene = UnloadedScene::new(camera_data);
+    scene.add_chunk(UnloadedChunk::from_raw_data(colors, dimensions, chunk_at(Vec3::ZERO, Quat::IDENTITY)));
+    let Some(loaded) = render(scene) else {
+        return;
+    };
+    assert!(edited == loaded, "the edited chunk does not render like the same albedo loaded as Rgba");
+}
+
+//Edits within the bricks already in the atlas, including erased bricks, are uploaded brick by brick
+#[test]
+fn edited_brickmap_matches_loaded_brickmap() {
+    let dimensions = UVec3::splat(16);
+    let camera_data = camera_looking_at(Vec3::new(0.8, 0.8, -1.6), Vec3::splat(0.8));
+    let sphere_scene = || {
+        let mut scene = UnloadedScene::new(camera_data);
+        scene.add_chunk(UnloadedChunk::from_raw_data(
+            procedural_volumes::sphere(16, [80, 200, 120, 255]),
+            dimensions,
+            chunk_at(Vec3::ZERO, Quat::IDENTITY),
+        ).with_albedo_storage(AlbedoStorage::Brickmap));
+        scene
+    };
+    let edit = |chunk: &mut Chunk| {
+        chunk.fill_box(UVec3::new(0, 8, 0), UVec3::new(16, 16, 16), [0; 4]);
+        chunk.fill_box(UVec3::new(4, 4, 0), UVec3::new(12, 8, 8), [220, 60, 40, 255]);
+    };
+
+    let Some(edited) = render_edited(sphere_scene(), |chunk| {
+        let brick_count = chunk.content().albedo.brickmap().unwrap().brick_count();
+        edit(chunk);
+        assert!(chunk.content().albedo.brickmap().unwrap().brick_count() < brick_count);
+    }) else {
+        return;
+    };
+
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(sphere_scene()).unwrap();
+    edit(game.scene_mut().unwrap().chunks_mut().values_mut().next().unwrap());
+    let chunk = game.scene_mut().unwrap().chunks().values().next().unwrap();
+    let brickmap = Brickmap::from_albedo(&chunk.content().albedo.to_dense(), dimensions).unwrap();
+
+    let mut scene = UnloadedScene::new(camera_data);
+    scene.add_chunk(UnloadedChunk::from_brickmap(brickmap, chunk_at(Vec3::ZERO, Quat::IDENTITY)));
+    let Some(loaded) = render(scene) else {
+        return;
+    };
+    assert!(edited == loaded, "the edited brickmap does not render like the same albedo loaded as a brickmap");
+}
+
+//Every voxel gets a new colour, more than the R8Uint index of the empty chunk can address
+#[test]
+fn edited_indexed_coordinate_gradient() {
+    let dimensions = UVec3::new(16, 8, 12);
+    let scene = empty_chunk_scene(camera_looking_at(Vec3::new(2.4, 1.6, -1.2), Vec3::new(0.8, 0.4, 0.6)), dimensions, AlbedoStorage::Indexed);
+    let gradient = procedural_volumes::coordinate_gradient(dimensions);
+
+    let Some(actual) = render_edited(scene, |chunk| {
+        for (i, color) in gradient.chunks_exact(4).enumerate() {
+            let i = i as u32;
+            let position = UVec3::new(i % dimensions.x, i / dimensions.x % dimensions.y, i / (dimensions.x * dimensions.y));
+            assert!(chunk.set_voxel(position, [color[0], color[1], color[2], color[3]]));
+        }
+        assert!(!chunk.set_voxel(dimensions, [255; 4]));
+    }) else {
+        return;
+    };
+
+    compare_with_golden("coordinate_gradient", actual);
+}
+
+#[test]
+fn undone_edits() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let color = [200, 60, 40, 255];
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.2, 1.0, -0.8), Vec3::splat(0.4)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(dimensions, color),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    let Some(actual) = render_edited(scene, |chunk| {
+        chunk.fill_box(UVec3::new(0, 4, 0), UVec3::splat(8), [0; 4]);
+
+        chunk.begin_transaction();
+        chunk.fill_sphere(Vec3::new(8.0, 4.0, 0.0), 3.0, [0; 4]);
+        chunk.set_voxel(UVec3::ZERO, [20, 200, 40, 255]);
+        chunk.commit_transaction();
+
+        assert!(chunk.undo());
+        assert_eq!(chunk.get_voxel(UVec3::ZERO), Some(color));
+        assert_eq!(chunk.get_voxel(UVec3::new(7, 3, 0)), Some(color));
+        assert_eq!(chunk.get_voxel(UVec3::new(0, 4, 0)), Some([0; 4]));
+
+        assert!(chunk.redo());
+        assert_eq!(chunk.get_voxel(UVec3::ZERO), Some([20, 200, 40, 255]));
+
+        assert!(chunk.undo());
+        assert!(chunk.undo());
+        assert!(!chunk.undo());
+        assert_eq!(chunk.get_voxel(UVec3::new(0, 4, 0)), Some(color));
+    }) else {
+        return;
+    };
+
+    compare_with_golden("solid_box", actual);
+}
+
+//Solidity comes from alpha alone: black and pure green or blue voxels are drawn, transparent coloured ones are not
+fn pure_colors() -> Vec<u8> {
+    const COLORS: [[u8; 4]; 6] = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [0, 0, 0, 255],
+        [255, 255, 255, 255],
+        [255, 255, 255, 0],
+    ];
+
+    procedural_volumes::generate(UVec3::new(12, 4, 6), |position| match (position.y, position.z % 2) {
+        (0, _) | (_, 0) => COLORS[(position.x / 2) as usize],
+        _ => [0, 0, 0, 0],
+    })
+}
+
+#[test]
+fn pure_color_voxels() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.6, 1.0, -0.9), Vec3::new(0.6, 0.1, 0.3)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(pure_colors(), UVec3::new(12, 4, 6), chunk_at(Vec3::ZERO, Quat::IDENTITY)));
+
+    assert_matches_golden("pure_color_voxels", scene);
+}
+
+//Voxels at or below the alpha threshold of their chunk are rendered exactly as if they were not there
+#[test]
+fn alpha_threshold() {
+    let dimensions = UVec3::splat(8);
+    let color_at = |position: UVec3| match (position.x + position.z) % 3 {
+        0 => [200, 60, 40, 255],
+        1 => [40, 200, 60, 128],
+        _ => [0, 0, 0, 0],
+    };
+    let camera = camera_looking_at(Vec3::new(1.2, 1.0, -0.8), Vec3::splat(0.4));
+
+    let mut thresholded = UnloadedScene::new(camera);
+    thresholded.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::generate(dimensions, color_at),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ).with_alpha_threshold(128));
+
+    let mut removed = UnloadedScene::new(camera);
+    removed.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::generate(dimensions, |position| match color_at(position) {
+            [_, _, _, 128] => [0, 0, 0, 0],
+            color => color,
+        }),
+        dimensions,
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    let (Some(thresholded), Some(removed)) = (render(thresholded), render(removed)) else {
+        return;
+    };
+    assert!(thresholded == removed, "voxels below the alpha threshold changed the image");
+    compare_with_golden("alpha_threshold", thresholded);
+}
+
+//A pane of tinted glass in front of an opaque box, the box is seen through it and the glass is not drawn where the box is in front
+#[test]
+fn translucent_glass() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.6, 1.0, -2.2), Vec3::new(0.4, 0.3, 0.4)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::splat(8), [200, 60, 40, 255]),
+        UVec3::splat(8),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::generate(UVec3::new(12, 8, 2), |position| match position.y % 4 {
+            0 => [40, 120, 220, 200],
+            _ => [60, 180, 240, 80],
+        }),
+        UVec3::new(12, 8, 2),
+        chunk_at(Vec3::new(-0.2, 0.1, -0.4), Quat::IDENTITY),
+    ).with_render_mode(RenderMode::Accumulate));
+
+    assert_matches_golden("translucent_glass", scene);
+}
+
+fn translucent_boxes(reversed: bool) -> UnloadedScene {
+    let mut chunks = vec![
+        UnloadedChunk::from_raw_data(
+            procedural_volumes::solid_box(UVec3::splat(6), [220, 40, 40, 100]),
+            UVec3::splat(6),
+            chunk_at(Vec3::ZERO, Quat::IDENTITY),
+        ),
+        UnloadedChunk::from_raw_data(
+            procedural_volumes::sphere(6, [40, 220, 40, 90]),
+            UVec3::splat(6),
+            chunk_at(Vec3::new(0.2, 0.2, -0.8), Quat::from_rotation_y(0.3)),
+        ),
+    ];
+    if reversed {
+        chunks.reverse();
+    }
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.5, 0.6, -1.8), Vec3::splat(0.3)));
+    for chunk in chunks {
+        scene.add_chunk(chunk.with_render_mode(RenderMode::Accumulate));
+    }
+    scene
+}
+
+//Translucent chunks are composited from the furthest to the closest whatever order they were added in
+#[test]
+fn translucent_chunk_order() {
+    let (Some(forward), Some(reversed)) = (render(translucent_boxes(false)), render(translucent_boxes(true))) else {
+        return;
+    };
+    assert!(forward == reversed, "the order of the translucent chunks changed the image");
+    compare_with_golden("translucent_chunk_order", forward);
+}
+
+//Panes of glass along x, the even ones red and the odd ones green, held by a single chunk or split between two chunks
+//whose boxes intersect
+fn interleaved_panes(split: bool) -> UnloadedScene {
+    let dimensions = UVec3::new(7, 6, 6);
+    let pane = |color: [u8; 4]| procedural_volumes::generate(dimensions, move |position| match position.x % 4 {
+        0 => color,
+        _ => [0, 0, 0, 0],
+    });
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(-0.9, 0.55, -0.2), Vec3::new(0.4, 0.3, 0.3)));
+    let chunks = match split {
+        true => vec![
+            UnloadedChunk::from_raw_data(pane([220, 40, 40, 110]), dimensions, chunk_at(Vec3::ZERO, Quat::IDENTITY)),
+            UnloadedChunk::from_raw_data(pane([40, 220, 40, 110]), dimensions, chunk_at(Vec3::new(0.2, 0.0, 0.0), Quat::IDENTITY)),
+        ],
+        false => vec![UnloadedChunk::from_raw_data(
+            procedural_volumes::generate(UVec3::new(9, 6, 6), |position| match position.x {
+                0 | 4 => [220, 40, 40, 110],
+                2 | 6 => [40, 220, 40, 110],
+                _ => [0, 0, 0, 0],
+            }),
+            UVec3::new(9, 6, 6),
+            chunk_at(Vec3::ZERO, Quat::IDENTITY),
+        )],
+    };
+    for chunk in chunks {
+        scene.add_chunk(chunk.with_render_mode(RenderMode::Accumulate));
+    }
+    scene
+}
+
+//Overlapping translucent chunks are composited voxel by voxel, not chunk by chunk
+#[test]
+fn intersecting_translucent_chunks() {
+    let (Some(split), Some(single)) = (render(interleaved_panes(true)), render(interleaved_panes(false))) else {
+        return;
+    };
+
+    let difference = split.pixels()
+        .zip(single.pixels())
+        .flat_map(|(split, single)| split.0.iter().zip(single.0.iter()).map(|(a, b)| a.abs_diff(*b)).collect::<Vec<u8>>())
+        .max()
+        .unwrap();
+    //Each translucent layer is blended into the 8 bit albedo on its own and rounded once more
+    assert!(difference <= 2 * TOLERANCE, "splitting the panes between two chunks changed a pixel by {}", difference);
+    compare_with_golden("intersecting_translucent_chunks", split);
+}
+
+//Accumulating opaque voxels stops at the first of them, exactly like the first hit mode
+#[test]
+fn accumulated_opaque_voxels() {
+    let scene = |render_mode| {
+        let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(1.2, 1.0, -0.8), Vec3::splat(0.4)));
+        scene.add_chunk(UnloadedChunk::from_raw_data(
+            procedural_volumes::coordinate_gradient(UVec3::splat(8)),
+            UVec3::splat(8),
+            chunk_at(Vec3::ZERO, Quat::IDENTITY),
+        ).with_render_mode(render_mode));
+        scene
+    };
+
+    let (Some(first_hit), Some(accumulated)) = (render(scene(RenderMode::FirstHit)), render(scene(RenderMode::Accumulate))) else {
+        return;
+    };
+    assert!(first_hit == accumulated, "accumulating opaque voxels changed the image");
+}
+
+//Low densities are a faint blue haze, high densities an opaque orange core
+fn density_transfer_function() -> TransferFunction {
+    TransferFunction::new(vec![
+        ControlPoint::new(0.0, [0, 0, 0, 0]),
+        ControlPoint::new(0.1, [60, 120, 255, 10]),
+        ControlPoint::new(0.5, [80, 160, 255, 40]),
+        ControlPoint::new(0.8, [255, 140, 40, 255]),
+    ])
+}
+
+fn density_scene(volume_step: f32) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.9, 1.2, -1.6), Vec3::new(0.8, 0.4, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::checkerboard(UVec3::new(16, 1, 16), [200, 200, 200, 255]),
+        UVec3::new(16, 1, 16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    let dimensions = UVec3::splat(16);
+    let raw = RawChunkContent::new(vec![0; (dimensions.element_product() * 4) as usize], dimensions)
+        .with_layer(VoxelComponent::Density, procedural_volumes::density_ball(16));
+    scene.add_chunk(UnloadedChunk::from_raw_content(raw, chunk_at(Vec3::new(0.0, 0.0, 0.0), Quat::IDENTITY))
+        .with_render_mode(RenderMode::Volume)
+        .with_transfer_function(density_transfer_function())
+        .with_volume_step(volume_step));
+    scene
+}
+
+#[test]
+fn density_volume() {
+    assert_matches_golden("density_volume", density_scene(0.5));
+}
+
+//The opacity of each sample is corrected for the step size, smaller steps only refine the same image
+#[test]
+fn volume_step_size() {
+    let (Some(coarse), Some(fine)) = (render(density_scene(0.5)), render(density_scene(0.125))) else {
+        return;
+    };
+
+    let difference = coarse.pixels()
+        .zip(fine.pixels())
+        .flat_map(|(coarse, fine)| coarse.0.iter().zip(fine.0.iter()).map(|(c, f)| c.abs_diff(*f)).collect::<Vec<u8>>())
+        .max()
+        .unwrap();
+    assert!(difference <= 24, "halving the volume step twice changed a pixel by {}", difference);
+}
+
+//Transparent up to the density 200 and opaque red from the density 201
+fn step_transfer_function() -> TransferFunction {
+    TransferFunction::new(vec![
+        ControlPoint::new(200.0 / 255.0, [0, 0, 0, 0]),
+        ControlPoint::new(201.0 / 255.0, [220, 40, 40, 255]),
+    ])
+}
+
+fn uniform_density_scene(density: Option<u8>) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.9, 1.2, -1.6), Vec3::new(0.8, 0.4, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::checkerboard(UVec3::new(16, 1, 16), [200, 200, 200, 255]),
+        UVec3::new(16, 1, 16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+
+    if let Some(density) = density {
+        let dimensions = UVec3::splat(8);
+        let voxel_count = dimensions.element_product() as usize;
+        let raw = RawChunkContent::new(vec![0; voxel_count * 4], dimensions)
+            .with_layer(VoxelComponent::Density, vec![density; voxel_count]);
+        scene.add_chunk(UnloadedChunk::from_raw_content(raw, chunk_at(Vec3::new(0.4, 0.1, 0.4), Quat::IDENTITY))
+            .with_render_mode(RenderMode::Volume)
+            .with_transfer_function(step_transfer_function()));
+    }
+    scene
+}
+
+//Each density reads its own texel of the transfer function, a sharp step is not blurred into the densities beside it
+#[test]
+fn transfer_function_step() {
+    let (Some(empty), Some(below), Some(above), Some(full)) = (
+        render(uniform_density_scene(None)),
+        render(uniform_density_scene(Some(200))),
+        render(uniform_density_scene(Some(201))),
+        render(uniform_density_scene(Some(255))),
+    ) else {
+        return;
+    };
+
+    assert!(below == empty, "the density right below the step was drawn");
+    assert!(above == full, "the density right above the step was not opaque");
+    assert!(above != empty, "the density right above the step was not drawn");
+}
+
+//A rotated sphere on a floor under a warm key light and a cool fill light
+#[test]
+fn lit_sphere() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.9, 1.1, -1.0), Vec3::new(0.8, 0.3, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(16, 1, 16), [200, 200, 200, 255]),
+        UVec3::new(16, 1, 16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(8, [60, 160, 220, 255]),
+        UVec3::splat(8),
+        chunk_at(Vec3::new(0.5, 0.1, 0.5), Quat::from_rotation_y(0.5)),
+    ));
+    scene.set_lighting_data(LightingData {
+        ambient_color: Vec3::new(0.6, 0.7, 1.0),
+        ambient_intensity: 0.2,
+        directional_lights: vec![
+            DirectionalLight::new(Vec3::new(0.5, -1.0, 0.8), Vec3::new(1.0, 0.9, 0.7), 0.8),
+            DirectionalLight::new(Vec3::new(-1.0, -0.3, -0.2), Vec3::new(0.4, 0.5, 0.9), 0.3),
+        ],
+        ..Default::default()
+    });
+
+    assert_matches_golden("lit_sphere", scene);
+}
+
+//A pillar and a floating glass slab over a floor, the pillar casts a full shadow and the slab a partial one
+fn shadow_scene(shadows: Shadows) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.2, 1.4, -0.5), Vec3::new(0.8, 0.2, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(16, 1, 16), [200, 200, 200, 255]),
+        UVec3::new(16, 1, 16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(2, 8, 2), [220, 120, 60, 255]),
+        UVec3::new(2, 8, 2),
+        chunk_at(Vec3::new(0.3, 0.1, 0.5), Quat::IDENTITY),
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(4, 1, 4), [60, 200, 120, 128]),
+        UVec3::new(4, 1, 4),
+        chunk_at(Vec3::new(0.9, 0.5, 0.1), Quat::IDENTITY),
+    ).with_render_mode(RenderMode::Accumulate));
+    scene.set_lighting_data(LightingData {
+        ambient_color: Vec3::ONE,
+        ambient_intensity: 0.3,
+        directional_lights: vec![
+            DirectionalLight::new(Vec3::new(0.6, -1.0, 0.5), Vec3::ONE, 0.8).with_shadows(shadows),
+        ],
+        shadow_quality: ShadowQuality::High,
+        ..Default::default()
+    });
+    scene
+}
+
+#[test]
+fn hard_shadows() {
+    assert_matches_golden("hard_shadows", shadow_scene(Shadows::Hard));
+}
+
+#[test]
+fn soft_shadows() {
+    assert_matches_golden("soft_shadows", shadow_scene(Shadows::Soft { angular_radius: 0.08 }));
+}
+
+//Stacked platforms on a floor with a sphere resting beside them, under ambient light only so the occlusion is all there is to see
+fn ambient_occlusion_scene(ambient_occlusion: AmbientOcclusion) -> UnloadedScene {
+    let dimensions = UVec3::new(16, 6, 16);
+    let platforms = procedural_volumes::generate(dimensions, |voxel| {
+        let inside = |min: u32, max: u32| voxel.x >= min && voxel.x < max && voxel.z >= min && voxel.z < max;
+        match voxel.y == 0 || (voxel.y < 3 && inside(3, 11)) || (voxel.y < 5 && inside(5, 9)) {
+            true => [210, 210, 210, 255],
+            false => [0, 0, 0, 0],
+        }
+    });
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(-0.2, 1.3, -0.6), Vec3::new(0.8, 0.1, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(platforms, dimensions, chunk_at(Vec3::ZERO, Quat::IDENTITY)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(4, [220, 160, 90, 255]),
+        UVec3::splat(4),
+        chunk_at(Vec3::new(1.15, 0.1, 0.3), Quat::IDENTITY),
+    ));
+    scene.set_lighting_data(LightingData {
+        ambient_occlusion,
+        ..Default::default()
+    });
+    scene
+}
+
+#[test]
+fn voxel_ambient_occlusion() {
+    assert_matches_golden("voxel_ambient_occlusion", ambient_occlusion_scene(AmbientOcclusion { voxel: true, screen_space: None }));
+}
+
+#[test]
+fn screen_space_ambient_occlusion() {
+    assert_matches_golden("screen_space_ambient_occlusion", ambient_occlusion_scene(AmbientOcclusion {
+        voxel: false,
+        screen_space: Some(ScreenSpaceAmbientOcclusion::default()),
+    }));
+}
+
+//A dark floor with pillars under a grid of 144 small coloured point lights and a spot light, more lights than a tile can afford to loop over
+#[test]
+fn night_lights() {
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.8, 1.5, -0.6), Vec3::new(0.8, 0.0, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(16, 1, 16), [200, 200, 200, 255]),
+        UVec3::new(16, 1, 16),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    for (x, z) in [(0.3, 0.4), (1.1, 0.5), (0.7, 1.1)] {
+        scene.add_chunk(UnloadedChunk::from_raw_data(
+            procedural_volumes::solid_box(UVec3::new(1, 5, 1), [200, 200, 200, 255]),
+            UVec3::new(1, 5, 1),
+            chunk_at(Vec3::new(x, 0.1, z), Quat::IDENTITY),
+        ));
+    }
+
+    let colors = [Vec3::new(1.0, 0.3, 0.1), Vec3::new(0.2, 1.0, 0.3), Vec3::new(0.3, 0.4, 1.0), Vec3::new(1.0, 0.9, 0.3)];
+    let point_lights = (0..144)
+        .map(|i| PointLight::new(Vec3::new(0.1 + (i % 12) as f32 * 0.12, 0.15, 0.1 + (i / 12) as f32 * 0.12), colors[(i + i / 12) % 4], 0.003, 0.1))
+        .collect();
+    scene.set_lighting_data(LightingData {
+        ambient_color: Vec3::new(0.3, 0.4, 1.0),
+        ambient_intensity: 0.08,
+        point_lights,
+        spot_lights: vec![SpotLight::new(Vec3::new(0.8, 0.8, 0.8), Vec3::new(0.2, -1.0, 0.1), Vec3::new(1.0, 0.95, 0.8), 0.3, 1.5, 0.35)],
+        ..Default::default()
+    });
+
+    assert_matches_golden("night_lights", scene);
+}
+
+//A pool of lava in a dark floor and a neon bar behind it, lit by their emission and a faint ambient light alone
+fn emissive_scene(bloom: Option<Bloom>) -> UnloadedScene {
+    let dimensions = UVec3::new(16, 4, 16);
+    let lava = |voxel: UVec3| voxel.y == 0 && (3..8).contains(&voxel.x) && (6..12).contains(&voxel.z);
+    let neon = |voxel: UVec3| voxel.z == 12 && (1..3).contains(&voxel.y) && (9..14).contains(&voxel.x);
+    let albedo = procedural_volumes::generate(dimensions, |voxel| match voxel {
+        voxel if lava(voxel) => [120, 40, 10, 255],
+        voxel if neon(voxel) => [40, 200, 255, 255],
+        voxel if voxel.y == 0 => [90, 90, 90, 255],
+        _ => [0; 4],
+    });
+    let emission = procedural_volumes::generate(dimensions, |voxel| match voxel {
+        voxel if lava(voxel) => [255, 90, 20, 64],
+        voxel if neon(voxel) => [40, 200, 255, 48],
+        _ => [0; 4],
+    });
+
+    let mut scene = UnloadedScene::new(camera_looking_at(Vec3::new(0.8, 1.2, -0.5), Vec3::new(0.8, 0.1, 0.8)));
+    scene.add_chunk(UnloadedChunk::from_raw_content(
+        RawChunkContent::new(albedo, dimensions).with_layer(VoxelComponent::Emission, emission),
+        chunk_at(Vec3::ZERO, Quat::IDENTITY),
+    ));
+    scene.set_lighting_data(LightingData {
+        ambient_color: Vec3::new(0.3, 0.4, 1.0),
+        ambient_intensity: 0.1,
+        bloom,
+        ..Default::default()
+    });
+    scene
+}
+
+#[test]
+fn emissive_voxels() {
+    assert_matches_golden("emissive_voxels", emissive_scene(None));
+}
+
+#[test]
+fn emissive_bloom() {
+    assert_matches_golden("emissive_bloom", emissive_scene(Some(Bloom::default())));
+}
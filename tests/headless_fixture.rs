@@ -0,0 +1,29 @@
+//Shared by the test crates rendering offscreen, each of them only uses some of the helpers
+#![allow(dead_code)]
+
+use std::env;
+
+use egde::{headless::HeadlessGame, GameConfig};
+
+//Set this variable to let the tests needing an adapter pass on a machine without any, they fail there otherwise
+pub const ALLOW_NO_GPU_VARIABLE: &str = "EGDE_ALLOW_NO_GPU";
+
+//The software adapter is preferred as it is the most deterministic.
+//Returns None only when the machine has no adapter at all and ALLOW_NO_GPU_VARIABLE is set.
+pub fn headless_game(game_name: &str, width: u32, height: u32) -> Option<HeadlessGame> {
+    let config = GameConfig {
+        game_name: game_name.to_string(),
+        window_width: width,
+        window_height: height,
+        render_scale: 1,
+    };
+
+    let game = pollster::block_on(HeadlessGame::new(config.clone(), true))
+        .or_else(|| pollster::block_on(HeadlessGame::new(config, false)));
+
+    if game.is_none() {
+        assert!(env::var_os(ALLOW_NO_GPU_VARIABLE).is_some(), "No wgpu adapter available, set {} to skip the tests needing one", ALLOW_NO_GPU_VARIABLE);
+        eprintln!("No wgpu adapter available, skipping {} test", game_name);
+    }
+    game
+}
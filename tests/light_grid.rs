@@ -0,0 +1,109 @@
+use egde::scene::{camera::CameraData, lighting::{light_grid::{LightGrid, LIGHT_TILE_SIZE}, LightingData, PointLight, SpotLight}};
+use glam::{Mat4, Quat, UVec2, Vec3};
+
+const SCREEN: UVec2 = UVec2::new(160, 96);
+
+//At the origin looking along +Z
+fn view_projection() -> Mat4 {
+    let camera_data = CameraData {
+        position: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.transform(SCREEN.x as f32 / SCREEN.y as f32)
+}
+
+fn grid(point_lights: Vec<PointLight>) -> LightGrid {
+    let data = LightingData {
+        point_lights,
+        ..Default::default()
+    };
+    LightGrid::new(&data, view_projection(), SCREEN)
+}
+
+fn point_light(position: Vec3, range: f32) -> PointLight {
+    PointLight::new(position, Vec3::ONE, 1.0, range)
+}
+
+fn tiles_with_lights(grid: &LightGrid) -> Vec<UVec2> {
+    let tiles = grid.tiles();
+    (0..tiles.y)
+        .flat_map(|y| (0..tiles.x).map(move |x| UVec2::new(x, y)))
+        .filter(|tile| !grid.tile_lights(*tile).is_empty())
+        .collect()
+}
+
+#[test]
+fn tiles_cover_the_screen() {
+    let grid = grid(Vec::new());
+    assert_eq!(grid.tiles(), UVec2::new(10, 6));
+    assert_eq!(LIGHT_TILE_SIZE, 16);
+}
+
+#[test]
+fn lights_only_reach_the_tiles_around_them() {
+    let grid = grid(vec![point_light(Vec3::new(0.0, 0.0, 5.0), 0.3)]);
+    let tiles = tiles_with_lights(&grid);
+
+    assert_eq!(grid.lights().len(), 1);
+    assert!(!tiles.is_empty() && tiles.len() <= 4, "a small light in the middle of the screen covers {:?}", tiles);
+    assert!(tiles.iter().all(|tile| (4..=5).contains(&tile.x) && (2..=3).contains(&tile.y)), "{:?}", tiles);
+}
+
+#[test]
+fn lights_off_screen_are_culled() {
+    let grid = grid(vec![
+        //Behind the camera
+        point_light(Vec3::new(0.0, 0.0, -5.0), 1.0),
+        //Far to the side
+        point_light(Vec3::new(50.0, 0.0, 5.0), 1.0),
+        //Above
+        point_light(Vec3::new(0.0, 20.0, 5.0), 1.0),
+    ]);
+
+    assert!(grid.lights().is_empty());
+    assert!(tiles_with_lights(&grid).is_empty());
+}
+
+//Lights around the camera cannot be projected, they are kept in every tile
+#[test]
+fn lights_around_the_camera_reach_every_tile() {
+    let grid = grid(vec![point_light(Vec3::new(0.2, 0.0, 0.5), 2.0)]);
+    assert_eq!(tiles_with_lights(&grid).len(), 60);
+}
+
+#[test]
+fn spot_lights_follow_point_lights() {
+    let data = LightingData {
+        point_lights: vec![point_light(Vec3::new(-1.0, 0.0, 5.0), 0.3)],
+        spot_lights: vec![SpotLight::new(Vec3::new(1.0, 0.0, 5.0), Vec3::NEG_Y, Vec3::ONE, 1.0, 0.3, 0.5)],
+        ..Default::default()
+    };
+    let grid = LightGrid::new(&data, view_projection(), SCREEN);
+
+    assert_eq!(grid.lights().len(), 2);
+    assert_eq!(grid.lights()[0].cos_outer, -1.0);
+    assert_eq!(grid.lights()[1].direction, Vec3::NEG_Y);
+    assert!(grid.lights()[1].cos_outer < grid.lights()[1].cos_inner);
+}
+
+#[test]
+fn contents_list_the_offset_and_count_of_each_tile_then_the_indices() {
+    let grid = grid(vec![
+        point_light(Vec3::new(0.0, 0.0, 5.0), 0.3),
+        point_light(Vec3::new(0.2, 0.0, 0.5), 2.0),
+    ]);
+    let contents = grid.contents();
+    let tiles = grid.tiles();
+
+    for y in 0..tiles.y {
+        for x in 0..tiles.x {
+            let tile = (y * tiles.x + x) as usize;
+            let (offset, count) = (contents[tile * 2] as usize, contents[tile * 2 + 1] as usize);
+            assert_eq!(&contents[offset..offset + count], grid.tile_lights(UVec2::new(x, y)));
+        }
+    }
+    assert_eq!(contents.len(), 60 * 2 + tiles_with_lights(&grid).iter().map(|tile| grid.tile_lights(*tile).len()).sum::<usize>());
+}
@@ -0,0 +1,423 @@
+mod headless_fixture;
+mod procedural_volumes;
+
+use egde::{headless::HeadlessGame, scene::{camera::CameraData, chunk::{chunk_content::{RawChunkContent, VoxelComponent, MAX_EMISSION}, ChunkData, UnloadedChunk}, lighting::{AmbientOcclusion, Bloom, DirectionalLight, LightingData, PointLight, ScreenSpaceAmbientOcclusion, ShadowQuality, Shadows, SpotLight}, UnloadedScene}};
+use glam::{Quat, UVec3, Vec3};
+use image::{Rgba, RgbaImage};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+const ALBEDO: [u8; 4] = [200, 100, 60, 255];
+
+fn headless_game() -> Option<HeadlessGame> {
+    headless_fixture::headless_game("Lighting", WIDTH, HEIGHT)
+}
+
+fn box_camera() -> CameraData {
+    let mut camera_data = CameraData {
+        position: Vec3::new(1.0, 1.3, -0.9),
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.look_at(Vec3::new(0.4, 0.3, 0.4), Vec3::Y);
+    camera_data
+}
+
+//A box turned around Y seen from above, its top face and two of its sides are visible
+fn box_scene(rotation: Quat) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(box_camera());
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::splat(8), ALBEDO),
+        UVec3::splat(8),
+        ChunkData { position: Vec3::new(0.4, 0.0, 0.4), rotation },
+    ));
+    scene
+}
+
+//The render plane presents to an sRGB target, the albedo is lit in linear space then encoded
+fn scaled(factor: f32) -> Rgba<u8> {
+    let encode = |channel: u8| {
+        let linear = channel as f32 / 255.0 * factor;
+        let srgb = match linear <= 0.0031308 {
+            true => linear * 12.92,
+            false => 1.055 * linear.powf(1.0 / 2.4) - 0.055,
+        };
+        (srgb * 255.0).round() as u8
+    };
+    Rgba([encode(ALBEDO[0]), encode(ALBEDO[1]), encode(ALBEDO[2]), ALBEDO[3]])
+}
+
+fn count(image: &RgbaImage, color: Rgba<u8>) -> usize {
+    image.pixels().filter(|pixel| pixel.0.iter().zip(color.0.iter()).all(|(a, b)| a.abs_diff(*b) <= 1)).count()
+}
+
+fn pillar_camera() -> CameraData {
+    let mut camera_data = CameraData {
+        position: Vec3::new(0.2, 1.4, -0.5),
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.look_at(Vec3::new(0.8, 0.2, 0.8), Vec3::Y);
+    camera_data
+}
+
+//A pillar on a floor, both of the box albedo
+fn pillar_scene() -> UnloadedScene {
+    let mut scene = UnloadedScene::new(pillar_camera());
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(16, 1, 16), ALBEDO),
+        UVec3::new(16, 1, 16),
+        ChunkData { position: Vec3::ZERO, rotation: Quat::IDENTITY },
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::solid_box(UVec3::new(2, 8, 2), ALBEDO),
+        UVec3::new(2, 8, 2),
+        ChunkData { position: Vec3::new(0.3, 0.1, 0.5), rotation: Quat::IDENTITY },
+    ));
+    scene
+}
+
+//The same pillar and floor as a single chunk, voxel ambient occlusion only sees the voxels of the chunk it shades
+fn single_chunk_pillar_scene() -> UnloadedScene {
+    let dimensions = UVec3::new(16, 9, 16);
+    let mut scene = UnloadedScene::new(pillar_camera());
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::generate(dimensions, |voxel| match voxel.y == 0 || ((3..5).contains(&voxel.x) && (5..7).contains(&voxel.z)) {
+            true => ALBEDO,
+            false => [0; 4],
+        }),
+        dimensions,
+        ChunkData { position: Vec3::ZERO, rotation: Quat::IDENTITY },
+    ));
+    scene
+}
+
+fn render(rotation: Quat, lighting: LightingData) -> Option<RgbaImage> {
+    render_scene(box_scene(rotation), lighting)
+}
+
+fn render_scene(scene: UnloadedScene, lighting: LightingData) -> Option<RgbaImage> {
+    let mut game = headless_game()?;
+    game.load_scene(scene).unwrap();
+    game.set_lighting_data(lighting);
+    Some(game.render())
+}
+
+fn mean_difference(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    let total: u32 = a.pixels()
+        .zip(b.pixels())
+        .flat_map(|(a, b)| a.0.iter().zip(b.0.iter()).map(|(a, b)| a.abs_diff(*b) as u32).collect::<Vec<u32>>())
+        .sum();
+    total as f32 / (a.width() * a.height() * 4) as f32
+}
+
+//Light coming straight down only reaches the top face, the sides are perpendicular to it and only get the ambient term
+#[test]
+fn light_from_above_lights_the_top_face() {
+    let lighting = LightingData {
+        ambient_color: Vec3::ONE,
+        ambient_intensity: 0.25,
+        directional_lights: vec![DirectionalLight::new(Vec3::NEG_Y, Vec3::ONE, 0.5)],
+        ..Default::default()
+    };
+
+    for rotation in [Quat::IDENTITY, Quat::from_rotation_y(0.7)] {
+        let Some(image) = render(rotation, lighting.clone()) else {
+            return;
+        };
+        let background = *image.get_pixel(0, 0);
+
+        let top = count(&image, scaled(0.75));
+        let sides = count(&image, scaled(0.25));
+        let covered = image.pixels().filter(|pixel| **pixel != background).count();
+        assert!(top > 50 && sides > 50, "{} top pixels and {} side pixels", top, sides);
+        assert_eq!(top + sides, covered, "some pixels are neither lit like the top face nor like the sides");
+    }
+}
+
+//Each face is lit by the cosine between its normal and the light, in world space once the chunk is rotated
+#[test]
+fn faces_are_lit_by_their_world_normal() {
+    let rotation = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+    let lighting = LightingData {
+        ambient_color: Vec3::ZERO,
+        ambient_intensity: 0.0,
+        //Hits the +X face of the chunk head on once it is rotated, the other faces seen are perpendicular to it
+        directional_lights: vec![DirectionalLight::new(rotation * Vec3::NEG_X, Vec3::ONE, 1.0)],
+        ..Default::default()
+    };
+
+    let Some(image) = render(rotation, lighting) else {
+        return;
+    };
+    let background = *image.get_pixel(0, 0);
+    let lit = count(&image, scaled(1.0));
+    let unlit = count(&image, scaled(0.0));
+    assert!(lit > 50 && unlit > 50, "{} lit pixels and {} unlit pixels", lit, unlit);
+    assert_eq!(lit + unlit, image.pixels().filter(|pixel| **pixel != background).count());
+}
+
+#[test]
+fn lights_are_coloured_and_added() {
+    let lighting = LightingData {
+        ambient_color: Vec3::new(0.5, 0.0, 0.0),
+        ambient_intensity: 1.0,
+        directional_lights: vec![
+            DirectionalLight::new(Vec3::NEG_Y, Vec3::new(0.0, 1.0, 0.0), 0.5),
+            DirectionalLight::new(Vec3::NEG_Y, Vec3::new(0.0, 0.0, 1.0), 0.25),
+        ],
+        ..Default::default()
+    };
+
+    let Some(image) = render(Quat::IDENTITY, lighting) else {
+        return;
+    };
+    let top = Rgba([scaled(0.5)[0], scaled(0.5)[1], scaled(0.25)[2], 255]);
+    assert!(count(&image, top) > 50, "the top face is not lit by both lights");
+}
+
+fn shadowed_lighting(direction: Vec3, shadows: Shadows, shadow_quality: ShadowQuality) -> LightingData {
+    LightingData {
+        ambient_color: Vec3::ONE,
+        ambient_intensity: 0.25,
+        directional_lights: vec![DirectionalLight::new(direction, Vec3::ONE, 0.5).with_shadows(shadows)],
+        shadow_quality,
+        ..Default::default()
+    }
+}
+
+//Surfaces do not shadow themselves, a lone box renders the same with and without shadows
+#[test]
+fn unoccluded_surfaces_are_not_shadowed() {
+    let direction = Vec3::new(0.3, -1.0, 0.2);
+    let render_with = |shadows| render(Quat::from_rotation_y(0.7), shadowed_lighting(direction, shadows, ShadowQuality::Medium));
+
+    let Some(unshadowed) = render_with(Shadows::None) else {
+        return;
+    };
+    for shadows in [Shadows::Hard, Shadows::Soft { angular_radius: 0.05 }] {
+        let shadowed = render_with(shadows).unwrap();
+        assert_eq!(mean_difference(&unshadowed, &shadowed), 0.0, "{:?} shadows darkened an unoccluded surface", shadows);
+    }
+}
+
+//The floor behind the pillar only gets the ambient light
+#[test]
+fn occluded_surfaces_only_get_ambient_light() {
+    let direction = Vec3::new(1.0, -1.0, 0.0);
+    let render_with = |shadows| render_scene(pillar_scene(), shadowed_lighting(direction, shadows, ShadowQuality::Medium));
+
+    let Some(unshadowed) = render_with(Shadows::None) else {
+        return;
+    };
+    let shadowed = render_with(Shadows::Hard).unwrap();
+
+    //The faces of the pillar turned away from the light are ambient only either way
+    let ambient_only = count(&unshadowed, scaled(0.25));
+    let shadow = count(&shadowed, scaled(0.25)) - ambient_only;
+    let lit_floor = scaled(0.25 + 0.5 * std::f32::consts::FRAC_1_SQRT_2);
+    assert!(shadow > 50, "only {} pixels of the floor are shadowed", shadow);
+    assert_eq!(count(&unshadowed, lit_floor) - count(&shadowed, lit_floor), shadow, "the shadow is not only ambient light");
+}
+
+//More rays per pixel refine the penumbra of soft shadows without moving it
+#[test]
+fn soft_shadow_quality_refines_the_penumbra() {
+    let direction = Vec3::new(1.0, -1.0, 0.3);
+    let render_with = |shadows, quality| render_scene(pillar_scene(), shadowed_lighting(direction, shadows, quality));
+
+    let Some(hard) = render_with(Shadows::Hard, ShadowQuality::Medium) else {
+        return;
+    };
+    let soft = Shadows::Soft { angular_radius: 0.1 };
+    let low = render_with(soft, ShadowQuality::Low).unwrap();
+    let high = render_with(soft, ShadowQuality::High).unwrap();
+
+    let penumbra = mean_difference(&hard, &high);
+    let noise = mean_difference(&low, &high);
+    assert!(penumbra > 0.0, "soft shadows have no penumbra");
+    assert!(noise < penumbra, "low quality soft shadows differ from high quality ones by {} against {} for hard shadows", noise, penumbra);
+}
+
+fn occluded_lighting(ambient_occlusion: AmbientOcclusion) -> LightingData {
+    LightingData {
+        ambient_occlusion,
+        ..Default::default()
+    }
+}
+
+const VOXEL_AMBIENT_OCCLUSION: AmbientOcclusion = AmbientOcclusion { voxel: true, screen_space: None };
+
+fn screen_space_ambient_occlusion() -> AmbientOcclusion {
+    AmbientOcclusion { voxel: false, screen_space: Some(ScreenSpaceAmbientOcclusion::default()) }
+}
+
+//Nothing rises above the faces of a lone box to hide the ambient light from them
+#[test]
+fn convex_surfaces_are_not_occluded() {
+    let Some(unoccluded) = render(Quat::from_rotation_y(0.7), LightingData::default()) else {
+        return;
+    };
+
+    for ambient_occlusion in [VOXEL_AMBIENT_OCCLUSION, screen_space_ambient_occlusion()] {
+        let occluded = render(Quat::from_rotation_y(0.7), occluded_lighting(ambient_occlusion)).unwrap();
+        assert_eq!(mean_difference(&unoccluded, &occluded), 0.0, "{:?} darkened a convex surface", ambient_occlusion);
+    }
+}
+
+//The floor around the foot of the pillar is darkened, the rest of the scene is left as it is
+#[test]
+fn ambient_occlusion_darkens_inner_corners() {
+    let Some(unoccluded) = render_scene(single_chunk_pillar_scene(), LightingData::default()) else {
+        return;
+    };
+
+    for ambient_occlusion in [VOXEL_AMBIENT_OCCLUSION, screen_space_ambient_occlusion()] {
+        let occluded = render_scene(single_chunk_pillar_scene(), occluded_lighting(ambient_occlusion)).unwrap();
+        let darkened = occluded.pixels().zip(unoccluded.pixels()).filter(|(occluded, unoccluded)| occluded != unoccluded).count();
+        let brightened = occluded.pixels().zip(unoccluded.pixels()).any(|(occluded, unoccluded)| occluded.0.iter().zip(unoccluded.0.iter()).any(|(o, u)| o > u));
+        assert!(!brightened, "{:?} brightened a pixel", ambient_occlusion);
+        assert!(darkened > 20 && darkened < (WIDTH * HEIGHT / 4) as usize, "{:?} darkened {} pixels", ambient_occlusion, darkened);
+    }
+}
+
+fn locally_lit(point_lights: Vec<PointLight>, spot_lights: Vec<SpotLight>) -> LightingData {
+    LightingData {
+        ambient_color: Vec3::ONE,
+        ambient_intensity: 0.25,
+        point_lights,
+        spot_lights,
+        ..Default::default()
+    }
+}
+
+//Pixels of the first image brighter than in the second one, the others must be the same
+fn brightened(lit: &RgbaImage, unlit: &RgbaImage) -> usize {
+    assert!(lit.pixels().zip(unlit.pixels()).all(|(lit, unlit)| lit.0.iter().zip(unlit.0.iter()).all(|(l, u)| l >= u)), "a local light darkened a pixel");
+    lit.pixels().zip(unlit.pixels()).filter(|(lit, unlit)| lit != unlit).count()
+}
+
+//Above the floor, away from the pillar
+const LAMP_POSITION: Vec3 = Vec3::new(1.1, 0.4, 1.0);
+
+#[test]
+fn point_lights_fade_out_at_their_range() {
+    let Some(unlit) = render_scene(pillar_scene(), locally_lit(Vec::new(), Vec::new())) else {
+        return;
+    };
+    let lit_within = |range| brightened(&render_scene(pillar_scene(), locally_lit(vec![PointLight::new(LAMP_POSITION, Vec3::ONE, 0.2, range)], Vec::new())).unwrap(), &unlit);
+
+    //The floor is further than the range below the light
+    assert_eq!(lit_within(0.3), 0);
+    let near = lit_within(0.6);
+    let far = lit_within(1.2);
+    assert!(near > 20, "only {} pixels lit", near);
+    assert!(far > near, "{} pixels lit by the light of long range against {}", far, near);
+}
+
+#[test]
+fn spot_lights_only_light_their_cone() {
+    let Some(unlit) = render_scene(pillar_scene(), locally_lit(Vec::new(), Vec::new())) else {
+        return;
+    };
+    let lit_by = |point_lights, spot_lights| brightened(&render_scene(pillar_scene(), locally_lit(point_lights, spot_lights)).unwrap(), &unlit);
+
+    let point = lit_by(vec![PointLight::new(LAMP_POSITION, Vec3::ONE, 0.2, 1.2)], Vec::new());
+    let down = lit_by(Vec::new(), vec![SpotLight::new(LAMP_POSITION, Vec3::NEG_Y, Vec3::ONE, 0.2, 1.2, 0.7)]);
+    let up = lit_by(Vec::new(), vec![SpotLight::new(LAMP_POSITION, Vec3::Y, Vec3::ONE, 0.2, 1.2, 0.7)]);
+
+    assert!(down > 20 && down < point, "{} pixels lit by the spot light against {} by the point light", down, point);
+    assert_eq!(up, 0);
+}
+
+//The buffers of the local lights are kept from frame to frame, growing them or leaving them partly used changes nothing
+#[test]
+fn local_lights_change_between_frames() {
+    let one_lamp = || locally_lit(vec![PointLight::new(LAMP_POSITION, Vec3::ONE, 0.2, 1.2)], Vec::new());
+    let lamps = || locally_lit(
+        (0..12).map(|i| PointLight::new(Vec3::new(0.2 + i as f32 * 0.12, 0.3, 1.2), Vec3::new(1.0, 0.5, 0.25), 0.05, 0.5)).collect(),
+        vec![SpotLight::new(LAMP_POSITION, Vec3::NEG_Y, Vec3::ONE, 0.2, 1.2, 0.7)],
+    );
+
+    let (Some(expected_one_lamp), Some(expected_lamps)) = (render_scene(pillar_scene(), one_lamp()), render_scene(pillar_scene(), lamps())) else {
+        return;
+    };
+
+    let mut game = headless_game().unwrap();
+    game.load_scene(pillar_scene()).unwrap();
+    game.set_lighting_data(one_lamp());
+    assert!(game.render() == expected_one_lamp);
+    game.set_lighting_data(lamps());
+    assert!(game.render() == expected_lamps, "the lights added since the last frame were not uploaded");
+    game.set_lighting_data(one_lamp());
+    assert!(game.render() == expected_one_lamp, "the lights removed since the last frame are still drawn");
+}
+
+//The box scene with every voxel giving off the same emission
+fn emissive_box_scene(emission: [u8; 4]) -> UnloadedScene {
+    let mut scene = UnloadedScene::new(box_camera());
+    let raw = RawChunkContent::new(procedural_volumes::solid_box(UVec3::splat(8), ALBEDO), UVec3::splat(8))
+        .with_layer(VoxelComponent::Emission, procedural_volumes::solid_box(UVec3::splat(8), emission));
+    scene.add_chunk(UnloadedChunk::from_raw_content(raw, ChunkData { position: Vec3::new(0.4, 0.0, 0.4), rotation: Quat::IDENTITY }));
+    scene
+}
+
+fn unlit() -> LightingData {
+    LightingData {
+        ambient_intensity: 0.0,
+        ..Default::default()
+    }
+}
+
+//The emission is added to the lit albedo, without any light the box shows its emission alone
+#[test]
+fn emissive_voxels_shine_without_light() {
+    let Some(dark) = render_scene(box_scene(Quat::IDENTITY), unlit()) else {
+        return;
+    };
+    let glowing = render_scene(emissive_box_scene([255, 255, 255, 16]), unlit()).unwrap();
+
+    let black = count(&dark, Rgba([0, 0, 0, 255]));
+    assert!(black > 400, "only {} pixels of the unlit box are black", black);
+
+    //Grey of the radiance of the emission, encoded like the albedo
+    let radiance = 16.0 / 255.0 * MAX_EMISSION;
+    let encoded = (1.055 * radiance.powf(1.0 / 2.4) - 0.055) * 255.0;
+    let grey = Rgba([encoded.round() as u8, encoded.round() as u8, encoded.round() as u8, 255]);
+    assert_eq!(count(&glowing, grey), black);
+}
+
+#[test]
+fn bloom_spreads_light_around_bright_pixels() {
+    let bloom = |bloom| LightingData { bloom, ..unlit() };
+    let Some(sharp) = render_scene(emissive_box_scene([255, 160, 60, 255]), bloom(None)) else {
+        return;
+    };
+    let bloomed = render_scene(emissive_box_scene([255, 160, 60, 255]), bloom(Some(Bloom::default()))).unwrap();
+
+    //The box is already saturated, the glow brightens the background around it
+    let brightened = brightened(&bloomed, &sharp);
+    let glowing = bloomed.pixels().zip(sharp.pixels()).filter(|(bloomed, sharp)| bloomed.0[0] > sharp.0[0].saturating_add(8)).count();
+    assert!(glowing > 1000 && glowing <= brightened, "only {} pixels glow", glowing);
+}
+
+//Lit albedo stays below the knee of the default threshold
+#[test]
+fn bloom_leaves_images_below_the_threshold_unchanged() {
+    let lighting = |bloom| LightingData {
+        directional_lights: vec![DirectionalLight::new(Vec3::NEG_Y, Vec3::ONE, 0.3)],
+        ambient_intensity: 0.5,
+        bloom,
+        ..Default::default()
+    };
+    let Some(plain) = render(Quat::IDENTITY, lighting(None)) else {
+        return;
+    };
+    let bloomed = render(Quat::IDENTITY, lighting(Some(Bloom::default()))).unwrap();
+    assert_eq!(mean_difference(&plain, &bloomed), 0.0);
+}
@@ -0,0 +1,108 @@
+mod headless_fixture;
+mod procedural_volumes;
+
+use egde::scene::{camera::CameraData, chunk::{chunk_content::voxel_is_solid, distance_field::DistanceField, traversal::raycast, ChunkData, UnloadedChunk}, UnloadedScene};
+use glam::{IVec3, Quat, UVec3, Vec3};
+
+const PURE_COLORS: [[u8; 4]; 5] = [
+    [255, 0, 0, 255],
+    [0, 255, 0, 255],
+    [0, 0, 255, 255],
+    [0, 0, 0, 255],
+    [255, 255, 255, 255],
+];
+
+//One voxel of the colour at the center of an otherwise empty volume
+fn single_voxel(dimensions: UVec3, color: [u8; 4]) -> Vec<u8> {
+    procedural_volumes::generate(dimensions, |position| match position == dimensions / 2 {
+        true => color,
+        false => [0, 0, 0, 0],
+    })
+}
+
+#[test]
+fn alpha_decides_solidity() {
+    for color in PURE_COLORS {
+        assert!(voxel_is_solid(&color, 0), "{:?}", color);
+    }
+
+    assert!(!voxel_is_solid(&[0, 0, 0, 0], 0));
+    assert!(!voxel_is_solid(&[255, 255, 255, 0], 0));
+    assert!(voxel_is_solid(&[0, 0, 0, 1], 0));
+
+    assert!(!voxel_is_solid(&[255, 0, 0, 128], 128));
+    assert!(voxel_is_solid(&[255, 0, 0, 129], 128));
+    assert!(!voxel_is_solid(&[255, 0, 0, 255], 255));
+}
+
+#[test]
+fn rays_hit_pure_color_voxels() {
+    let dimensions = UVec3::splat(9);
+    for color in PURE_COLORS {
+        let albedo = single_voxel(dimensions, color);
+        let distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+        assert_eq!(distance_field.distance(dimensions / 2), Some(0), "{:?}", color);
+
+        let is_solid = |position: UVec3| {
+            let index = ((position.x + position.y * dimensions.x + position.z * dimensions.x * dimensions.y) * 4) as usize;
+            voxel_is_solid(&albedo[index..index + 4], 0)
+        };
+        for field in [None, Some(&distance_field)] {
+            let hit = raycast(dimensions, is_solid, field, Vec3::new(4.5, 20.0, 4.5), Vec3::NEG_Y, 100.0).unwrap();
+            assert_eq!(hit.voxel, dimensions / 2, "{:?}", color);
+            assert_eq!(hit.normal, IVec3::Y);
+        }
+    }
+}
+
+#[test]
+fn rays_go_through_voxels_below_the_threshold() {
+    let dimensions = UVec3::splat(9);
+    let albedo = single_voxel(dimensions, [0, 255, 0, 100]);
+    let albedo = albedo.as_slice();
+    let is_solid = |alpha_threshold: u8| move |position: UVec3| {
+        let index = ((position.x + position.y * dimensions.x + position.z * dimensions.x * dimensions.y) * 4) as usize;
+        voxel_is_solid(&albedo[index..index + 4], alpha_threshold)
+    };
+
+    assert!(raycast(dimensions, is_solid(99), None, Vec3::new(4.5, 20.0, 4.5), Vec3::NEG_Y, 100.0).is_some());
+    assert!(raycast(dimensions, is_solid(100), None, Vec3::new(4.5, 20.0, 4.5), Vec3::NEG_Y, 100.0).is_none());
+
+    let distance_field = DistanceField::from_albedo(albedo, dimensions, 100);
+    assert!(distance_field.distances.iter().all(|distance| *distance > 0));
+}
+
+#[test]
+fn loaded_chunks_count_and_hit_voxels_with_their_threshold() {
+    let Some(mut game) = headless_fixture::headless_game("Occupancy", 16, 16) else {
+        return;
+    };
+
+    //A black floor under voxels of decreasing alpha
+    let dimensions = UVec3::new(4, 2, 1);
+    let albedo = procedural_volumes::generate(dimensions, |position| match position.y {
+        0 => [0, 0, 0, 255],
+        _ => [0, 0, 255, [200, 100, 1, 0][position.x as usize]],
+    });
+    let camera = CameraData {
+        position: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 1.0,
+    };
+
+    let mut scene = UnloadedScene::new(camera);
+    scene.add_chunk(UnloadedChunk::from_raw_data(albedo, dimensions, ChunkData { position: Vec3::ZERO, rotation: Quat::IDENTITY }).with_alpha_threshold(50));
+    game.load_scene(scene).unwrap();
+    let scene = game.scene_mut().unwrap();
+
+    let chunk = scene.chunks().values().next().unwrap();
+    assert_eq!(chunk.content().manifest.alpha_threshold, 50);
+    assert_eq!(chunk.content().solid_voxel_count(), 6);
+
+    let top_hits: Vec<u32> = (0..4)
+        .map(|x| scene.raycast(Vec3::new(x as f32 * 0.1 + 0.05, 1.0, 0.05), Vec3::NEG_Y, 10.0).unwrap().voxel.y)
+        .collect();
+    assert_eq!(top_hits, vec![1, 1, 0, 0]);
+}
@@ -0,0 +1,127 @@
+mod headless_fixture;
+mod procedural_volumes;
+
+use egde::{headless::HeadlessGame, scene::{camera::CameraData, chunk::{ChunkData, UnloadedChunk}, UnloadedScene}};
+use glam::{Quat, UVec3, Vec3};
+
+const WIDTH: u32 = 48;
+const HEIGHT: u32 = 48;
+
+fn headless_game() -> Option<HeadlessGame> {
+    headless_fixture::headless_game("Picking", WIDTH, HEIGHT)
+}
+
+fn camera() -> CameraData {
+    let mut camera_data = CameraData {
+        position: Vec3::new(1.8, 1.5, -1.2),
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.look_at(Vec3::new(0.7, 0.3, 0.5), Vec3::Y);
+    camera_data
+}
+
+//A checkerboard slab and a sphere partly in front of it
+fn scene() -> UnloadedScene {
+    let mut scene = UnloadedScene::new(camera());
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::checkerboard(UVec3::new(12, 2, 12), [220, 220, 220, 255]),
+        UVec3::new(12, 2, 12),
+        ChunkData { position: Vec3::ZERO, rotation: Quat::IDENTITY },
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(6, [40, 200, 90, 255]),
+        UVec3::splat(6),
+        ChunkData { position: Vec3::new(0.5, 0.2, 0.2), rotation: Quat::from_rotation_y(0.4) },
+    ));
+    scene
+}
+
+#[test]
+fn picks_match_the_voxels_hit_by_camera_rays() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(scene()).unwrap();
+    let camera_data = camera();
+
+    let half_height = (camera_data.fov / 2.0).tan();
+    let half_width = half_height * WIDTH as f32 / HEIGHT as f32;
+    let (mut hits, mut mismatches) = (0, 0);
+    for y in (0..HEIGHT).step_by(3) {
+        for x in (0..WIDTH).step_by(3) {
+            game.request_pick(x, y);
+            game.render();
+            let result = game.poll_pick().expect("The pick is read back with the frame");
+            assert_eq!((result.x, result.y), (x, y));
+
+            let ndc_x = (x as f32 + 0.5) / WIDTH as f32 * 2.0 - 1.0;
+            let ndc_y = 1.0 - (y as f32 + 0.5) / HEIGHT as f32 * 2.0;
+            let direction = camera_data.rotation * Vec3::new(ndc_x * half_width, ndc_y * half_height, 1.0);
+            let hit = game.scene_mut().unwrap().raycast(camera_data.position, direction, camera_data.far);
+
+            hits += hit.is_some() as u32;
+            if result.pick.map(|pick| (pick.chunk, pick.voxel)) != hit.map(|hit| (hit.chunk, hit.voxel)) {
+                mismatches += 1;
+            }
+        }
+    }
+
+    assert!(hits > 20, "only {} pixels cover a chunk", hits);
+    //Rays grazing a voxel edge can fall on either side of it on the GPU
+    assert!(mismatches <= 2, "{} picks disagree with the raycast", mismatches);
+}
+
+#[test]
+fn picking_the_background_finds_nothing() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(scene()).unwrap();
+
+    assert!(game.poll_pick().is_none());
+
+    game.request_pick(0, 0);
+    game.render();
+    let result = game.poll_pick().unwrap();
+    assert_eq!(result.pick, None);
+    assert!(game.poll_pick().is_none());
+}
+
+#[test]
+fn picks_name_the_chunk_rendered_at_the_pixel() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(scene()).unwrap();
+    let image = game.render();
+
+    //Only the last request before a frame is read back
+    game.request_pick(0, 0);
+    let (x, y) = image.enumerate_pixels()
+        .find(|(_, _, pixel)| pixel.0[1] > 150 && pixel.0[0] < 150)
+        .map(|(x, y, _)| (x, y))
+        .expect("The sphere is visible");
+    game.request_pick(x, y);
+    game.render();
+
+    let pick = game.poll_pick().unwrap().pick.unwrap();
+    let chunk = &game.scene_mut().unwrap().chunks()[&pick.chunk];
+    assert_eq!(chunk.get_voxel(pick.voxel), Some([40, 200, 90, 255]));
+}
+
+//A resize can shrink the window after a request, it is still answered from the closest pixel
+#[test]
+fn picks_outside_of_the_window_are_answered() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(scene()).unwrap();
+
+    game.request_pick(WIDTH + 10, HEIGHT * 2);
+    game.render();
+    let result = game.poll_pick().unwrap();
+    assert_eq!((result.x, result.y), (WIDTH + 10, HEIGHT * 2));
+}
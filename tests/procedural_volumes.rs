@@ -0,0 +1,71 @@
+//Shared by several test crates, each of them only uses some of the volumes
+#![allow(dead_code)]
+
+use glam::UVec3;
+
+//Builds a RGBA8 albedo volume by evaluating `voxel` at every coordinate, x varies fastest then y then z
+pub fn generate(dimensions: UVec3, voxel: impl Fn(UVec3) -> [u8; 4]) -> Vec<u8> {
+    let mut albedo = Vec::<u8>::with_capacity((dimensions.x * dimensions.y * dimensions.z * 4) as usize);
+    for z in 0..dimensions.z {
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                albedo.extend_from_slice(&voxel(UVec3::new(x, y, z)));
+            }
+        }
+    }
+    albedo
+}
+
+pub fn solid_box(dimensions: UVec3, color: [u8; 4]) -> Vec<u8> {
+    generate(dimensions, |_| color)
+}
+
+pub fn sphere(diameter: u32, color: [u8; 4]) -> Vec<u8> {
+    let radius = diameter as f32 / 2.0;
+    generate(UVec3::splat(diameter), |position| {
+        let offset = position.as_vec3() + 0.5 - radius;
+        if offset.length() <= radius {
+            color
+        } else {
+            [0, 0, 0, 0]
+        }
+    })
+}
+
+//Every voxel gets a distinct opaque colour from its coordinates
+pub fn coordinate_gradient(dimensions: UVec3) -> Vec<u8> {
+    generate(dimensions, |position| {
+        let normalized = (position.as_vec3() + 1.0) / dimensions.as_vec3();
+        [
+            (normalized.x * 255.0) as u8,
+            (normalized.y * 255.0) as u8,
+            (normalized.z * 255.0) as u8,
+            255,
+        ]
+    })
+}
+
+//Every other voxel is filled, in a 3D checkerboard pattern
+pub fn checkerboard(dimensions: UVec3, color: [u8; 4]) -> Vec<u8> {
+    generate(dimensions, |position| {
+        if (position.x + position.y + position.z) % 2 == 0 {
+            color
+        } else {
+            [0, 0, 0, 0]
+        }
+    })
+}
+
+//Builds a single byte per voxel volume, for the density layer
+pub fn generate_scalar(dimensions: UVec3, voxel: impl Fn(UVec3) -> u8) -> Vec<u8> {
+    generate(dimensions, |position| [voxel(position), 0, 0, 0]).into_iter().step_by(4).collect()
+}
+
+//Density falling from 255 at the center to 0 at the surface of the ball
+pub fn density_ball(diameter: u32) -> Vec<u8> {
+    let radius = diameter as f32 / 2.0;
+    generate_scalar(UVec3::splat(diameter), |position| {
+        let offset = position.as_vec3() + 0.5 - radius;
+        ((1.0 - offset.length() / radius).max(0.0) * 255.0) as u8
+    })
+}
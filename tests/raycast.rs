@@ -0,0 +1,185 @@
+mod headless_fixture;
+mod procedural_volumes;
+
+use egde::{headless::HeadlessGame, scene::{camera::CameraData, chunk::{chunk_content::voxel_is_solid, distance_field::DistanceField, traversal::raycast, ChunkData, UnloadedChunk}, UnloadedScene, VOXEL_SIZE}};
+use glam::{IVec3, Quat, UVec3, Vec3};
+
+fn is_solid(albedo: &[u8], dimensions: UVec3, position: UVec3) -> bool {
+    let index = ((position.x + position.y * dimensions.x + position.z * dimensions.x * dimensions.y) * 4) as usize;
+    voxel_is_solid(&albedo[index..index + 4], 0)
+}
+
+//Solid voxels on the floor and in a small pillar, the rest is empty
+fn floor_and_pillar(dimensions: UVec3) -> Vec<u8> {
+    procedural_volumes::generate(dimensions, |position| {
+        if position.y == 0 || (position.x == 5 && position.z == 5 && position.y < 4) {
+            [180, 120, 60, 255]
+        } else {
+            [0, 0, 0, 0]
+        }
+    })
+}
+
+#[test]
+fn ray_from_outside_hits_the_face_it_enters() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let albedo = floor_and_pillar(dimensions);
+    let solid = |position| is_solid(&albedo, dimensions, position);
+
+    let hit = raycast(dimensions, solid, None, Vec3::new(2.5, 12.0, 3.5), Vec3::NEG_Y, 100.0).unwrap();
+    assert_eq!(hit.voxel, UVec3::new(2, 0, 3));
+    assert_eq!(hit.normal, IVec3::Y);
+    assert!((hit.distance - 11.0).abs() < 1e-3);
+
+    let hit = raycast(dimensions, solid, None, Vec3::new(-3.0, 2.5, 5.5), Vec3::X, 100.0).unwrap();
+    assert_eq!(hit.voxel, UVec3::new(5, 2, 5));
+    assert_eq!(hit.normal, IVec3::NEG_X);
+    assert!((hit.distance - 8.0).abs() < 1e-3);
+}
+
+#[test]
+fn ray_hitting_the_chunk_face_gets_the_face_normal() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let albedo = floor_and_pillar(dimensions);
+
+    let hit = raycast(dimensions, |position| is_solid(&albedo, dimensions, position), None, Vec3::new(4.5, 0.5, -2.0), Vec3::Z, 100.0).unwrap();
+    assert_eq!(hit.voxel, UVec3::new(4, 0, 0));
+    assert_eq!(hit.normal, IVec3::NEG_Z);
+    assert!((hit.distance - 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn ray_starting_in_a_solid_voxel_hits_it() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let albedo = floor_and_pillar(dimensions);
+
+    let hit = raycast(dimensions, |position| is_solid(&albedo, dimensions, position), None, Vec3::new(5.5, 1.5, 5.5), Vec3::X, 100.0).unwrap();
+    assert_eq!(hit.voxel, UVec3::new(5, 1, 5));
+    assert_eq!(hit.normal, IVec3::ZERO);
+    assert_eq!(hit.distance, 0.0);
+}
+
+#[test]
+fn rays_stop_at_max_distance() {
+    let dimensions = UVec3::new(8, 8, 8);
+    let albedo = floor_and_pillar(dimensions);
+    let solid = |position| is_solid(&albedo, dimensions, position);
+
+    assert!(raycast(dimensions, solid, None, Vec3::new(2.5, 12.0, 3.5), Vec3::NEG_Y, 10.9).is_none());
+    assert!(raycast(dimensions, solid, None, Vec3::new(2.5, 12.0, 3.5), Vec3::NEG_Y, 11.1).is_some());
+    assert!(raycast(dimensions, solid, None, Vec3::new(2.5, 7.5, 3.5), Vec3::Y, 100.0).is_none());
+    assert!(raycast(dimensions, solid, None, Vec3::new(-20.0, 0.5, 3.5), Vec3::NEG_X, 100.0).is_none());
+}
+
+#[test]
+fn leaping_gives_the_same_hits() {
+    let dimensions = UVec3::new(24, 16, 20);
+    let albedo = floor_and_pillar(dimensions);
+    let distance_field = DistanceField::from_albedo(&albedo, dimensions, 0);
+    let solid = |position| is_solid(&albedo, dimensions, position);
+
+    for i in 0..200 {
+        let angle = i as f32 * 0.37;
+        let origin = Vec3::new(12.0 + 20.0 * angle.cos(), 10.0 + (i % 7) as f32, 10.0 + 20.0 * angle.sin());
+        let direction = (Vec3::new(5.5 + (i % 5) as f32, 0.0, 5.5 + (i % 3) as f32) - origin).normalize();
+
+        let stepped = raycast(dimensions, solid, None, origin, direction, 100.0);
+        let leaped = raycast(dimensions, solid, Some(&distance_field), origin, direction, 100.0);
+        assert_eq!(stepped.map(|hit| (hit.voxel, hit.normal)), leaped.map(|hit| (hit.voxel, hit.normal)), "ray {}", i);
+        if let (Some(stepped), Some(leaped)) = (stepped, leaped) {
+            assert!((stepped.distance - leaped.distance).abs() < 1e-3, "ray {}", i);
+        }
+    }
+}
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+fn headless_game() -> Option<HeadlessGame> {
+    headless_fixture::headless_game("Raycast", WIDTH, HEIGHT)
+}
+
+fn camera() -> CameraData {
+    let mut camera_data = CameraData {
+        position: Vec3::new(2.2, 1.4, -1.0),
+        rotation: Quat::IDENTITY,
+        near: 0.01,
+        far: 100.0,
+        fov: 60.0_f32.to_radians(),
+    };
+    camera_data.look_at(Vec3::new(0.8, 0.2, 0.6), Vec3::Y);
+    camera_data
+}
+
+fn two_chunks(camera_data: CameraData) -> UnloadedScene {
+    let dimensions = UVec3::new(8, 8, 8);
+    let mut scene = UnloadedScene::new(camera_data);
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        floor_and_pillar(dimensions),
+        dimensions,
+        ChunkData { position: Vec3::ZERO, rotation: Quat::IDENTITY },
+    ));
+    scene.add_chunk(UnloadedChunk::from_raw_data(
+        procedural_volumes::sphere(8, [40, 200, 90, 255]),
+        UVec3::splat(8),
+        ChunkData { position: Vec3::new(1.0, 0.2, 0.4), rotation: Quat::from_rotation_y(0.6) },
+    ));
+    scene
+}
+
+#[test]
+fn scene_raycast_returns_the_closest_chunk() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+    game.load_scene(two_chunks(camera())).unwrap();
+    let scene = game.scene_mut().unwrap();
+
+    let floor = scene.raycast(Vec3::new(0.25, 2.0, 0.35), Vec3::NEG_Y, 10.0).unwrap();
+    assert_eq!(floor.voxel, UVec3::new(2, 0, 3));
+    assert_eq!(floor.normal, IVec3::Y);
+    assert!((floor.distance - (2.0 - VOXEL_SIZE)).abs() < 1e-4);
+    assert_eq!(scene.chunks()[&floor.chunk].get_voxel(floor.voxel), Some([180, 120, 60, 255]));
+
+    //The sphere chunk is in front of the floor chunk along this ray
+    let sphere_center = Vec3::new(1.0, 0.2, 0.4) + Quat::from_rotation_y(0.6) * Vec3::splat(0.4);
+    let sphere = scene.raycast(sphere_center + Vec3::new(-2.0, 0.0, 0.0), Vec3::X, 10.0).unwrap();
+    assert_ne!(sphere.chunk, floor.chunk);
+    assert!(sphere.distance > 1.6 && sphere.distance < 2.0);
+
+    assert!(scene.raycast(Vec3::new(0.25, 2.0, 0.35), Vec3::Y, 10.0).is_none());
+    assert!(scene.raycast(Vec3::new(0.25, 2.0, 0.35), Vec3::NEG_Y, 1.0).is_none());
+}
+
+//Every pixel is covered exactly when the ray through its center hits a voxel
+#[test]
+fn scene_raycast_agrees_with_the_rendered_image() {
+    let Some(mut game) = headless_game() else {
+        return;
+    };
+
+    let camera_data = camera();
+    game.load_scene(two_chunks(camera_data)).unwrap();
+
+    let image = game.render();
+    let background = *image.get_pixel(0, 0);
+    let scene = game.scene_mut().unwrap();
+
+    let half_height = (camera_data.fov / 2.0).tan();
+    let half_width = half_height * WIDTH as f32 / HEIGHT as f32;
+    let mut mismatches = 0;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let ndc_x = (x as f32 + 0.5) / WIDTH as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y as f32 + 0.5) / HEIGHT as f32 * 2.0;
+        let direction = camera_data.rotation * Vec3::new(ndc_x * half_width, ndc_y * half_height, 1.0);
+
+        let hit = scene.raycast(camera_data.position, direction, camera_data.far);
+        let covered = *pixel != background;
+        if hit.is_some() != covered {
+            mismatches += 1;
+        }
+    }
+
+    //Rays grazing a voxel edge can fall on either side of it on the GPU
+    assert!(mismatches <= (WIDTH * HEIGHT) / 1000, "{} pixels disagree", mismatches);
+}
@@ -14,8 +14,11 @@ impl Script for CameraController {
 
     fn update(&mut self, chunks: &mut std::collections::HashMap<uuid::Uuid, egde::scene::chunk::Chunk>, camera: &mut egde::scene::camera::Camera, delta_time: f32, event_pump: &sdl2::EventPump, queue: &Queue) {
         let mut move_direction = Vec3::ZERO;
+        let mut yaw: f32 = 0.;
+        let mut pitch: f32 = 0.;
         let speed: f32 = 5.;
         let dash_speed: f32 = 8.;
+        let turn_speed: f32 = 2.;
 
         let pressed_keys: Vec<Scancode> = event_pump
         .keyboard_state()
@@ -34,6 +37,10 @@ impl Script for CameraController {
                 Scancode::D => move_direction += Vec3::new(1., 0., 0.),
                 Scancode::Space => move_direction += Vec3::new(0., 1., 0.),
                 Scancode::LCtrl => move_direction += Vec3::new(0., -1., 0.),
+                Scancode::Left => yaw -= 1.,
+                Scancode::Right => yaw += 1.,
+                Scancode::Up => pitch -= 1.,
+                Scancode::Down => pitch += 1.,
                 _ => {}
             }
         }
@@ -46,7 +53,8 @@ impl Script for CameraController {
 
         move_direction *= delta_time;
 
-        camera.move_towards(move_direction);
+        camera.rotate_yaw_pitch(yaw * turn_speed * delta_time, pitch * turn_speed * delta_time);
+        camera.move_relative(move_direction);
         camera.update_uniform_buffer(queue);
     }
 }
\ No newline at end of file
@@ -0,0 +1,51 @@
+use egde::scene::chunk::transfer_function::{ControlPoint, TransferFunction, TRANSFER_FUNCTION_SIZE};
+
+#[test]
+fn interpolates_between_control_points() {
+    let transfer_function = TransferFunction::new(vec![
+        ControlPoint::new(0.25, [0, 0, 0, 0]),
+        ControlPoint::new(0.75, [200, 100, 40, 255]),
+    ]);
+
+    assert_eq!(transfer_function.evaluate(0.25), [0, 0, 0, 0]);
+    assert_eq!(transfer_function.evaluate(0.5), [100, 50, 20, 128]);
+    assert_eq!(transfer_function.evaluate(0.75), [200, 100, 40, 255]);
+}
+
+#[test]
+fn densities_outside_the_control_points_take_the_closest_one() {
+    let transfer_function = TransferFunction::new(vec![
+        ControlPoint::new(0.2, [10, 20, 30, 40]),
+        ControlPoint::new(0.6, [200, 100, 40, 255]),
+    ]);
+
+    assert_eq!(transfer_function.evaluate(0.0), [10, 20, 30, 40]);
+    assert_eq!(transfer_function.evaluate(1.0), [200, 100, 40, 255]);
+    assert_eq!(TransferFunction::new(Vec::new()).evaluate(0.5), [0, 0, 0, 0]);
+}
+
+#[test]
+fn control_points_are_sorted_by_density() {
+    let sorted = TransferFunction::new(vec![
+        ControlPoint::new(0.0, [0, 0, 0, 0]),
+        ControlPoint::new(0.5, [255, 0, 0, 128]),
+        ControlPoint::new(1.0, [255, 255, 255, 255]),
+    ]);
+    let shuffled = TransferFunction::new(vec![
+        ControlPoint::new(1.0, [255, 255, 255, 255]),
+        ControlPoint::new(0.0, [0, 0, 0, 0]),
+        ControlPoint::new(0.5, [255, 0, 0, 128]),
+    ]);
+
+    assert_eq!(sorted, shuffled);
+}
+
+#[test]
+fn texels_cover_every_density() {
+    let texels = TransferFunction::default().texels();
+
+    assert_eq!(texels.len(), (TRANSFER_FUNCTION_SIZE * 4) as usize);
+    assert_eq!(&texels[..4], &[0, 0, 0, 0]);
+    assert_eq!(&texels[texels.len() - 4..], &[255, 255, 255, 255]);
+    assert_eq!(&texels[128 * 4..129 * 4], &[128, 128, 128, 128]);
+}
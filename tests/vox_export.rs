@@ -0,0 +1,106 @@
+use egde::scene::chunk::{chunk_content::ChunkContentSavingError, magica_voxel, UnloadedChunk, UnloadedChunkContent};
+use glam::{UVec3, Vec3};
+
+fn raw_albedo(chunk: &UnloadedChunk) -> (&Vec<u8>, UVec3, Vec3) {
+    match &chunk.content {
+        UnloadedChunkContent::Raw(raw) => (&raw.albedo, raw.manifest.dimensions, raw.manifest.pivot),
+        UnloadedChunkContent::File(_) | UnloadedChunkContent::Brickmap(_) => panic!("vox models are loaded as raw content"),
+    }
+}
+
+//World position of the corner of the first voxel of an imported chunk
+fn origin(chunk: &UnloadedChunk) -> Vec3 {
+    let (_, _, pivot) = raw_albedo(chunk);
+    chunk.chunk_data.position - chunk.chunk_data.rotation * pivot * 0.1
+}
+
+fn generate(dimensions: UVec3, voxel: impl Fn(UVec3) -> [u8; 4]) -> Vec<u8> {
+    let mut albedo = Vec::with_capacity((dimensions.x * dimensions.y * dimensions.z * 4) as usize);
+    for z in 0..dimensions.z {
+        for y in 0..dimensions.y {
+            for x in 0..dimensions.x {
+                albedo.extend(voxel(UVec3::new(x, y, z)));
+            }
+        }
+    }
+    albedo
+}
+
+#[test]
+fn export_round_trips_through_import() {
+    let dimensions = UVec3::new(5, 3, 4);
+    let albedo = generate(dimensions, |position| match (position.x + position.y + position.z) % 3 {
+        0 => [0, 0, 0, 0],
+        1 => [200, 10, 10, 255],
+        _ => [10, 200, 10, 128],
+    });
+
+    let chunks = magica_voxel::parse_vox(&magica_voxel::export_vox(&albedo, dimensions, 0).unwrap()).unwrap();
+    assert_eq!(chunks.len(), 1);
+
+    let (imported, imported_dimensions, _) = raw_albedo(&chunks[0]);
+    assert_eq!(imported_dimensions, dimensions);
+    assert_eq!(imported, &albedo);
+    assert!(origin(&chunks[0]).abs_diff_eq(Vec3::ZERO, 1e-5));
+}
+
+//Voxels the renderer treats as empty under the threshold of the chunk are not exported
+#[test]
+fn alpha_threshold_decides_exported_voxels() {
+    let dimensions = UVec3::new(3, 1, 1);
+    let albedo = generate(dimensions, |position| [200, 10, 10, [255, 100, 30][position.x as usize]]);
+
+    let chunks = magica_voxel::parse_vox(&magica_voxel::export_vox(&albedo, dimensions, 50).unwrap()).unwrap();
+    let (imported, _, _) = raw_albedo(&chunks[0]);
+    assert_eq!(imported, &vec![200, 10, 10, 255, 200, 10, 10, 100, 0, 0, 0, 0]);
+}
+
+#[test]
+fn large_volumes_are_split_into_models() {
+    let dimensions = UVec3::new(300, 2, 1);
+    let albedo = generate(dimensions, |position| if position.x % 2 == 0 { [255, 255, 255, 255] } else { [0, 0, 255, 255] });
+
+    let mut chunks = magica_voxel::parse_vox(&magica_voxel::export_vox(&albedo, dimensions, 0).unwrap()).unwrap();
+    assert_eq!(chunks.len(), 2);
+    chunks.sort_by(|a, b| origin(a).x.total_cmp(&origin(b).x));
+
+    assert_eq!(raw_albedo(&chunks[0]).1, UVec3::new(256, 2, 1));
+    assert_eq!(raw_albedo(&chunks[1]).1, UVec3::new(44, 2, 1));
+    assert!(origin(&chunks[0]).abs_diff_eq(Vec3::ZERO, 1e-4));
+    assert!(origin(&chunks[1]).abs_diff_eq(Vec3::new(25.6, 0.0, 0.0), 1e-4));
+
+    //Both models start on an even voxel
+    for chunk in chunks.iter() {
+        let (imported, _, _) = raw_albedo(chunk);
+        assert_eq!(&imported[0..8], &[255, 255, 255, 255, 0, 0, 255, 255]);
+    }
+}
+
+#[test]
+fn colours_are_quantized_into_the_palette() {
+    let dimensions = UVec3::new(20, 20, 1);
+    let albedo = generate(dimensions, |position| [(position.x * 12) as u8, (position.y * 12) as u8, 100, 255]);
+
+    let chunks = magica_voxel::parse_vox(&magica_voxel::export_vox(&albedo, dimensions, 0).unwrap()).unwrap();
+    let (imported, _, _) = raw_albedo(&chunks[0]);
+
+    let mut distinct: Vec<&[u8]> = imported.chunks_exact(4).collect();
+    distinct.sort();
+    distinct.dedup();
+    assert!(distinct.len() <= magica_voxel::MAX_PALETTE_SIZE);
+
+    for (original, quantized) in albedo.chunks_exact(4).zip(imported.chunks_exact(4)) {
+        for (a, b) in original.iter().zip(quantized.iter()) {
+            assert!((*a as i32 - *b as i32).abs() <= 12, "{:?} was quantized to {:?}", original, quantized);
+        }
+    }
+}
+
+#[test]
+fn invalid_dimensions_are_rejected() {
+    assert!(matches!(magica_voxel::export_vox(&[255; 8], UVec3::new(2, 2, 1), 0), Err(ChunkContentSavingError::InvalidDimensions)));
+    assert!(matches!(magica_voxel::export_vox(&[], UVec3::ZERO, 0), Err(ChunkContentSavingError::InvalidDimensions)));
+    //4 * 2^32 bytes wrap to zero in u32
+    assert!(matches!(magica_voxel::export_vox(&[], UVec3::new(1024, 1024, 1024), 0), Err(ChunkContentSavingError::InvalidDimensions)));
+    assert!(matches!(magica_voxel::export_vox(&[], UVec3::splat(u32::MAX), 0), Err(ChunkContentSavingError::InvalidDimensions)));
+}
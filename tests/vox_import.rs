@@ -0,0 +1,175 @@
+use egde::scene::chunk::{chunk_content::ChunkContentLoadingError, magica_voxel, UnloadedChunk, UnloadedChunkContent};
+use glam::{UVec3, Vec3};
+
+fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((content.len() as i32).to_le_bytes());
+    bytes.extend((children.len() as i32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+    bytes
+}
+
+fn ints(values: &[i32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+    let mut bytes = ints(&[pairs.len() as i32]);
+    for (key, value) in pairs {
+        bytes.extend(ints(&[key.len() as i32]));
+        bytes.extend(key.as_bytes());
+        bytes.extend(ints(&[value.len() as i32]));
+        bytes.extend(value.as_bytes());
+    }
+    bytes
+}
+
+fn vox(children: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend(ints(&[150]));
+    bytes.extend(chunk(b"MAIN", &[], &children.concat()));
+    bytes
+}
+
+fn model(size: [i32; 3], voxels: &[[u8; 4]]) -> Vec<Vec<u8>> {
+    let mut xyzi = ints(&[voxels.len() as i32]);
+    xyzi.extend(voxels.concat());
+    vec![chunk(b"SIZE", &ints(&size), &[]), chunk(b"XYZI", &xyzi, &[])]
+}
+
+fn transform(node_id: i32, child: i32, frame: &[(&str, &str)]) -> Vec<u8> {
+    let mut content = ints(&[node_id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[child, -1, 0, 1]));
+    content.extend(dict(frame));
+    chunk(b"nTRN", &content, &[])
+}
+
+fn group(node_id: i32, children: &[i32]) -> Vec<u8> {
+    let mut content = ints(&[node_id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[children.len() as i32]));
+    content.extend(ints(children));
+    chunk(b"nGRP", &content, &[])
+}
+
+fn shape(node_id: i32, model: i32) -> Vec<u8> {
+    let mut content = ints(&[node_id]);
+    content.extend(dict(&[]));
+    content.extend(ints(&[1, model]));
+    content.extend(dict(&[]));
+    chunk(b"nSHP", &content, &[])
+}
+
+fn raw_albedo(chunk: &UnloadedChunk) -> (&Vec<u8>, UVec3) {
+    match &chunk.content {
+        UnloadedChunkContent::Raw(raw) => (&raw.albedo, raw.manifest.dimensions),
+        UnloadedChunkContent::File(_) | UnloadedChunkContent::Brickmap(_) => panic!("vox models are loaded as raw content"),
+    }
+}
+
+fn voxel(albedo: &[u8], dimensions: UVec3, position: UVec3) -> [u8; 4] {
+    let index = ((position.x + position.y * dimensions.x + position.z * dimensions.x * dimensions.y) * 4) as usize;
+    [albedo[index], albedo[index + 1], albedo[index + 2], albedo[index + 3]]
+}
+
+#[test]
+fn single_model_without_scene_graph() {
+    let mut palette = vec![0u8; 256 * 4];
+    palette[0..4].copy_from_slice(&[10, 20, 30, 255]);
+    palette[4..8].copy_from_slice(&[40, 50, 60, 128]);
+
+    let mut children = model([2, 3, 4], &[[0, 0, 0, 1], [1, 2, 3, 2]]);
+    children.push(chunk(b"RGBA", &palette, &[]));
+
+    let chunks = magica_voxel::parse_vox(&vox(&children)).unwrap();
+    assert_eq!(chunks.len(), 1);
+
+    //MagicaVoxel Z is egde Y
+    let (albedo, dimensions) = raw_albedo(&chunks[0]);
+    assert_eq!(dimensions, UVec3::new(2, 4, 3));
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(0, 0, 0)), [10, 20, 30, 255]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(1, 3, 2)), [40, 50, 60, 128]);
+    assert_eq!(albedo.iter().filter(|byte| **byte != 0).count(), 8);
+}
+
+#[test]
+fn default_palette_is_used_without_rgba_chunk() {
+    let chunks = magica_voxel::parse_vox(&vox(&model([3, 1, 1], &[[0, 0, 0, 1], [1, 0, 0, 216], [2, 0, 0, 255]]))).unwrap();
+
+    let (albedo, dimensions) = raw_albedo(&chunks[0]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(0, 0, 0)), [255, 255, 255, 255]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(1, 0, 0)), [0xee, 0, 0, 255]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(2, 0, 0)), [0x11, 0x11, 0x11, 255]);
+}
+
+#[test]
+fn scene_graph_places_every_shape() {
+    let mut children = model([4, 4, 4], &[[0, 0, 0, 1]]);
+    children.extend([
+        transform(0, 1, &[]),
+        group(1, &[2, 4]),
+        transform(2, 3, &[("_t", "10 0 0")]),
+        shape(3, 0),
+        //Quarter turn around the MagicaVoxel Z axis
+        transform(4, 5, &[("_t", "0 5 0"), ("_r", "17")]),
+        shape(5, 0),
+    ]);
+
+    let chunks = magica_voxel::parse_vox(&vox(&children)).unwrap();
+    assert_eq!(chunks.len(), 2);
+
+    assert!(chunks[0].chunk_data.position.abs_diff_eq(Vec3::new(10.5, 0.5, 0.5) * 0.1, 1e-5));
+    assert!((chunks[0].chunk_data.rotation * Vec3::X).abs_diff_eq(Vec3::X, 1e-5));
+
+    assert!(chunks[1].chunk_data.position.abs_diff_eq(Vec3::new(0.5, 0.5, 5.5) * 0.1, 1e-5));
+    assert!((chunks[1].chunk_data.rotation * Vec3::X).abs_diff_eq(Vec3::Z, 1e-5));
+    assert!((chunks[1].chunk_data.rotation * Vec3::Y).abs_diff_eq(Vec3::Y, 1e-5));
+
+    match &chunks[1].content {
+        UnloadedChunkContent::Raw(raw) => assert_eq!(raw.manifest.pivot, Vec3::splat(2.5)),
+        UnloadedChunkContent::File(_) | UnloadedChunkContent::Brickmap(_) => unreachable!(),
+    }
+}
+
+#[test]
+fn mirrored_model_is_flipped() {
+    let mut children = model([3, 1, 1], &[[0, 0, 0, 1]]);
+    //Mirror along the MagicaVoxel X axis
+    children.extend([transform(0, 1, &[("_r", "20")]), shape(1, 0)]);
+
+    let chunks = magica_voxel::parse_vox(&vox(&children)).unwrap();
+    let (albedo, dimensions) = raw_albedo(&chunks[0]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(0, 0, 0)), [0, 0, 0, 0]);
+    assert_eq!(voxel(albedo, dimensions, UVec3::new(2, 0, 0)), [255, 255, 255, 255]);
+    assert!(chunks[0].chunk_data.rotation.abs_diff_eq(glam::Quat::IDENTITY, 1e-5));
+
+    match &chunks[0].content {
+        UnloadedChunkContent::Raw(raw) => assert_eq!(raw.manifest.pivot.x, 1.5),
+        UnloadedChunkContent::File(_) | UnloadedChunkContent::Brickmap(_) => unreachable!(),
+    }
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    let file = vox(&model([2, 2, 2], &[[0, 0, 0, 1]]));
+    assert!(matches!(magica_voxel::parse_vox(&file[..file.len() - 2]), Err(ChunkContentLoadingError::InvalidVoxFile(_))));
+    assert!(matches!(magica_voxel::parse_vox(b"PNG not a vox file"), Err(ChunkContentLoadingError::InvalidVoxFile(_))));
+    assert!(matches!(magica_voxel::parse_vox(&vox(&model([2, 2, 2], &[[2, 0, 0, 1]]))), Err(ChunkContentLoadingError::InvalidVoxFile(_))));
+}
+
+#[test]
+fn oversized_models_are_rejected() {
+    assert!(matches!(magica_voxel::parse_vox(&vox(&model([257, 2, 2], &[]))), Err(ChunkContentLoadingError::InvalidDimensions)));
+    assert!(matches!(magica_voxel::parse_vox(&vox(&model([2, 2, i32::MAX], &[]))), Err(ChunkContentLoadingError::InvalidDimensions)));
+}
+
+//A voxel count larger than the XYZI chunk is rejected before anything is allocated for it
+#[test]
+fn voxel_count_beyond_the_chunk_is_rejected() {
+    let mut xyzi = ints(&[i32::MAX]);
+    xyzi.extend([0, 0, 0, 1]);
+    let file = vox(&[chunk(b"SIZE", &ints(&[2, 2, 2]), &[]), chunk(b"XYZI", &xyzi, &[])]);
+    assert!(matches!(magica_voxel::parse_vox(&file), Err(ChunkContentLoadingError::InvalidVoxFile(_))));
+}